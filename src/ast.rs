@@ -1,86 +1,1373 @@
-use crate::token::Token;
-use std::fmt;
-
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
-pub enum Expression {
-    Int(i64),
-    Ident(String),
-    Boolean(bool),
-    Grouped(Box<Expression>),
-    If {
-        condition: Box<Expression>,
-        consequence: Box<Expression>,
-        alternative: Option<Box<Expression>>,
-    },
-    Prefix {
-        operator: Token,
-        right: Box<Expression>,
-    },
-    Infix {
-        operator: Token,
-        left: Box<Expression>,
-        right: Box<Expression>,
-    },
-    Block(Vec<Statement>),
-    Function {
-        parameters: Vec<String>,
-        body: Box<Expression>
-    },
-}
-
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
-pub enum Statement {
-    Let {
-        identifier: String,
-        expr: Expression,
-    },
-    Return(Expression),
-    Expr(Expression),
-}
-
-impl fmt::Display for Expression {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Expression::Ident(value) => write!(f, "{}", &value),
-            Expression::Int(value) => write!(f, "{}", value),
-            Expression::Boolean(value) => write!(f, "{}", value),
-            Expression::Grouped(value) => write!(f, "{}", value),
-            Expression::If {
-                condition,
-                consequence,
-                alternative,
-            } => match alternative {
-                Some(alt) => write!(f, "If{}{{{}}}else{{{}}}", condition, consequence, alt),
-                None => write!(f, "If{}{{{}}}", condition, consequence),
-            },
-            Expression::Prefix { operator, right } => write!(f, "({}{})", operator, right),
-            Expression::Infix {
-                operator,
-                left,
-                right,
-            } => write!(f, "({}{}{})", left, operator, right),
-            Expression::Block(statements) => {
-                for stmt in statements.iter() {
-                    write!(f, "{}", stmt)?;
-                }
-                Ok(())
-            }
-            Expression::Function { parameters, body } => write!(
-                f,
-                "fn({}){{{}}}",
-                parameters.join(","),
-                body
-            ),
-        }
-    }
-}
-
-impl fmt::Display for Statement {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Statement::Expr(expr) => write!(f, "{}", expr),
-            Statement::Let { identifier, expr } => write!(f, "Let {0:}={1:}", identifier, expr),
-            Statement::Return(expr) => write!(f, "return {}", expr),
-        }
-    }
-}
+use crate::operator::Associativity;
+use crate::token::Token;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum Expression {
+    Int(i64),
+    Ident(String),
+    Boolean(bool),
+    Str(String),
+    Grouped(Box<Expression>),
+    If {
+        condition: Box<Expression>,
+        consequence: Box<Expression>,
+        alternative: Option<Box<Expression>>,
+    },
+    Prefix {
+        operator: Token,
+        right: Box<Expression>,
+    },
+    Infix {
+        operator: Token,
+        left: Box<Expression>,
+        right: Box<Expression>,
+    },
+    Postfix {
+        operator: Token,
+        left: Box<Expression>,
+    },
+    Block(Vec<Statement>),
+    /// `fn(a: int) -> bool { body }` 型の注釈は省略できる
+    Function {
+        parameters: Vec<Parameter>,
+        return_type: Option<TypeExpr>,
+        body: Box<Expression>,
+    },
+    /// `macro(a, b) { body }` 実引数の構文木を受け取り、`quote` した構文木を返す
+    MacroLiteral {
+        parameters: Vec<Parameter>,
+        body: Box<Expression>,
+    },
+    While {
+        condition: Box<Expression>,
+        body: Box<Expression>,
+    },
+    For {
+        variable: String,
+        iterable: Box<Expression>,
+        body: Box<Expression>,
+    },
+    Match {
+        subject: Box<Expression>,
+        arms: Vec<MatchArm>,
+    },
+    Call {
+        function: Box<Expression>,
+        arguments: Vec<Argument>,
+    },
+    /// `condition ? consequence : alternative`
+    Ternary {
+        condition: Box<Expression>,
+        consequence: Box<Expression>,
+        alternative: Box<Expression>,
+    },
+    /// `left |> right` 元の形を保つため呼び出しには展開しない
+    Pipe {
+        left: Box<Expression>,
+        right: Box<Expression>,
+    },
+    /// `x = value` や `x += value`（代入先は識別子に限る）
+    Assign {
+        operator: Token,
+        target: Box<Expression>,
+        value: Box<Expression>,
+    },
+    /// `util.f` 名前空間の要素
+    Member {
+        object: Box<Expression>,
+        name: String,
+    },
+}
+
+impl Expression {
+    /// パイプラインを等価な関数呼び出しに展開する
+    ///
+    /// `a |> f(b)` は `f(a, b)`、`a |> f` は `f(a)` になる。
+    pub fn desugar_pipe(&self) -> Option<Expression> {
+        let (left, right) = match self {
+            Expression::Pipe { left, right } => (left, right),
+            _ => return None,
+        };
+        let piped = Argument::Positional(*left.clone());
+        Some(match &**right {
+            Expression::Call {
+                function,
+                arguments,
+            } => Expression::Call {
+                function: function.clone(),
+                arguments: std::iter::once(piped)
+                    .chain(arguments.iter().cloned())
+                    .collect(),
+            },
+            function => Expression::Call {
+                function: Box::new(function.clone()),
+                arguments: vec![piped],
+            },
+        })
+    }
+}
+
+/// 関数の仮引数 `a`, `b: int = 10`, `...rest`
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct Parameter {
+    pub name: String,
+    /// `name: type` の型の注釈
+    pub annotation: Option<TypeExpr>,
+    pub default: Option<Expression>,
+    /// 残りの実引数をまとめて受け取る（最後の1つのみ）
+    pub rest: bool,
+}
+
+impl Parameter {
+    pub fn new(name: &str) -> Parameter {
+        Parameter {
+            name: name.to_string(),
+            annotation: None,
+            default: None,
+            rest: false,
+        }
+    }
+}
+
+/// 呼び出しの実引数
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum Argument {
+    /// `f(1)`
+    Positional(Expression),
+    /// `f(b: 3)`
+    Keyword(String, Expression),
+}
+
+impl From<Expression> for Argument {
+    fn from(value: Expression) -> Argument {
+        Argument::Positional(value)
+    }
+}
+
+impl Argument {
+    pub fn value(&self) -> &Expression {
+        match self {
+            Argument::Positional(value) | Argument::Keyword(_, value) => value,
+        }
+    }
+}
+
+/// match式の腕 `pattern if guard => body`
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Expression>,
+    pub body: Expression,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum Pattern {
+    /// `_`
+    Wildcard,
+    /// 値を名前に束縛する
+    Ident(String),
+    Int(i64),
+    Boolean(bool),
+    Str(String),
+    /// `[p1, p2]` 要素数も一致する必要がある。`[p1, ...rest]` なら残りの要素を配列で束縛する
+    Array {
+        elements: Vec<Pattern>,
+        rest: Option<String>,
+    },
+    /// `{"k": p}` キーはリテラルパターンに限る
+    Hash(Vec<(Pattern, Pattern)>),
+}
+
+impl Pattern {
+    /// どんな値にも一致するパターンか
+    pub fn is_irrefutable(&self) -> bool {
+        matches!(self, Pattern::Wildcard | Pattern::Ident(_))
+    }
+
+    /// パターンが束縛する名前（左から順に、重複も含めて）
+    pub fn bindings(&self) -> Vec<&str> {
+        let mut names = vec![];
+        self.collect_bindings(&mut names);
+        names
+    }
+
+    fn collect_bindings<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            Pattern::Ident(name) => names.push(name),
+            Pattern::Array { elements, rest } => {
+                for element in elements {
+                    element.collect_bindings(names);
+                }
+                names.extend(rest.as_deref());
+            }
+            Pattern::Hash(pairs) => {
+                for (_, value) in pairs {
+                    value.collect_bindings(names);
+                }
+            }
+            Pattern::Wildcard | Pattern::Int(_) | Pattern::Boolean(_) | Pattern::Str(_) => {}
+        }
+    }
+}
+
+/// 型の注釈（検査には使わず、書いたとおりに保持する）
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum TypeExpr {
+    /// `int`, `string` など
+    Named(String),
+    /// `[int]`
+    Array(Box<TypeExpr>),
+    /// `{string: int}`
+    Hash {
+        key: Box<TypeExpr>,
+        value: Box<TypeExpr>,
+    },
+    /// `fn(int, string) -> bool`
+    Function {
+        parameters: Vec<TypeExpr>,
+        return_type: Box<TypeExpr>,
+    },
+    /// `int?` 値がないこともある
+    Optional(Box<TypeExpr>),
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum Statement {
+    /// `let pattern: type = expr;` パターンは名前か配列・ハッシュの分解
+    Let {
+        pattern: Pattern,
+        annotation: Option<TypeExpr>,
+        expr: Expression,
+        /// 直前の `///` コメント
+        doc: Option<String>,
+    },
+    /// `const NAME: type = expr;` 代入で書き換えられない束縛
+    Const {
+        name: String,
+        annotation: Option<TypeExpr>,
+        expr: Expression,
+        doc: Option<String>,
+    },
+    Return(Expression),
+    Expr(Expression),
+    /// `fn name(params) -> type { body }`
+    FnDecl {
+        name: String,
+        parameters: Vec<Parameter>,
+        return_type: Option<TypeExpr>,
+        body: Expression,
+        doc: Option<String>,
+    },
+    Break,
+    Continue,
+    /// `infixl 65 <+>;` 以降の入力で使える中置演算子を宣言する
+    ///
+    /// 宣言は字句解析器全体に効くので、ブロックや関数の中で宣言してもその後ろの入力すべてで
+    /// 記号が1つのトークンになる。`//` を含む記号は宣言できない。
+    OperatorDecl {
+        associativity: Associativity,
+        precedence: u8,
+        operator: String,
+    },
+    /// `import "./util.monkey" as util;` パスは読み込む側のファイルからの相対パス
+    Import {
+        path: String,
+        alias: String,
+    },
+    /// `export let f = ...;` 対象は `let`・`const`・関数宣言に限る
+    Export(Box<Statement>),
+}
+
+impl Statement {
+    /// `///` コメントを持てる文ならその欄（`export` は対象の文の欄）
+    pub fn doc_mut(&mut self) -> Option<&mut Option<String>> {
+        match self {
+            Statement::Let { doc, .. }
+            | Statement::Const { doc, .. }
+            | Statement::FnDecl { doc, .. } => Some(doc),
+            Statement::Export(stmt) => stmt.doc_mut(),
+            _ => None,
+        }
+    }
+
+    /// 文が束縛する名前（`export` は対象の文が束縛する名前）
+    pub fn bindings(&self) -> Vec<&str> {
+        match self {
+            Statement::Let { pattern, .. } => pattern.bindings(),
+            Statement::Const { name, .. }
+            | Statement::FnDecl { name, .. }
+            | Statement::Import { alias: name, .. } => vec![name],
+            Statement::Export(stmt) => stmt.bindings(),
+            _ => vec![],
+        }
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expression::Ident(value) => write!(f, "{}", &value),
+            Expression::Int(value) => write!(f, "{}", value),
+            Expression::Boolean(value) => write!(f, "{}", value),
+            Expression::Str(value) => write!(f, "{:?}", value),
+            Expression::Grouped(value) => write!(f, "{}", value),
+            Expression::If {
+                condition,
+                consequence,
+                alternative,
+            } => match alternative.as_deref() {
+                Some(alt @ Expression::If { .. }) => {
+                    write!(f, "If{}{{{}}}else{}", condition, consequence, alt)
+                }
+                Some(alt) => write!(f, "If{}{{{}}}else{{{}}}", condition, consequence, alt),
+                None => write!(f, "If{}{{{}}}", condition, consequence),
+            },
+            Expression::Prefix { operator, right } => write!(f, "({}{})", operator, right),
+            Expression::Infix {
+                operator,
+                left,
+                right,
+            } => write!(f, "({}{}{})", left, operator, right),
+            Expression::Postfix { operator, left } => write!(f, "({}{})", left, operator),
+            Expression::Block(statements) => {
+                for stmt in statements.iter() {
+                    write!(f, "{}", stmt)?;
+                }
+                Ok(())
+            }
+            Expression::Function {
+                parameters,
+                return_type,
+                body,
+            } => write!(
+                f,
+                "fn({}){}{{{}}}",
+                join(parameters),
+                returns(return_type),
+                body
+            ),
+            Expression::MacroLiteral { parameters, body } => {
+                write!(f, "macro({}){{{}}}", join(parameters), body)
+            }
+            Expression::While { condition, body } => write!(f, "While{}{{{}}}", condition, body),
+            Expression::For {
+                variable,
+                iterable,
+                body,
+            } => write!(f, "For({} in {}){{{}}}", variable, iterable, body),
+            Expression::Match { subject, arms } => write!(f, "Match{}{{{}}}", subject, join(arms)),
+            Expression::Call {
+                function,
+                arguments,
+            } => {
+                write!(f, "{}({})", function, join(arguments))
+            }
+            Expression::Ternary {
+                condition,
+                consequence,
+                alternative,
+            } => write!(f, "({}?{}:{})", condition, consequence, alternative),
+            Expression::Pipe { left, right } => write!(f, "({}|>{})", left, right),
+            Expression::Assign {
+                operator,
+                target,
+                value,
+            } => write!(f, "({}{}{})", target, operator, value),
+            Expression::Member { object, name } => write!(f, "{}.{}", object, name),
+        }
+    }
+}
+
+/// `->type`（戻り値の型がなければ空）
+fn returns(return_type: &Option<TypeExpr>) -> String {
+    match return_type {
+        Some(ty) => format!("->{}", ty),
+        None => String::new(),
+    }
+}
+
+fn join<T: fmt::Display>(items: &[T]) -> String {
+    let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+    items.join(",")
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.rest {
+            write!(f, "...")?;
+        }
+        write!(f, "{}", self.name)?;
+        if let Some(annotation) = &self.annotation {
+            write!(f, ":{}", annotation)?;
+        }
+        match &self.default {
+            Some(default) => write!(f, "={}", default),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for Argument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Argument::Positional(value) => write!(f, "{}", value),
+            Argument::Keyword(name, value) => write!(f, "{}:{}", name, value),
+        }
+    }
+}
+
+impl fmt::Display for MatchArm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.guard {
+            Some(guard) => write!(f, "{} if {}=>{}", self.pattern, guard, self.body),
+            None => write!(f, "{}=>{}", self.pattern, self.body),
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Ident(name) => write!(f, "{}", name),
+            Pattern::Int(value) => write!(f, "{}", value),
+            Pattern::Boolean(value) => write!(f, "{}", value),
+            Pattern::Str(value) => write!(f, "{:?}", value),
+            Pattern::Array {
+                elements,
+                rest: None,
+            } => write!(f, "[{}]", join(elements)),
+            Pattern::Array {
+                elements,
+                rest: Some(rest),
+            } if elements.is_empty() => write!(f, "[...{}]", rest),
+            Pattern::Array {
+                elements,
+                rest: Some(rest),
+            } => write!(f, "[{},...{}]", join(elements), rest),
+            Pattern::Hash(pairs) => {
+                let pairs: Vec<String> = pairs
+                    .iter()
+                    .map(|(key, value)| format!("{}:{}", key, value))
+                    .collect();
+                write!(f, "{{{}}}", pairs.join(","))
+            }
+        }
+    }
+}
+
+impl fmt::Display for TypeExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeExpr::Named(name) => write!(f, "{}", name),
+            TypeExpr::Array(element) => write!(f, "[{}]", element),
+            TypeExpr::Hash { key, value } => write!(f, "{{{}:{}}}", key, value),
+            TypeExpr::Function {
+                parameters,
+                return_type,
+            } => write!(f, "fn({})->{}", join(parameters), return_type),
+            // `fn() -> int?` は戻り値の型が省略可能という意味になるので括弧で囲む
+            TypeExpr::Optional(inner) => match **inner {
+                TypeExpr::Function { .. } => write!(f, "({})?", inner),
+                _ => write!(f, "{}?", inner),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Statement::Expr(expr) => write!(f, "{}", expr),
+            Statement::Let {
+                pattern,
+                annotation: Some(annotation),
+                expr,
+                ..
+            } => write!(f, "Let {}:{}={}", pattern, annotation, expr),
+            Statement::Let { pattern, expr, .. } => write!(f, "Let {0:}={1:}", pattern, expr),
+            Statement::Const {
+                name,
+                annotation: Some(annotation),
+                expr,
+                ..
+            } => write!(f, "Const {}:{}={}", name, annotation, expr),
+            Statement::Const { name, expr, .. } => write!(f, "Const {}={}", name, expr),
+            Statement::Return(expr) => write!(f, "return {}", expr),
+            Statement::FnDecl {
+                name,
+                parameters,
+                return_type,
+                body,
+                ..
+            } => write!(
+                f,
+                "fn {}({}){}{{{}}}",
+                name,
+                join(parameters),
+                returns(return_type),
+                body
+            ),
+            Statement::Break => write!(f, "break"),
+            Statement::Continue => write!(f, "continue"),
+            Statement::OperatorDecl {
+                associativity,
+                precedence,
+                operator,
+            } => {
+                let keyword = match associativity {
+                    Associativity::Left => Token::INFIXL,
+                    Associativity::Right => Token::INFIXR,
+                    Associativity::None => Token::INFIX,
+                };
+                write!(f, "{} {} {}", keyword, precedence, operator)
+            }
+            Statement::Import { path, alias } => write!(f, "import {:?} as {}", path, alias),
+            Statement::Export(stmt) => write!(f, "export {}", stmt),
+        }
+    }
+}
+
+/// 構文木を読み取りのみで辿る
+///
+/// 既定の実装は `walk_*` で子を入力中の順に辿る。上書きしたメソッドから `walk_*` を呼べば子の走査を続けられる。
+pub trait Visitor<'ast>: Sized {
+    fn visit_statement(&mut self, stmt: &'ast Statement) {
+        walk_statement(self, stmt)
+    }
+
+    fn visit_expression(&mut self, expr: &'ast Expression) {
+        walk_expression(self, expr)
+    }
+
+    fn visit_parameter(&mut self, param: &'ast Parameter) {
+        walk_parameter(self, param)
+    }
+
+    fn visit_argument(&mut self, arg: &'ast Argument) {
+        walk_argument(self, arg)
+    }
+
+    fn visit_match_arm(&mut self, arm: &'ast MatchArm) {
+        walk_match_arm(self, arm)
+    }
+
+    fn visit_pattern(&mut self, pattern: &'ast Pattern) {
+        walk_pattern(self, pattern)
+    }
+
+    fn visit_type(&mut self, ty: &'ast TypeExpr) {
+        walk_type(self, ty)
+    }
+}
+
+pub fn walk_statement<'ast, V: Visitor<'ast>>(visitor: &mut V, stmt: &'ast Statement) {
+    match stmt {
+        Statement::Let {
+            pattern,
+            annotation,
+            expr,
+            ..
+        } => {
+            visitor.visit_pattern(pattern);
+            if let Some(ty) = annotation {
+                visitor.visit_type(ty);
+            }
+            visitor.visit_expression(expr)
+        }
+        Statement::Const {
+            annotation, expr, ..
+        } => {
+            if let Some(ty) = annotation {
+                visitor.visit_type(ty);
+            }
+            visitor.visit_expression(expr)
+        }
+        Statement::Return(expr) | Statement::Expr(expr) => visitor.visit_expression(expr),
+        Statement::FnDecl {
+            parameters,
+            return_type,
+            body,
+            ..
+        } => {
+            for param in parameters {
+                visitor.visit_parameter(param);
+            }
+            if let Some(ty) = return_type {
+                visitor.visit_type(ty);
+            }
+            visitor.visit_expression(body);
+        }
+        Statement::Export(stmt) => visitor.visit_statement(stmt),
+        Statement::Break
+        | Statement::Continue
+        | Statement::OperatorDecl { .. }
+        | Statement::Import { .. } => {}
+    }
+}
+
+pub fn walk_expression<'ast, V: Visitor<'ast>>(visitor: &mut V, expr: &'ast Expression) {
+    match expr {
+        Expression::Int(_) | Expression::Ident(_) => {}
+        Expression::Boolean(_) | Expression::Str(_) => {}
+        Expression::Grouped(inner) | Expression::Member { object: inner, .. } => {
+            visitor.visit_expression(inner)
+        }
+        Expression::If {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            visitor.visit_expression(condition);
+            visitor.visit_expression(consequence);
+            if let Some(alt) = alternative {
+                visitor.visit_expression(alt);
+            }
+        }
+        Expression::Ternary {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            visitor.visit_expression(condition);
+            visitor.visit_expression(consequence);
+            visitor.visit_expression(alternative);
+        }
+        Expression::Prefix { right, .. } => visitor.visit_expression(right),
+        Expression::Postfix { left, .. } => visitor.visit_expression(left),
+        Expression::Infix { left, right, .. }
+        | Expression::Pipe { left, right }
+        | Expression::Assign {
+            target: left,
+            value: right,
+            ..
+        } => {
+            visitor.visit_expression(left);
+            visitor.visit_expression(right);
+        }
+        Expression::Block(statements) => {
+            for stmt in statements {
+                visitor.visit_statement(stmt);
+            }
+        }
+        Expression::Function {
+            parameters,
+            return_type,
+            body,
+        } => {
+            for param in parameters {
+                visitor.visit_parameter(param);
+            }
+            if let Some(ty) = return_type {
+                visitor.visit_type(ty);
+            }
+            visitor.visit_expression(body);
+        }
+        Expression::MacroLiteral { parameters, body } => {
+            for param in parameters {
+                visitor.visit_parameter(param);
+            }
+            visitor.visit_expression(body);
+        }
+        Expression::While { condition, body } => {
+            visitor.visit_expression(condition);
+            visitor.visit_expression(body);
+        }
+        Expression::For { iterable, body, .. } => {
+            visitor.visit_expression(iterable);
+            visitor.visit_expression(body);
+        }
+        Expression::Match { subject, arms } => {
+            visitor.visit_expression(subject);
+            for arm in arms {
+                visitor.visit_match_arm(arm);
+            }
+        }
+        Expression::Call {
+            function,
+            arguments,
+        } => {
+            visitor.visit_expression(function);
+            for arg in arguments {
+                visitor.visit_argument(arg);
+            }
+        }
+    }
+}
+
+pub fn walk_parameter<'ast, V: Visitor<'ast>>(visitor: &mut V, param: &'ast Parameter) {
+    if let Some(ty) = &param.annotation {
+        visitor.visit_type(ty);
+    }
+    if let Some(default) = &param.default {
+        visitor.visit_expression(default);
+    }
+}
+
+pub fn walk_argument<'ast, V: Visitor<'ast>>(visitor: &mut V, arg: &'ast Argument) {
+    visitor.visit_expression(arg.value())
+}
+
+pub fn walk_match_arm<'ast, V: Visitor<'ast>>(visitor: &mut V, arm: &'ast MatchArm) {
+    visitor.visit_pattern(&arm.pattern);
+    if let Some(guard) = &arm.guard {
+        visitor.visit_expression(guard);
+    }
+    visitor.visit_expression(&arm.body);
+}
+
+pub fn walk_pattern<'ast, V: Visitor<'ast>>(visitor: &mut V, pattern: &'ast Pattern) {
+    match pattern {
+        Pattern::Array { elements, .. } => {
+            for element in elements {
+                visitor.visit_pattern(element);
+            }
+        }
+        Pattern::Hash(pairs) => {
+            for (key, value) in pairs {
+                visitor.visit_pattern(key);
+                visitor.visit_pattern(value);
+            }
+        }
+        _ => {}
+    }
+}
+
+pub fn walk_type<'ast, V: Visitor<'ast>>(visitor: &mut V, ty: &'ast TypeExpr) {
+    match ty {
+        TypeExpr::Named(_) => {}
+        TypeExpr::Array(inner) | TypeExpr::Optional(inner) => visitor.visit_type(inner),
+        TypeExpr::Hash { key, value } => {
+            visitor.visit_type(key);
+            visitor.visit_type(value);
+        }
+        TypeExpr::Function {
+            parameters,
+            return_type,
+        } => {
+            for parameter in parameters {
+                visitor.visit_type(parameter);
+            }
+            visitor.visit_type(return_type);
+        }
+    }
+}
+
+/// 構文木をその場で書き換えながら辿る
+pub trait VisitorMut: Sized {
+    fn visit_statement_mut(&mut self, stmt: &mut Statement) {
+        walk_statement_mut(self, stmt)
+    }
+
+    fn visit_expression_mut(&mut self, expr: &mut Expression) {
+        walk_expression_mut(self, expr)
+    }
+
+    fn visit_parameter_mut(&mut self, param: &mut Parameter) {
+        walk_parameter_mut(self, param)
+    }
+
+    fn visit_argument_mut(&mut self, arg: &mut Argument) {
+        walk_argument_mut(self, arg)
+    }
+
+    fn visit_match_arm_mut(&mut self, arm: &mut MatchArm) {
+        walk_match_arm_mut(self, arm)
+    }
+
+    fn visit_pattern_mut(&mut self, pattern: &mut Pattern) {
+        walk_pattern_mut(self, pattern)
+    }
+
+    fn visit_type_mut(&mut self, ty: &mut TypeExpr) {
+        walk_type_mut(self, ty)
+    }
+}
+
+pub fn walk_statement_mut<V: VisitorMut>(visitor: &mut V, stmt: &mut Statement) {
+    match stmt {
+        Statement::Let {
+            pattern,
+            annotation,
+            expr,
+            ..
+        } => {
+            visitor.visit_pattern_mut(pattern);
+            if let Some(ty) = annotation {
+                visitor.visit_type_mut(ty);
+            }
+            visitor.visit_expression_mut(expr)
+        }
+        Statement::Const {
+            annotation, expr, ..
+        } => {
+            if let Some(ty) = annotation {
+                visitor.visit_type_mut(ty);
+            }
+            visitor.visit_expression_mut(expr)
+        }
+        Statement::Return(expr) | Statement::Expr(expr) => visitor.visit_expression_mut(expr),
+        Statement::FnDecl {
+            parameters,
+            return_type,
+            body,
+            ..
+        } => {
+            for param in parameters {
+                visitor.visit_parameter_mut(param);
+            }
+            if let Some(ty) = return_type {
+                visitor.visit_type_mut(ty);
+            }
+            visitor.visit_expression_mut(body);
+        }
+        Statement::Export(stmt) => visitor.visit_statement_mut(stmt),
+        Statement::Break
+        | Statement::Continue
+        | Statement::OperatorDecl { .. }
+        | Statement::Import { .. } => {}
+    }
+}
+
+pub fn walk_expression_mut<V: VisitorMut>(visitor: &mut V, expr: &mut Expression) {
+    match expr {
+        Expression::Int(_) | Expression::Ident(_) => {}
+        Expression::Boolean(_) | Expression::Str(_) => {}
+        Expression::Grouped(inner) | Expression::Member { object: inner, .. } => {
+            visitor.visit_expression_mut(inner)
+        }
+        Expression::If {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            visitor.visit_expression_mut(condition);
+            visitor.visit_expression_mut(consequence);
+            if let Some(alt) = alternative {
+                visitor.visit_expression_mut(alt);
+            }
+        }
+        Expression::Ternary {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            visitor.visit_expression_mut(condition);
+            visitor.visit_expression_mut(consequence);
+            visitor.visit_expression_mut(alternative);
+        }
+        Expression::Prefix { right, .. } => visitor.visit_expression_mut(right),
+        Expression::Postfix { left, .. } => visitor.visit_expression_mut(left),
+        Expression::Infix { left, right, .. }
+        | Expression::Pipe { left, right }
+        | Expression::Assign {
+            target: left,
+            value: right,
+            ..
+        } => {
+            visitor.visit_expression_mut(left);
+            visitor.visit_expression_mut(right);
+        }
+        Expression::Block(statements) => {
+            for stmt in statements {
+                visitor.visit_statement_mut(stmt);
+            }
+        }
+        Expression::Function {
+            parameters,
+            return_type,
+            body,
+        } => {
+            for param in parameters {
+                visitor.visit_parameter_mut(param);
+            }
+            if let Some(ty) = return_type {
+                visitor.visit_type_mut(ty);
+            }
+            visitor.visit_expression_mut(body);
+        }
+        Expression::MacroLiteral { parameters, body } => {
+            for param in parameters {
+                visitor.visit_parameter_mut(param);
+            }
+            visitor.visit_expression_mut(body);
+        }
+        Expression::While { condition, body } => {
+            visitor.visit_expression_mut(condition);
+            visitor.visit_expression_mut(body);
+        }
+        Expression::For { iterable, body, .. } => {
+            visitor.visit_expression_mut(iterable);
+            visitor.visit_expression_mut(body);
+        }
+        Expression::Match { subject, arms } => {
+            visitor.visit_expression_mut(subject);
+            for arm in arms {
+                visitor.visit_match_arm_mut(arm);
+            }
+        }
+        Expression::Call {
+            function,
+            arguments,
+        } => {
+            visitor.visit_expression_mut(function);
+            for arg in arguments {
+                visitor.visit_argument_mut(arg);
+            }
+        }
+    }
+}
+
+pub fn walk_parameter_mut<V: VisitorMut>(visitor: &mut V, param: &mut Parameter) {
+    if let Some(ty) = &mut param.annotation {
+        visitor.visit_type_mut(ty);
+    }
+    if let Some(default) = &mut param.default {
+        visitor.visit_expression_mut(default);
+    }
+}
+
+pub fn walk_argument_mut<V: VisitorMut>(visitor: &mut V, arg: &mut Argument) {
+    match arg {
+        Argument::Positional(value) | Argument::Keyword(_, value) => {
+            visitor.visit_expression_mut(value)
+        }
+    }
+}
+
+pub fn walk_match_arm_mut<V: VisitorMut>(visitor: &mut V, arm: &mut MatchArm) {
+    visitor.visit_pattern_mut(&mut arm.pattern);
+    if let Some(guard) = &mut arm.guard {
+        visitor.visit_expression_mut(guard);
+    }
+    visitor.visit_expression_mut(&mut arm.body);
+}
+
+pub fn walk_pattern_mut<V: VisitorMut>(visitor: &mut V, pattern: &mut Pattern) {
+    match pattern {
+        Pattern::Array { elements, .. } => {
+            for element in elements {
+                visitor.visit_pattern_mut(element);
+            }
+        }
+        Pattern::Hash(pairs) => {
+            for (key, value) in pairs {
+                visitor.visit_pattern_mut(key);
+                visitor.visit_pattern_mut(value);
+            }
+        }
+        _ => {}
+    }
+}
+
+pub fn walk_type_mut<V: VisitorMut>(visitor: &mut V, ty: &mut TypeExpr) {
+    match ty {
+        TypeExpr::Named(_) => {}
+        TypeExpr::Array(inner) | TypeExpr::Optional(inner) => visitor.visit_type_mut(inner),
+        TypeExpr::Hash { key, value } => {
+            visitor.visit_type_mut(key);
+            visitor.visit_type_mut(value);
+        }
+        TypeExpr::Function {
+            parameters,
+            return_type,
+        } => {
+            for parameter in parameters {
+                visitor.visit_type_mut(parameter);
+            }
+            visitor.visit_type_mut(return_type);
+        }
+    }
+}
+
+/// 構文木を受け取って作り直す
+///
+/// 既定の実装は子を `fold_*` で作り直し、節点の形はそのまま保つ。
+pub trait Fold: Sized {
+    fn fold_statement(&mut self, stmt: Statement) -> Statement {
+        fold_statement(self, stmt)
+    }
+
+    fn fold_expression(&mut self, expr: Expression) -> Expression {
+        fold_expression(self, expr)
+    }
+
+    fn fold_parameter(&mut self, param: Parameter) -> Parameter {
+        fold_parameter(self, param)
+    }
+
+    fn fold_argument(&mut self, arg: Argument) -> Argument {
+        fold_argument(self, arg)
+    }
+
+    fn fold_match_arm(&mut self, arm: MatchArm) -> MatchArm {
+        fold_match_arm(self, arm)
+    }
+
+    fn fold_pattern(&mut self, pattern: Pattern) -> Pattern {
+        fold_pattern(self, pattern)
+    }
+
+    fn fold_type(&mut self, ty: TypeExpr) -> TypeExpr {
+        fold_type(self, ty)
+    }
+}
+
+pub fn fold_statement<F: Fold>(folder: &mut F, stmt: Statement) -> Statement {
+    match stmt {
+        Statement::Let {
+            pattern,
+            annotation,
+            expr,
+            doc,
+        } => Statement::Let {
+            pattern: folder.fold_pattern(pattern),
+            annotation: annotation.map(|ty| folder.fold_type(ty)),
+            expr: folder.fold_expression(expr),
+            doc,
+        },
+        Statement::Const {
+            name,
+            annotation,
+            expr,
+            doc,
+        } => Statement::Const {
+            name,
+            annotation: annotation.map(|ty| folder.fold_type(ty)),
+            expr: folder.fold_expression(expr),
+            doc,
+        },
+        Statement::Return(expr) => Statement::Return(folder.fold_expression(expr)),
+        Statement::Expr(expr) => Statement::Expr(folder.fold_expression(expr)),
+        Statement::FnDecl {
+            name,
+            parameters,
+            return_type,
+            body,
+            doc,
+        } => Statement::FnDecl {
+            name,
+            parameters: fold_parameters(folder, parameters),
+            return_type: return_type.map(|ty| folder.fold_type(ty)),
+            body: folder.fold_expression(body),
+            doc,
+        },
+        Statement::Export(stmt) => Statement::Export(Box::new(folder.fold_statement(*stmt))),
+        stmt @ Statement::Break
+        | stmt @ Statement::Continue
+        | stmt @ Statement::OperatorDecl { .. }
+        | stmt @ Statement::Import { .. } => stmt,
+    }
+}
+
+pub fn fold_expression<F: Fold>(folder: &mut F, expr: Expression) -> Expression {
+    let mut fold_box = |expr: Box<Expression>| Box::new(folder.fold_expression(*expr));
+    match expr {
+        Expression::Grouped(inner) => Expression::Grouped(fold_box(inner)),
+        Expression::If {
+            condition,
+            consequence,
+            alternative,
+        } => Expression::If {
+            condition: fold_box(condition),
+            consequence: fold_box(consequence),
+            alternative: alternative.map(fold_box),
+        },
+        Expression::Ternary {
+            condition,
+            consequence,
+            alternative,
+        } => Expression::Ternary {
+            condition: fold_box(condition),
+            consequence: fold_box(consequence),
+            alternative: fold_box(alternative),
+        },
+        Expression::Prefix { operator, right } => Expression::Prefix {
+            operator,
+            right: fold_box(right),
+        },
+        Expression::Postfix { operator, left } => Expression::Postfix {
+            operator,
+            left: fold_box(left),
+        },
+        Expression::Infix {
+            operator,
+            left,
+            right,
+        } => Expression::Infix {
+            operator,
+            left: fold_box(left),
+            right: fold_box(right),
+        },
+        Expression::Pipe { left, right } => Expression::Pipe {
+            left: fold_box(left),
+            right: fold_box(right),
+        },
+        Expression::Assign {
+            operator,
+            target,
+            value,
+        } => Expression::Assign {
+            operator,
+            target: fold_box(target),
+            value: fold_box(value),
+        },
+        Expression::Member { object, name } => Expression::Member {
+            object: fold_box(object),
+            name,
+        },
+        Expression::Block(statements) => Expression::Block(
+            statements
+                .into_iter()
+                .map(|stmt| folder.fold_statement(stmt))
+                .collect(),
+        ),
+        Expression::Function {
+            parameters,
+            return_type,
+            body,
+        } => Expression::Function {
+            parameters: fold_parameters(folder, parameters),
+            return_type: return_type.map(|ty| folder.fold_type(ty)),
+            body: Box::new(folder.fold_expression(*body)),
+        },
+        Expression::MacroLiteral { parameters, body } => Expression::MacroLiteral {
+            parameters: fold_parameters(folder, parameters),
+            body: Box::new(folder.fold_expression(*body)),
+        },
+        Expression::While { condition, body } => Expression::While {
+            condition: fold_box(condition),
+            body: fold_box(body),
+        },
+        Expression::For {
+            variable,
+            iterable,
+            body,
+        } => Expression::For {
+            variable,
+            iterable: fold_box(iterable),
+            body: fold_box(body),
+        },
+        Expression::Match { subject, arms } => Expression::Match {
+            subject: Box::new(folder.fold_expression(*subject)),
+            arms: arms
+                .into_iter()
+                .map(|arm| folder.fold_match_arm(arm))
+                .collect(),
+        },
+        Expression::Call {
+            function,
+            arguments,
+        } => Expression::Call {
+            function: Box::new(folder.fold_expression(*function)),
+            arguments: arguments
+                .into_iter()
+                .map(|arg| folder.fold_argument(arg))
+                .collect(),
+        },
+        expr @ Expression::Int(_)
+        | expr @ Expression::Ident(_)
+        | expr @ Expression::Boolean(_)
+        | expr @ Expression::Str(_) => expr,
+    }
+}
+
+fn fold_parameters<F: Fold>(folder: &mut F, parameters: Vec<Parameter>) -> Vec<Parameter> {
+    parameters
+        .into_iter()
+        .map(|param| folder.fold_parameter(param))
+        .collect()
+}
+
+pub fn fold_parameter<F: Fold>(folder: &mut F, param: Parameter) -> Parameter {
+    Parameter {
+        annotation: param.annotation.map(|ty| folder.fold_type(ty)),
+        default: param.default.map(|default| folder.fold_expression(default)),
+        ..param
+    }
+}
+
+pub fn fold_argument<F: Fold>(folder: &mut F, arg: Argument) -> Argument {
+    match arg {
+        Argument::Positional(value) => Argument::Positional(folder.fold_expression(value)),
+        Argument::Keyword(name, value) => Argument::Keyword(name, folder.fold_expression(value)),
+    }
+}
+
+pub fn fold_match_arm<F: Fold>(folder: &mut F, arm: MatchArm) -> MatchArm {
+    MatchArm {
+        pattern: folder.fold_pattern(arm.pattern),
+        guard: arm.guard.map(|guard| folder.fold_expression(guard)),
+        body: folder.fold_expression(arm.body),
+    }
+}
+
+pub fn fold_pattern<F: Fold>(folder: &mut F, pattern: Pattern) -> Pattern {
+    match pattern {
+        Pattern::Array { elements, rest } => Pattern::Array {
+            elements: elements
+                .into_iter()
+                .map(|element| folder.fold_pattern(element))
+                .collect(),
+            rest,
+        },
+        Pattern::Hash(pairs) => Pattern::Hash(
+            pairs
+                .into_iter()
+                .map(|(key, value)| (folder.fold_pattern(key), folder.fold_pattern(value)))
+                .collect(),
+        ),
+        pattern => pattern,
+    }
+}
+
+pub fn fold_type<F: Fold>(folder: &mut F, ty: TypeExpr) -> TypeExpr {
+    let mut fold_box = |ty: Box<TypeExpr>| Box::new(folder.fold_type(*ty));
+    match ty {
+        TypeExpr::Array(inner) => TypeExpr::Array(fold_box(inner)),
+        TypeExpr::Optional(inner) => TypeExpr::Optional(fold_box(inner)),
+        TypeExpr::Hash { key, value } => TypeExpr::Hash {
+            key: fold_box(key),
+            value: fold_box(value),
+        },
+        TypeExpr::Function {
+            parameters,
+            return_type,
+        } => TypeExpr::Function {
+            parameters: parameters
+                .into_iter()
+                .map(|parameter| folder.fold_type(parameter))
+                .collect(),
+            return_type: Box::new(folder.fold_type(*return_type)),
+        },
+        ty @ TypeExpr::Named(_) => ty,
+    }
+}
+
+#[cfg(test)]
+fn parse_source(input: &str) -> Vec<Statement> {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    Parser::new(Lexer::new(input)).parse_program()
+}
+
+#[test]
+fn test_visitor() {
+    struct Idents<'ast>(Vec<&'ast str>);
+    impl<'ast> Visitor<'ast> for Idents<'ast> {
+        fn visit_expression(&mut self, expr: &'ast Expression) {
+            if let Expression::Ident(name) = expr {
+                self.0.push(name);
+            }
+            walk_expression(self, expr)
+        }
+    }
+    let program = parse_source(
+        "fn f(a, b = c) { if (a) { d } else { e(g: h) } } match (i) { j if k => l, _ => m }",
+    );
+    let mut idents = Idents(vec![]);
+    for stmt in &program {
+        idents.visit_statement(stmt);
+    }
+    assert_eq!(idents.0, vec!["c", "a", "d", "e", "h", "i", "k", "l", "m"]);
+}
+
+#[test]
+fn test_visitor_mut() {
+    struct Rename;
+    impl VisitorMut for Rename {
+        fn visit_expression_mut(&mut self, expr: &mut Expression) {
+            if let Expression::Ident(name) = expr {
+                name.insert(0, '_');
+            }
+            walk_expression_mut(self, expr)
+        }
+    }
+    let mut program = parse_source("let x = fn(a) { a + y }(z);");
+    for stmt in &mut program {
+        Rename.visit_statement_mut(stmt);
+    }
+    assert_eq!(program[0].to_string(), "Let x=fn(a){(_a+_y)}(_z)");
+}
+
+#[test]
+fn test_fold() {
+    struct ConstantFold;
+    impl Fold for ConstantFold {
+        fn fold_expression(&mut self, expr: Expression) -> Expression {
+            match fold_expression(self, expr) {
+                Expression::Infix {
+                    operator: Token::PLUS,
+                    left,
+                    right,
+                } => match (*left, *right) {
+                    (Expression::Int(a), Expression::Int(b)) => Expression::Int(a + b),
+                    (left, right) => Expression::Infix {
+                        operator: Token::PLUS,
+                        left: Box::new(left),
+                        right: Box::new(right),
+                    },
+                },
+                expr => expr,
+            }
+        }
+    }
+    let program = parse_source("while (x) { f(1 + 2 + 3, y + 1); }");
+    let folded: Vec<Statement> = program
+        .into_iter()
+        .map(|stmt| ConstantFold.fold_statement(stmt))
+        .collect();
+    assert_eq!(folded[0].to_string(), "Whilex{f(6,(y+1))}");
+}
+
+#[test]
+fn test_type_visitors() {
+    struct Names<'ast>(Vec<&'ast str>);
+    impl<'ast> Visitor<'ast> for Names<'ast> {
+        fn visit_type(&mut self, ty: &'ast TypeExpr) {
+            if let TypeExpr::Named(name) = ty {
+                self.0.push(name);
+            }
+            walk_type(self, ty)
+        }
+    }
+    let input = "let a: [b] = fn(c: d?, e = fn() -> f { 1 }) -> {g: h} { 0 }; \
+        const i: fn(j) -> k = l; fn m(...n: [o]) -> p { q }";
+    let program = parse_source(input);
+    let mut names = Names(vec![]);
+    for stmt in &program {
+        names.visit_statement(stmt);
+    }
+    assert_eq!(names.0, vec!["b", "d", "f", "g", "h", "j", "k", "o", "p"]);
+
+    struct Rename;
+    impl VisitorMut for Rename {
+        fn visit_type_mut(&mut self, ty: &mut TypeExpr) {
+            if let TypeExpr::Named(name) = ty {
+                name.insert(0, '_');
+            }
+            walk_type_mut(self, ty)
+        }
+    }
+    let mut renamed = program.clone();
+    for stmt in &mut renamed {
+        Rename.visit_statement_mut(stmt);
+    }
+    assert_eq!(renamed[1].to_string(), "Const i:fn(_j)->_k=l");
+
+    struct Required;
+    impl Fold for Required {
+        fn fold_type(&mut self, ty: TypeExpr) -> TypeExpr {
+            match fold_type(self, ty) {
+                TypeExpr::Optional(inner) => *inner,
+                ty => ty,
+            }
+        }
+    }
+    let folded: Vec<Statement> = parse_source("let x: [int?]? = fn(a: int?) -> int? { a };")
+        .into_iter()
+        .map(|stmt| Required.fold_statement(stmt))
+        .collect();
+    assert_eq!(folded[0].to_string(), "Let x:[int]=fn(a:int)->int{a}");
+}
@@ -0,0 +1,525 @@
+//! 構文木を組み立てるビルダー
+//!
+//! `Box::new(Expression::Infix { .. })` を入れ子に書かずに済むよう、子を値で受け取って式や文を返す。
+//! `b.call(b.ident("add"), [b.int(1), b.int(2)])` は `add(1, 2)` になる。
+//! 組み立てた構文木は検査しないので、必要なら `validate::validate_program` に通す。
+use crate::ast::{Argument, Expression, MatchArm, Parameter, Pattern, Statement, TypeExpr};
+use crate::operator::Associativity;
+use crate::token::Token;
+use crate::validate::operator_token;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Builder;
+
+impl Builder {
+    pub fn new() -> Builder {
+        Builder
+    }
+
+    pub fn int(&self, value: i64) -> Expression {
+        Expression::Int(value)
+    }
+
+    pub fn ident(&self, name: &str) -> Expression {
+        Expression::Ident(name.to_string())
+    }
+
+    pub fn boolean(&self, value: bool) -> Expression {
+        Expression::Boolean(value)
+    }
+
+    pub fn string(&self, value: &str) -> Expression {
+        Expression::Str(value.to_string())
+    }
+
+    pub fn grouped(&self, expr: Expression) -> Expression {
+        Expression::Grouped(Box::new(expr))
+    }
+
+    pub fn prefix(&self, operator: Token, right: Expression) -> Expression {
+        Expression::Prefix {
+            operator,
+            right: Box::new(right),
+        }
+    }
+
+    pub fn infix(&self, operator: Token, left: Expression, right: Expression) -> Expression {
+        Expression::Infix {
+            operator,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    /// 記号で指定する中置演算 `b.binary("+", a, c)`（利用者定義の演算子も使える）
+    pub fn binary(&self, symbol: &str, left: Expression, right: Expression) -> Expression {
+        self.infix(operator_token(symbol), left, right)
+    }
+
+    pub fn postfix(&self, operator: Token, left: Expression) -> Expression {
+        Expression::Postfix {
+            operator,
+            left: Box::new(left),
+        }
+    }
+
+    pub fn block(&self, statements: impl IntoIterator<Item = Statement>) -> Expression {
+        Expression::Block(statements.into_iter().collect())
+    }
+
+    pub fn if_then(&self, condition: Expression, consequence: Expression) -> Expression {
+        Expression::If {
+            condition: Box::new(condition),
+            consequence: Box::new(consequence),
+            alternative: None,
+        }
+    }
+
+    pub fn if_else(
+        &self,
+        condition: Expression,
+        consequence: Expression,
+        alternative: Expression,
+    ) -> Expression {
+        Expression::If {
+            condition: Box::new(condition),
+            consequence: Box::new(consequence),
+            alternative: Some(Box::new(alternative)),
+        }
+    }
+
+    pub fn function(
+        &self,
+        parameters: impl IntoIterator<Item = Parameter>,
+        body: Expression,
+    ) -> Expression {
+        Expression::Function {
+            parameters: parameters.into_iter().collect(),
+            return_type: None,
+            body: Box::new(body),
+        }
+    }
+
+    /// `fn(params) -> type { body }`
+    pub fn function_returning(
+        &self,
+        parameters: impl IntoIterator<Item = Parameter>,
+        return_type: TypeExpr,
+        body: Expression,
+    ) -> Expression {
+        Expression::Function {
+            parameters: parameters.into_iter().collect(),
+            return_type: Some(return_type),
+            body: Box::new(body),
+        }
+    }
+
+    /// `macro(params) { body }`
+    pub fn macro_literal(
+        &self,
+        parameters: impl IntoIterator<Item = Parameter>,
+        body: Expression,
+    ) -> Expression {
+        Expression::MacroLiteral {
+            parameters: parameters.into_iter().collect(),
+            body: Box::new(body),
+        }
+    }
+
+    pub fn while_loop(&self, condition: Expression, body: Expression) -> Expression {
+        Expression::While {
+            condition: Box::new(condition),
+            body: Box::new(body),
+        }
+    }
+
+    pub fn for_loop(&self, variable: &str, iterable: Expression, body: Expression) -> Expression {
+        Expression::For {
+            variable: variable.to_string(),
+            iterable: Box::new(iterable),
+            body: Box::new(body),
+        }
+    }
+
+    pub fn match_expr(
+        &self,
+        subject: Expression,
+        arms: impl IntoIterator<Item = MatchArm>,
+    ) -> Expression {
+        Expression::Match {
+            subject: Box::new(subject),
+            arms: arms.into_iter().collect(),
+        }
+    }
+
+    /// 実引数には式（位置引数）と `keyword` の結果を渡せる
+    pub fn call<A: Into<Argument>>(
+        &self,
+        function: Expression,
+        arguments: impl IntoIterator<Item = A>,
+    ) -> Expression {
+        Expression::Call {
+            function: Box::new(function),
+            arguments: arguments.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    pub fn ternary(
+        &self,
+        condition: Expression,
+        consequence: Expression,
+        alternative: Expression,
+    ) -> Expression {
+        Expression::Ternary {
+            condition: Box::new(condition),
+            consequence: Box::new(consequence),
+            alternative: Box::new(alternative),
+        }
+    }
+
+    pub fn pipe(&self, left: Expression, right: Expression) -> Expression {
+        Expression::Pipe {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    /// `target = value` や `target += value`（代入の記号で指定する）
+    pub fn assign(&self, symbol: &str, target: Expression, value: Expression) -> Expression {
+        Expression::Assign {
+            operator: operator_token(symbol),
+            target: Box::new(target),
+            value: Box::new(value),
+        }
+    }
+
+    /// `object.name`
+    pub fn member(&self, object: Expression, name: &str) -> Expression {
+        Expression::Member {
+            object: Box::new(object),
+            name: name.to_string(),
+        }
+    }
+
+    /// `name: value`
+    pub fn keyword(&self, name: &str, value: Expression) -> Argument {
+        Argument::Keyword(name.to_string(), value)
+    }
+
+    pub fn param(&self, name: &str) -> Parameter {
+        Parameter::new(name)
+    }
+
+    /// `name = default`
+    pub fn param_default(&self, name: &str, default: Expression) -> Parameter {
+        Parameter {
+            default: Some(default),
+            ..Parameter::new(name)
+        }
+    }
+
+    /// `...name`
+    /// `name: type`
+    pub fn typed_param(&self, name: &str, ty: TypeExpr) -> Parameter {
+        Parameter {
+            annotation: Some(ty),
+            ..Parameter::new(name)
+        }
+    }
+
+    pub fn rest_param(&self, name: &str) -> Parameter {
+        Parameter {
+            rest: true,
+            ..Parameter::new(name)
+        }
+    }
+
+    pub fn arm(&self, pattern: Pattern, body: Expression) -> MatchArm {
+        MatchArm {
+            pattern,
+            guard: None,
+            body,
+        }
+    }
+
+    pub fn guarded_arm(&self, pattern: Pattern, guard: Expression, body: Expression) -> MatchArm {
+        MatchArm {
+            pattern,
+            guard: Some(guard),
+            body,
+        }
+    }
+
+    pub fn let_stmt(&self, name: &str, expr: Expression) -> Statement {
+        self.let_pattern(Pattern::Ident(name.to_string()), expr)
+    }
+
+    /// `let [a, ...rest] = expr;` のように分解して束縛する
+    pub fn let_pattern(&self, pattern: Pattern, expr: Expression) -> Statement {
+        Statement::Let {
+            pattern,
+            annotation: None,
+            expr,
+            doc: None,
+        }
+    }
+
+    /// `let name: type = expr;`
+    pub fn let_typed(&self, name: &str, ty: TypeExpr, expr: Expression) -> Statement {
+        Statement::Let {
+            pattern: Pattern::Ident(name.to_string()),
+            annotation: Some(ty),
+            expr,
+            doc: None,
+        }
+    }
+
+    pub fn const_stmt(&self, name: &str, expr: Expression) -> Statement {
+        Statement::Const {
+            name: name.to_string(),
+            annotation: None,
+            expr,
+            doc: None,
+        }
+    }
+
+    /// `const NAME: type = expr;`
+    pub fn const_typed(&self, name: &str, ty: TypeExpr, expr: Expression) -> Statement {
+        Statement::Const {
+            name: name.to_string(),
+            annotation: Some(ty),
+            expr,
+            doc: None,
+        }
+    }
+
+    pub fn return_stmt(&self, expr: Expression) -> Statement {
+        Statement::Return(expr)
+    }
+
+    pub fn expr_stmt(&self, expr: Expression) -> Statement {
+        Statement::Expr(expr)
+    }
+
+    pub fn fn_decl(
+        &self,
+        name: &str,
+        parameters: impl IntoIterator<Item = Parameter>,
+        body: Expression,
+    ) -> Statement {
+        Statement::FnDecl {
+            name: name.to_string(),
+            parameters: parameters.into_iter().collect(),
+            return_type: None,
+            body,
+            doc: None,
+        }
+    }
+
+    /// `fn name(params) -> type { body }`
+    pub fn fn_decl_returning(
+        &self,
+        name: &str,
+        parameters: impl IntoIterator<Item = Parameter>,
+        return_type: TypeExpr,
+        body: Expression,
+    ) -> Statement {
+        Statement::FnDecl {
+            name: name.to_string(),
+            parameters: parameters.into_iter().collect(),
+            return_type: Some(return_type),
+            body,
+            doc: None,
+        }
+    }
+
+    /// `let`・`const` と関数宣言（`export` したものも含む）に `///` コメントを付ける
+    ///
+    /// 他の文はそのまま返す。
+    pub fn documented(&self, doc: &str, mut stmt: Statement) -> Statement {
+        if let Some(slot) = stmt.doc_mut() {
+            *slot = Some(doc.to_string());
+        }
+        stmt
+    }
+
+    /// `import "path" as alias;`
+    pub fn import(&self, path: &str, alias: &str) -> Statement {
+        Statement::Import {
+            path: path.to_string(),
+            alias: alias.to_string(),
+        }
+    }
+
+    pub fn export(&self, stmt: Statement) -> Statement {
+        Statement::Export(Box::new(stmt))
+    }
+
+    pub fn break_stmt(&self) -> Statement {
+        Statement::Break
+    }
+
+    pub fn continue_stmt(&self) -> Statement {
+        Statement::Continue
+    }
+
+    /// `int` や `string` などの名前の型
+    pub fn named_type(&self, name: &str) -> TypeExpr {
+        TypeExpr::Named(name.to_string())
+    }
+
+    /// `[element]`
+    pub fn array_type(&self, element: TypeExpr) -> TypeExpr {
+        TypeExpr::Array(Box::new(element))
+    }
+
+    /// `{key: value}`
+    pub fn hash_type(&self, key: TypeExpr, value: TypeExpr) -> TypeExpr {
+        TypeExpr::Hash {
+            key: Box::new(key),
+            value: Box::new(value),
+        }
+    }
+
+    /// `fn(parameters) -> return_type`
+    pub fn fn_type(
+        &self,
+        parameters: impl IntoIterator<Item = TypeExpr>,
+        return_type: TypeExpr,
+    ) -> TypeExpr {
+        TypeExpr::Function {
+            parameters: parameters.into_iter().collect(),
+            return_type: Box::new(return_type),
+        }
+    }
+
+    /// `inner?`
+    pub fn optional_type(&self, inner: TypeExpr) -> TypeExpr {
+        TypeExpr::Optional(Box::new(inner))
+    }
+
+    pub fn operator_decl(
+        &self,
+        associativity: Associativity,
+        precedence: u8,
+        operator: &str,
+    ) -> Statement {
+        Statement::OperatorDecl {
+            associativity,
+            precedence,
+            operator: operator.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+fn parse(input: &str) -> Vec<Statement> {
+    let mut parser = crate::parser::Parser::new(crate::lexer::Lexer::new(input));
+    let program = parser.parse_program();
+    assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+    program
+}
+
+#[test]
+fn test_builder_matches_parser() {
+    let b = Builder::new();
+    let program = vec![
+        b.fn_decl(
+            "f",
+            vec![
+                b.param("a"),
+                b.param_default("k", b.int(1)),
+                b.rest_param("xs"),
+            ],
+            b.block(vec![b.expr_stmt(b.while_loop(
+                b.binary(">", b.ident("a"), b.int(0)),
+                b.block(vec![b.expr_stmt(b.if_else(
+                    b.prefix(Token::BANG, b.ident("k")),
+                    b.block(vec![b.break_stmt()]),
+                    b.block(vec![b.continue_stmt()]),
+                ))]),
+            ))]),
+        ),
+        b.documented(
+            "Calls f.",
+            b.let_stmt(
+                "x",
+                b.call(
+                    b.ident("f"),
+                    vec![b.int(1).into(), b.keyword("k", b.string("s"))],
+                ),
+            ),
+        ),
+        b.operator_decl(Associativity::Left, 65, "<+>"),
+        b.expr_stmt(b.match_expr(
+            b.binary("<+>", b.ident("x"), b.grouped(b.ident("y"))),
+            vec![
+                b.guarded_arm(Pattern::Int(1), b.boolean(true), b.int(2)),
+                b.arm(Pattern::Wildcard, b.pipe(b.ident("x"), b.ident("g"))),
+            ],
+        )),
+        b.expr_stmt(b.for_loop(
+            "i",
+            b.ident("xs"),
+            b.block(vec![b.return_stmt(b.ternary(
+                b.ident("i"),
+                b.int(1),
+                b.int(0),
+            ))]),
+        )),
+        b.documented("Limit.", b.export(b.const_stmt("n", b.int(3)))),
+        b.import("./util.monkey", "util"),
+        b.expr_stmt(b.assign(
+            "=",
+            b.ident("x"),
+            b.assign("+=", b.ident("y"), b.member(b.ident("util"), "n")),
+        )),
+        b.let_typed(
+            "scores",
+            b.optional_type(b.hash_type(b.named_type("string"), b.named_type("int"))),
+            b.ident("s"),
+        ),
+        b.const_typed("max", b.named_type("int"), b.int(10)),
+        b.fn_decl_returning(
+            "apply",
+            vec![
+                b.typed_param(
+                    "g",
+                    b.fn_type(vec![b.named_type("int")], b.named_type("bool")),
+                ),
+                b.param("v"),
+            ],
+            b.array_type(b.named_type("bool")),
+            b.block(vec![b.expr_stmt(b.function_returning(
+                vec![],
+                b.named_type("bool"),
+                b.block(vec![b.expr_stmt(b.call(b.ident("g"), [b.ident("v")]))]),
+            ))]),
+        ),
+    ];
+    let input = "fn f(a, k = 1, ...xs) { while (a > 0) { if (!k) { break } else { continue } } }
+        /// Calls f.
+        let x = f(1, k: \"s\");
+        infixl 65 <+>;
+        match (x <+> (y)) { 1 if true => 2, _ => x |> g };
+        for (i in xs) { return i ? 1 : 0; }
+        /// Limit.
+        export const n = 3;
+        import \"./util.monkey\" as util;
+        x = y += util.n;
+        let scores: {string: int}? = s;
+        const max: int = 10;
+        fn apply(g: fn(int) -> bool, v) -> [bool] { fn() -> bool { g(v) } }";
+    assert_eq!(program, parse(input));
+    assert_eq!(
+        crate::validate::validate_program(&program),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn test_builder_call() {
+    let b = Builder::new();
+    let expr = b.call(b.ident("add"), [b.int(1), b.int(2)]);
+    assert_eq!(parse("add(1, 2)"), vec![b.expr_stmt(expr)]);
+}
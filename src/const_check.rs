@@ -0,0 +1,319 @@
+//! `const` で束縛した名前への代入の検査
+//!
+//! 名前は内側のスコープから探し、最も内側の束縛が `const` なら `=` も複合代入も誤りにする。
+//! 同じスコープで `const` の名前を `let`・`const`・`fn`・`import` で宣言し直すのも誤りにする。
+//! 節点の番号は `node_id::NodeIds` で引くので、`SpanMap` で位置を引ける。
+use crate::ast::{walk_expression, walk_match_arm, walk_statement, Visitor};
+use crate::ast::{Expression, MatchArm, Parameter, Statement};
+use crate::node_id::{NodeId, NodeIds, SpanMap};
+use crate::token::Token;
+use std::fmt;
+
+/// `const` への代入か、同じスコープでの宣言し直し
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ConstViolation {
+    pub name: String,
+    /// `=` か複合代入の演算子（宣言し直しなら `let` などのキーワード）
+    pub operator: Token,
+    /// 代入式か、宣言し直した文の番号
+    pub assignment: NodeId,
+    /// `const` 文の番号
+    pub declaration: NodeId,
+}
+
+impl ConstViolation {
+    fn action(&self) -> String {
+        match self.operator {
+            Token::ASSIGN => format!("cannot reassign const {}", self.name),
+            Token::LET | Token::CONST | Token::FUNCTION | Token::IMPORT => {
+                format!("cannot redeclare const {}", self.name)
+            }
+            _ => format!("cannot apply {} to const {}", self.operator, self.name),
+        }
+    }
+
+    /// 代入と宣言の位置を入力中の範囲で示す（範囲が分からなければ番号）
+    pub fn describe(&self, spans: &SpanMap) -> String {
+        let location = |id: NodeId| match spans.get(id) {
+            Some(span) => span.to_string(),
+            None => id.to_string(),
+        };
+        format!(
+            "{} at {} (declared at {})",
+            self.action(),
+            location(self.assignment),
+            location(self.declaration)
+        )
+    }
+}
+
+impl fmt::Display for ConstViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at {} (declared at {})",
+            self.action(),
+            self.assignment,
+            self.declaration
+        )
+    }
+}
+
+/// プログラム中の `const` への代入をすべて返す
+pub fn check_program(program: &[Statement]) -> Vec<ConstViolation> {
+    let mut checker = ConstChecker {
+        ids: NodeIds::new(program),
+        scopes: vec![vec![]],
+        violations: vec![],
+    };
+    for stmt in program {
+        checker.visit_statement(stmt);
+    }
+    checker.violations
+}
+
+struct ConstChecker {
+    ids: NodeIds,
+    /// スコープごとの束縛（`const` なら宣言の番号）
+    scopes: Vec<Vec<(String, Option<NodeId>)>>,
+    violations: Vec<ConstViolation>,
+}
+
+impl ConstChecker {
+    fn bind(&mut self, name: &str, declaration: Option<NodeId>) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push((name.to_string(), declaration));
+        }
+    }
+
+    /// 同じスコープの `const` を宣言し直していれば誤りにしてから束縛する
+    fn declare(&mut self, name: &str, keyword: Token, id: NodeId, declaration: Option<NodeId>) {
+        if let Some(original) = self.const_in_scope(name) {
+            self.violations.push(ConstViolation {
+                name: name.to_string(),
+                operator: keyword,
+                assignment: id,
+                declaration: original,
+            });
+        }
+        self.bind(name, declaration);
+    }
+
+    /// 最も内側のスコープで最後に束縛した同じ名前が `const` ならその宣言
+    fn const_in_scope(&self, name: &str) -> Option<NodeId> {
+        self.scopes
+            .last()
+            .and_then(|scope| scope.iter().rev().find(|(bound, _)| bound == name))
+            .and_then(|(_, declaration)| *declaration)
+    }
+
+    /// 最も内側の束縛が `const` ならその宣言
+    fn const_declaration(&self, name: &str) -> Option<NodeId> {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .find(|(bound, _)| bound == name)
+            .and_then(|(_, declaration)| *declaration)
+    }
+
+    fn scoped(&mut self, f: impl FnOnce(&mut ConstChecker)) {
+        self.scopes.push(vec![]);
+        f(self);
+        self.scopes.pop();
+    }
+}
+
+impl Visitor<'_> for ConstChecker {
+    fn visit_statement(&mut self, stmt: &Statement) {
+        // 関数は本体の中から自分を呼べるよう、本体より先に束縛する
+        let redeclared = match stmt {
+            Statement::FnDecl { name, .. } => {
+                let original = self.const_in_scope(name);
+                self.bind(name, None);
+                original
+            }
+            _ => None,
+        };
+        walk_statement(self, stmt);
+        let id = self.ids.statement(stmt);
+        match stmt {
+            Statement::Let { pattern, .. } => {
+                for name in pattern.bindings() {
+                    self.declare(name, Token::LET, id, None);
+                }
+            }
+            Statement::Const { name, .. } => self.declare(name, Token::CONST, id, Some(id)),
+            Statement::Import { alias, .. } => self.declare(alias, Token::IMPORT, id, None),
+            Statement::FnDecl { name, .. } => {
+                if let Some(declaration) = redeclared {
+                    self.violations.push(ConstViolation {
+                        name: name.clone(),
+                        operator: Token::FUNCTION,
+                        assignment: id,
+                        declaration,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        match expr {
+            Expression::Block(_)
+            | Expression::Function { .. }
+            | Expression::MacroLiteral { .. } => {
+                self.scoped(|checker| walk_expression(checker, expr))
+            }
+            Expression::For {
+                variable,
+                iterable,
+                body,
+            } => {
+                self.visit_expression(iterable);
+                self.scoped(|checker| {
+                    checker.bind(variable, None);
+                    checker.visit_expression(body);
+                });
+            }
+            _ => walk_expression(self, expr),
+        }
+        let id = self.ids.expression(expr);
+        if let Expression::Assign {
+            operator, target, ..
+        } = expr
+        {
+            if let Expression::Ident(name) = &**target {
+                if let Some(declaration) = self.const_declaration(name) {
+                    self.violations.push(ConstViolation {
+                        name: name.clone(),
+                        operator: operator.clone(),
+                        assignment: id,
+                        declaration,
+                    });
+                }
+            }
+        }
+    }
+
+    fn visit_parameter(&mut self, param: &Parameter) {
+        if let Some(default) = &param.default {
+            self.visit_expression(default);
+        }
+        self.bind(&param.name, None);
+    }
+
+    fn visit_match_arm(&mut self, arm: &MatchArm) {
+        self.scoped(|checker| {
+            for name in arm.pattern.bindings() {
+                checker.bind(name, None);
+            }
+            walk_match_arm(checker, arm);
+        });
+    }
+}
+
+#[cfg(test)]
+fn check_source(input: &str) -> Vec<String> {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    let mut parser = Parser::new(Lexer::new(input));
+    let program = parser.parse_program();
+    assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+    let spans = parser.span_map();
+    check_program(&program)
+        .iter()
+        .map(|violation| violation.describe(&spans))
+        .collect()
+}
+
+#[test]
+fn test_const_reassignment() {
+    let input = "const limit = 10;\nlimit = 20;\nfn f() { limit += 1; }";
+    assert_eq!(
+        check_source(input),
+        vec![
+            "cannot reassign const limit at 18..28 (declared at 0..17)",
+            "cannot apply += to const limit at 39..49 (declared at 0..17)",
+        ]
+    );
+}
+
+#[test]
+fn test_const_violation_ids() {
+    use crate::lexer::Lexer;
+    use crate::node_id::number_nodes;
+    use crate::parser::Parser;
+    let program = Parser::new(Lexer::new("const x = 1; x *= 2;")).parse_program();
+    let violations = check_program(&program);
+    let nodes = number_nodes(&program);
+    assert_eq!(
+        violations
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>(),
+        vec!["cannot apply *= to const x at #4 (declared at #1)"]
+    );
+    assert_eq!(nodes[violations[0].assignment.0].to_string(), "(x*=2)");
+    assert_eq!(nodes[violations[0].declaration.0].to_string(), "Const x=1");
+}
+
+#[test]
+fn test_assignment_to_shadowed_const() {
+    let input = [
+        "let x = 1; x = 2; x += 3;",
+        "const x = 1; fn f(x) { x = 2; }",
+        "const x = 1; if (y) { let x = 2; x = 3; }",
+        "const x = 1; for (x in xs) { x += 1; }",
+        "const x = 1; match (y) { [x] => x = 2, _ => 0 }",
+        "const x = 1; if (y) { fn x() { 2 }; x = 3; }",
+        "const x = 1; let f = |x| x = 2;",
+        "let x = 1; const x = 2;",
+    ];
+    for src in input.iter() {
+        assert_eq!(check_source(src), Vec::<String>::new(), "{}", src);
+    }
+    let still_const = [
+        "const x = 1; if (z) { let y = 2; x = y; }",
+        "const x = 1; fn f(y = x = 2) { y }",
+        "let x = 1; if (y) { const x = 2; x = 3; }",
+        "const x = 1; match (x) { [y] => x -= y, _ => 0 }",
+        "export const x = 1; fn f() { x = 2; }",
+    ];
+    for src in still_const.iter() {
+        assert_eq!(check_source(src).len(), 1, "{}", src);
+    }
+}
+
+#[test]
+fn test_const_redeclaration() {
+    let input = [
+        (
+            "const x = 1; fn x() { 2 }; x = 3;",
+            vec!["cannot redeclare const x at 13..26 (declared at 0..12)"],
+        ),
+        (
+            "const x = 1; let [a, x] = y;",
+            vec!["cannot redeclare const x at 13..28 (declared at 0..12)"],
+        ),
+        (
+            "const x = 1; const x = 2; x = 3;",
+            vec![
+                "cannot redeclare const x at 13..25 (declared at 0..12)",
+                "cannot reassign const x at 26..31 (declared at 13..25)",
+            ],
+        ),
+        (
+            "const x = 1; import \"./x.monkey\" as x;",
+            vec!["cannot redeclare const x at 13..38 (declared at 0..12)"],
+        ),
+        (
+            "fn f() { const x = 1; let x = 2; }",
+            vec!["cannot redeclare const x at 22..32 (declared at 9..21)"],
+        ),
+    ];
+    for (src, expected) in input.iter() {
+        assert_eq!(&check_source(src), expected, "{}", src);
+    }
+}
@@ -0,0 +1,1206 @@
+//! 具象構文木
+//!
+//! 空白や括弧・セミコロンも含めて入力をそのまま保持する木。
+//! 位置を持たない共有可能な「緑」の木と、親と絶対位置を辿れる「赤」の木の2層からなる。
+//! 構文解析器が記録した節点の範囲（[`SyntaxEvent`]）とトークン列から組み立てる。
+use crate::ast::{Argument, Expression, MatchArm, Parameter, Pattern, Statement, TypeExpr};
+use crate::lexer::{doc_comment, Lexer};
+use crate::operator::Associativity;
+use crate::parser::{ParseError, Parser};
+use crate::token::{Span, Token};
+use std::fmt;
+use std::rc::Rc;
+
+/// 節点の種類
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum SyntaxKind {
+    /// 入力全体
+    Root,
+    /// 読み飛ばした誤りのある文
+    Error,
+    LetStmt,
+    ConstStmt,
+    ReturnStmt,
+    ExprStmt,
+    FnDecl,
+    BreakStmt,
+    ContinueStmt,
+    OperatorDecl,
+    ImportStmt,
+    ExportStmt,
+    IntLit,
+    BoolLit,
+    StrLit,
+    Ident,
+    ParenExpr,
+    IfExpr,
+    PrefixExpr,
+    InfixExpr,
+    PostfixExpr,
+    BlockExpr,
+    FnExpr,
+    MacroExpr,
+    WhileExpr,
+    ForExpr,
+    MatchExpr,
+    CallExpr,
+    TernaryExpr,
+    PipeExpr,
+    AssignExpr,
+    MemberExpr,
+    Param,
+    Arg,
+    MatchArm,
+    WildcardPat,
+    IdentPat,
+    LiteralPat,
+    ArrayPat,
+    HashPat,
+    NamedType,
+    ArrayType,
+    HashType,
+    FnType,
+    OptionalType,
+    /// `(fn() -> int)?` の括弧（構文木には残らない）
+    ParenType,
+}
+
+impl SyntaxKind {
+    pub fn of_statement(stmt: &Statement) -> SyntaxKind {
+        match stmt {
+            Statement::Let { .. } => SyntaxKind::LetStmt,
+            Statement::Const { .. } => SyntaxKind::ConstStmt,
+            Statement::Return(_) => SyntaxKind::ReturnStmt,
+            Statement::Expr(_) => SyntaxKind::ExprStmt,
+            Statement::FnDecl { .. } => SyntaxKind::FnDecl,
+            Statement::Break => SyntaxKind::BreakStmt,
+            Statement::Continue => SyntaxKind::ContinueStmt,
+            Statement::OperatorDecl { .. } => SyntaxKind::OperatorDecl,
+            Statement::Import { .. } => SyntaxKind::ImportStmt,
+            Statement::Export(_) => SyntaxKind::ExportStmt,
+        }
+    }
+
+    pub fn of_expression(expr: &Expression) -> SyntaxKind {
+        match expr {
+            Expression::Int(_) => SyntaxKind::IntLit,
+            Expression::Boolean(_) => SyntaxKind::BoolLit,
+            Expression::Str(_) => SyntaxKind::StrLit,
+            Expression::Ident(_) => SyntaxKind::Ident,
+            Expression::Grouped(_) => SyntaxKind::ParenExpr,
+            Expression::If { .. } => SyntaxKind::IfExpr,
+            Expression::Prefix { .. } => SyntaxKind::PrefixExpr,
+            Expression::Infix { .. } => SyntaxKind::InfixExpr,
+            Expression::Postfix { .. } => SyntaxKind::PostfixExpr,
+            Expression::Block(_) => SyntaxKind::BlockExpr,
+            Expression::Function { .. } => SyntaxKind::FnExpr,
+            Expression::MacroLiteral { .. } => SyntaxKind::MacroExpr,
+            Expression::While { .. } => SyntaxKind::WhileExpr,
+            Expression::For { .. } => SyntaxKind::ForExpr,
+            Expression::Match { .. } => SyntaxKind::MatchExpr,
+            Expression::Call { .. } => SyntaxKind::CallExpr,
+            Expression::Ternary { .. } => SyntaxKind::TernaryExpr,
+            Expression::Pipe { .. } => SyntaxKind::PipeExpr,
+            Expression::Assign { .. } => SyntaxKind::AssignExpr,
+            Expression::Member { .. } => SyntaxKind::MemberExpr,
+        }
+    }
+
+    pub fn of_pattern(pattern: &Pattern) -> SyntaxKind {
+        match pattern {
+            Pattern::Wildcard => SyntaxKind::WildcardPat,
+            Pattern::Ident(_) => SyntaxKind::IdentPat,
+            Pattern::Int(_) | Pattern::Boolean(_) | Pattern::Str(_) => SyntaxKind::LiteralPat,
+            Pattern::Array { .. } => SyntaxKind::ArrayPat,
+            Pattern::Hash(_) => SyntaxKind::HashPat,
+        }
+    }
+
+    pub fn of_type(ty: &TypeExpr) -> SyntaxKind {
+        match ty {
+            TypeExpr::Named(_) => SyntaxKind::NamedType,
+            TypeExpr::Array(_) => SyntaxKind::ArrayType,
+            TypeExpr::Hash { .. } => SyntaxKind::HashType,
+            TypeExpr::Function { .. } => SyntaxKind::FnType,
+            TypeExpr::Optional(_) => SyntaxKind::OptionalType,
+        }
+    }
+
+    pub fn is_statement(self) -> bool {
+        matches!(
+            self,
+            SyntaxKind::LetStmt
+                | SyntaxKind::ConstStmt
+                | SyntaxKind::ReturnStmt
+                | SyntaxKind::ExprStmt
+                | SyntaxKind::FnDecl
+                | SyntaxKind::BreakStmt
+                | SyntaxKind::ContinueStmt
+                | SyntaxKind::OperatorDecl
+                | SyntaxKind::ImportStmt
+                | SyntaxKind::ExportStmt
+        )
+    }
+
+    pub fn is_expression(self) -> bool {
+        matches!(
+            self,
+            SyntaxKind::IntLit
+                | SyntaxKind::BoolLit
+                | SyntaxKind::StrLit
+                | SyntaxKind::Ident
+                | SyntaxKind::ParenExpr
+                | SyntaxKind::IfExpr
+                | SyntaxKind::PrefixExpr
+                | SyntaxKind::InfixExpr
+                | SyntaxKind::PostfixExpr
+                | SyntaxKind::BlockExpr
+                | SyntaxKind::FnExpr
+                | SyntaxKind::MacroExpr
+                | SyntaxKind::WhileExpr
+                | SyntaxKind::ForExpr
+                | SyntaxKind::MatchExpr
+                | SyntaxKind::CallExpr
+                | SyntaxKind::TernaryExpr
+                | SyntaxKind::PipeExpr
+                | SyntaxKind::AssignExpr
+                | SyntaxKind::MemberExpr
+        )
+    }
+
+    pub fn is_pattern(self) -> bool {
+        matches!(
+            self,
+            SyntaxKind::WildcardPat
+                | SyntaxKind::IdentPat
+                | SyntaxKind::LiteralPat
+                | SyntaxKind::ArrayPat
+                | SyntaxKind::HashPat
+        )
+    }
+
+    pub fn is_type(self) -> bool {
+        matches!(
+            self,
+            SyntaxKind::NamedType
+                | SyntaxKind::ArrayType
+                | SyntaxKind::HashType
+                | SyntaxKind::FnType
+                | SyntaxKind::OptionalType
+                | SyntaxKind::ParenType
+        )
+    }
+}
+
+/// 構文解析器が読み終えた節点1つ分の記録
+///
+/// `first`・`last` は節点の最初と最後のトークンの番号。子の記録は親より先に並ぶ。
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct SyntaxEvent {
+    pub kind: SyntaxKind,
+    pub first: usize,
+    pub last: usize,
+}
+
+/// 緑の木の葉（トークンまたはトークン間の空白）
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct GreenToken {
+    /// 空白なら `None`
+    token: Option<Token>,
+    text: String,
+}
+
+impl GreenToken {
+    pub fn new(token: Option<Token>, text: &str) -> GreenToken {
+        GreenToken {
+            token,
+            text: text.to_string(),
+        }
+    }
+
+    pub fn token(&self) -> Option<&Token> {
+        self.token.as_ref()
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_trivia(&self) -> bool {
+        self.token.is_none()
+    }
+
+    /// 文字数
+    pub fn len(&self) -> usize {
+        self.text.chars().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum GreenElement {
+    Node(Rc<GreenNode>),
+    Token(Rc<GreenToken>),
+}
+
+impl GreenElement {
+    fn len(&self) -> usize {
+        match self {
+            GreenElement::Node(node) => node.len(),
+            GreenElement::Token(token) => token.len(),
+        }
+    }
+}
+
+/// 緑の木の節点（位置を持たず、同じ形の部分木は共有できる）
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct GreenNode {
+    kind: SyntaxKind,
+    len: usize,
+    children: Vec<GreenElement>,
+}
+
+impl GreenNode {
+    pub fn new(kind: SyntaxKind, children: Vec<GreenElement>) -> GreenNode {
+        let len = children.iter().map(GreenElement::len).sum();
+        GreenNode {
+            kind,
+            len,
+            children,
+        }
+    }
+
+    pub fn kind(&self) -> SyntaxKind {
+        self.kind
+    }
+
+    /// 文字数
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn children(&self) -> &[GreenElement] {
+        &self.children
+    }
+
+    fn write_text(&self, out: &mut String) {
+        for child in &self.children {
+            match child {
+                GreenElement::Node(node) => node.write_text(out),
+                GreenElement::Token(token) => out.push_str(token.text()),
+            }
+        }
+    }
+}
+
+/// 深い木でも再帰せずに解放する（子の節点は最後の参照になったものから順に取り出す）
+impl Drop for GreenNode {
+    fn drop(&mut self) {
+        let mut nodes: Vec<Rc<GreenNode>> = vec![];
+        let take_nodes = |children: &mut Vec<GreenElement>, nodes: &mut Vec<Rc<GreenNode>>| {
+            nodes.extend(children.drain(..).filter_map(|child| match child {
+                GreenElement::Node(node) => Some(node),
+                GreenElement::Token(_) => None,
+            }))
+        };
+        take_nodes(&mut self.children, &mut nodes);
+        while let Some(node) = nodes.pop() {
+            if let Ok(mut node) = Rc::try_unwrap(node) {
+                take_nodes(&mut node.children, &mut nodes);
+            }
+        }
+    }
+}
+
+/// 赤の木の節点（親と入力中の位置が分かる）
+#[derive(Clone)]
+pub struct SyntaxNode(Rc<NodeData>);
+
+struct NodeData {
+    green: Rc<GreenNode>,
+    parent: Option<SyntaxNode>,
+    offset: usize,
+}
+
+impl SyntaxNode {
+    pub fn new_root(green: Rc<GreenNode>) -> SyntaxNode {
+        SyntaxNode(Rc::new(NodeData {
+            green,
+            parent: None,
+            offset: 0,
+        }))
+    }
+
+    pub fn kind(&self) -> SyntaxKind {
+        self.0.green.kind()
+    }
+
+    pub fn green(&self) -> &Rc<GreenNode> {
+        &self.0.green
+    }
+
+    pub fn parent(&self) -> Option<SyntaxNode> {
+        self.0.parent.clone()
+    }
+
+    /// 親を根まで辿る（自身は含まない）
+    pub fn ancestors(&self) -> Vec<SyntaxNode> {
+        let mut ancestors = vec![];
+        let mut node = self.parent();
+        while let Some(parent) = node {
+            node = parent.parent();
+            ancestors.push(parent);
+        }
+        ancestors
+    }
+
+    pub fn text_range(&self) -> Span {
+        Span::new(self.0.offset, self.0.offset + self.0.green.len())
+    }
+
+    /// 節点が覆う入力の文字列（空白も含む）
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        self.0.green.write_text(&mut text);
+        text
+    }
+
+    pub fn children_with_tokens(&self) -> Vec<SyntaxElement> {
+        let mut offset = self.0.offset;
+        let mut elements = vec![];
+        for child in self.0.green.children() {
+            elements.push(match child {
+                GreenElement::Node(node) => SyntaxElement::Node(SyntaxNode(Rc::new(NodeData {
+                    green: node.clone(),
+                    parent: Some(self.clone()),
+                    offset,
+                }))),
+                GreenElement::Token(token) => SyntaxElement::Token(SyntaxToken {
+                    green: token.clone(),
+                    parent: self.clone(),
+                    offset,
+                }),
+            });
+            offset += child.len();
+        }
+        elements
+    }
+
+    pub fn children(&self) -> Vec<SyntaxNode> {
+        self.children_with_tokens()
+            .into_iter()
+            .filter_map(|element| match element {
+                SyntaxElement::Node(node) => Some(node),
+                SyntaxElement::Token(_) => None,
+            })
+            .collect()
+    }
+
+    /// 直下の空白以外のトークン
+    pub fn tokens(&self) -> Vec<SyntaxToken> {
+        self.children_with_tokens()
+            .into_iter()
+            .filter_map(|element| match element {
+                SyntaxElement::Token(token) if !token.is_trivia() => Some(token),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// 自身と子孫の節点（行きがけ順）
+    pub fn descendants(&self) -> Vec<SyntaxNode> {
+        let mut nodes = vec![self.clone()];
+        for child in self.children() {
+            nodes.extend(child.descendants());
+        }
+        nodes
+    }
+
+    /// 種類と範囲を字下げして並べた木の表示
+    pub fn debug_tree(&self) -> String {
+        let mut out = String::new();
+        self.write_tree(0, &mut out);
+        out
+    }
+
+    fn write_tree(&self, depth: usize, out: &mut String) {
+        out.push_str(&format!(
+            "{}{:?}@{}\n",
+            "  ".repeat(depth),
+            self.kind(),
+            self.text_range()
+        ));
+        for element in self.children_with_tokens() {
+            match element {
+                SyntaxElement::Node(node) => node.write_tree(depth + 1, out),
+                SyntaxElement::Token(token) => out.push_str(&format!(
+                    "{}{:?}@{}\n",
+                    "  ".repeat(depth + 1),
+                    token.text(),
+                    token.text_range()
+                )),
+            }
+        }
+    }
+}
+
+impl PartialEq for SyntaxNode {
+    fn eq(&self, other: &SyntaxNode) -> bool {
+        Rc::ptr_eq(&self.0.green, &other.0.green) && self.0.offset == other.0.offset
+    }
+}
+
+impl Eq for SyntaxNode {}
+
+impl fmt::Debug for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}@{}", self.kind(), self.text_range())
+    }
+}
+
+impl fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text())
+    }
+}
+
+/// 赤の木の葉
+#[derive(Debug, Clone)]
+pub struct SyntaxToken {
+    green: Rc<GreenToken>,
+    parent: SyntaxNode,
+    offset: usize,
+}
+
+impl SyntaxToken {
+    pub fn token(&self) -> Option<&Token> {
+        self.green.token()
+    }
+
+    pub fn text(&self) -> &str {
+        self.green.text()
+    }
+
+    pub fn is_trivia(&self) -> bool {
+        self.green.is_trivia()
+    }
+
+    pub fn text_range(&self) -> Span {
+        Span::new(self.offset, self.offset + self.green.len())
+    }
+
+    pub fn parent(&self) -> SyntaxNode {
+        self.parent.clone()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+/// トークン列と節点の記録から具象構文木を組み立てる
+///
+/// トークンの間の文字列は空白として、両側のトークンを共に含む最も内側の節点に置く。
+pub fn build_tree(input: &str, tokens: &[(Token, Span)], events: &[SyntaxEvent]) -> SyntaxNode {
+    let chars: Vec<char> = input.chars().collect();
+    let text = |start: usize, end: usize| -> String {
+        chars[start.min(chars.len())..end.min(chars.len())]
+            .iter()
+            .collect()
+    };
+    let tokens: Vec<&(Token, Span)> = tokens
+        .iter()
+        .take_while(|(token, _)| token != &Token::EOF)
+        .collect();
+    let mut leaves = vec![];
+    let mut trivia = vec![];
+    let mut prev_end = 0;
+    for (token, span) in &tokens {
+        trivia.push(GreenToken::new(None, &text(prev_end, span.start)));
+        leaves.push(GreenToken::new(
+            Some(token.clone()),
+            &text(span.start, span.end),
+        ));
+        prev_end = span.end;
+    }
+    let builder = TreeBuilder { leaves, trivia };
+
+    let mut stack: Vec<(usize, usize, GreenNode)> = vec![];
+    for event in events {
+        if event.first > event.last || event.last >= tokens.len() {
+            continue;
+        }
+        let mut children = vec![];
+        while let Some((first, _, _)) = stack.last() {
+            if *first < event.first {
+                break;
+            }
+            children.push(stack.pop().unwrap());
+        }
+        children.reverse();
+        let node = builder.node(event.kind, event.first, event.last, children);
+        stack.push((event.first, event.last, node));
+    }
+
+    let mut root = vec![];
+    if !tokens.is_empty() {
+        push_trivia(&mut root, &builder.trivia[0]);
+        let mut node = builder.node(SyntaxKind::Root, 0, tokens.len() - 1, stack);
+        root.append(&mut node.children);
+    }
+    push_trivia(
+        &mut root,
+        &GreenToken::new(None, &text(prev_end, chars.len())),
+    );
+    SyntaxNode::new_root(Rc::new(GreenNode::new(SyntaxKind::Root, root)))
+}
+
+struct TreeBuilder {
+    leaves: Vec<GreenToken>,
+    /// `trivia[i]` は `i` 番目のトークンの直前の空白
+    trivia: Vec<GreenToken>,
+}
+
+impl TreeBuilder {
+    fn node(
+        &self,
+        kind: SyntaxKind,
+        first: usize,
+        last: usize,
+        children: Vec<(usize, usize, GreenNode)>,
+    ) -> GreenNode {
+        let mut elements = vec![];
+        let mut children = children.into_iter().peekable();
+        let mut index = first;
+        while index <= last {
+            if index > first {
+                push_trivia(&mut elements, &self.trivia[index]);
+            }
+            match children.peek() {
+                Some((child_first, _, _)) if *child_first == index => {
+                    let (_, child_last, child) = children.next().unwrap();
+                    elements.push(GreenElement::Node(Rc::new(child)));
+                    index = child_last + 1;
+                }
+                _ => {
+                    elements.push(GreenElement::Token(Rc::new(self.leaves[index].clone())));
+                    index += 1;
+                }
+            }
+        }
+        GreenNode::new(kind, elements)
+    }
+}
+
+fn push_trivia(elements: &mut Vec<GreenElement>, trivia: &GreenToken) {
+    if !trivia.is_empty() {
+        elements.push(GreenElement::Token(Rc::new(trivia.clone())));
+    }
+}
+
+/// 文字列を構文解析した結果
+pub struct Parse {
+    pub root: SyntaxNode,
+    pub program: Vec<Statement>,
+    pub errors: Vec<ParseError>,
+}
+
+impl Parse {
+    pub fn source_file(&self) -> SourceFile {
+        SourceFile(self.root.clone())
+    }
+}
+
+/// 抽象構文木と具象構文木を同時に作る
+pub fn parse(input: &str) -> Parse {
+    let mut parser = Parser::new(Lexer::new(input));
+    let program = parser.parse_program();
+    let root = build_tree(input, parser.tokens(), parser.syntax_events());
+    Parse {
+        root,
+        program,
+        errors: parser.errors().to_vec(),
+    }
+}
+
+/// 具象構文木の節点に型を付けた見方
+pub trait AstNode: Sized {
+    fn cast(node: SyntaxNode) -> Option<Self>;
+    fn syntax(&self) -> &SyntaxNode;
+}
+
+/// 入力全体
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFile(SyntaxNode);
+
+/// 文の節点（`Statement` に対応する）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stmt(SyntaxNode);
+
+/// 式の節点（`Expression` に対応する）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expr(SyntaxNode);
+
+/// パターンの節点（`Pattern` に対応する）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pat(SyntaxNode);
+
+/// 型の注釈の節点（`TypeExpr` に対応する）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ty(SyntaxNode);
+
+impl AstNode for SourceFile {
+    fn cast(node: SyntaxNode) -> Option<SourceFile> {
+        match node.kind() {
+            SyntaxKind::Root => Some(SourceFile(node)),
+            _ => None,
+        }
+    }
+
+    fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+}
+
+impl AstNode for Stmt {
+    fn cast(node: SyntaxNode) -> Option<Stmt> {
+        match node.kind() {
+            kind if kind.is_statement() => Some(Stmt(node)),
+            _ => None,
+        }
+    }
+
+    fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+}
+
+impl AstNode for Expr {
+    fn cast(node: SyntaxNode) -> Option<Expr> {
+        match node.kind() {
+            kind if kind.is_expression() => Some(Expr(node)),
+            _ => None,
+        }
+    }
+
+    fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+}
+
+impl AstNode for Pat {
+    fn cast(node: SyntaxNode) -> Option<Pat> {
+        match node.kind() {
+            kind if kind.is_pattern() => Some(Pat(node)),
+            _ => None,
+        }
+    }
+
+    fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+}
+
+impl AstNode for Ty {
+    fn cast(node: SyntaxNode) -> Option<Ty> {
+        match node.kind() {
+            kind if kind.is_type() => Some(Ty(node)),
+            _ => None,
+        }
+    }
+
+    fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+}
+
+impl SourceFile {
+    /// 誤りのない文（誤りのある部分は `SyntaxKind::Error` の節点として残る）
+    pub fn statements(&self) -> Vec<Stmt> {
+        self.0
+            .children()
+            .into_iter()
+            .filter_map(Stmt::cast)
+            .collect()
+    }
+
+    pub fn to_ast(&self) -> Vec<Statement> {
+        self.statements().iter().filter_map(Stmt::to_ast).collect()
+    }
+}
+
+impl Stmt {
+    /// `let x` や `const x`、`fn name` で束縛する名前（分解する `let` では `None`）
+    pub fn name(&self) -> Option<String> {
+        match self.0.kind() {
+            SyntaxKind::LetStmt => match self.pattern()?.0.kind() {
+                SyntaxKind::IdentPat => ident_token(&self.pattern()?.0),
+                _ => None,
+            },
+            SyntaxKind::ConstStmt | SyntaxKind::FnDecl => ident_token(&self.0),
+            _ => None,
+        }
+    }
+
+    /// `let` の左辺のパターン
+    pub fn pattern(&self) -> Option<Pat> {
+        self.0.children().into_iter().find_map(Pat::cast)
+    }
+
+    /// `let`・`const` の型の注釈か関数宣言の戻り値の型
+    pub fn type_annotation(&self) -> Option<Ty> {
+        child_type(&self.0)
+    }
+
+    /// 文の直前の空白にある `///` コメント
+    pub fn doc_comment(&self) -> Option<String> {
+        let parent = self.0.parent()?;
+        let elements = parent.children_with_tokens();
+        let index = elements.iter().position(|element| match element {
+            SyntaxElement::Node(node) => node == &self.0,
+            SyntaxElement::Token(_) => false,
+        })?;
+        match &elements[..index] {
+            [.., SyntaxElement::Token(token)] if token.is_trivia() => doc_comment(token.text()),
+            _ => None,
+        }
+    }
+
+    /// 文に直接含まれる式
+    pub fn expressions(&self) -> Vec<Expr> {
+        child_exprs(&self.0)
+    }
+
+    pub fn to_ast(&self) -> Option<Statement> {
+        let node = &self.0;
+        Some(match node.kind() {
+            SyntaxKind::LetStmt => Statement::Let {
+                pattern: self.pattern()?.to_ast()?,
+                annotation: lower_type(node)?,
+                expr: first_expr(node)?,
+                doc: self.doc_comment(),
+            },
+            SyntaxKind::ConstStmt => Statement::Const {
+                name: ident_token(node)?,
+                annotation: lower_type(node)?,
+                expr: first_expr(node)?,
+                doc: self.doc_comment(),
+            },
+            SyntaxKind::ReturnStmt => Statement::Return(first_expr(node)?),
+            SyntaxKind::ExprStmt => Statement::Expr(first_expr(node)?),
+            SyntaxKind::FnDecl => Statement::FnDecl {
+                name: ident_token(node)?,
+                parameters: lower_parameters(node)?,
+                return_type: lower_type(node)?,
+                body: child_exprs(node).last()?.to_ast()?,
+                doc: self.doc_comment(),
+            },
+            SyntaxKind::BreakStmt => Statement::Break,
+            SyntaxKind::ContinueStmt => Statement::Continue,
+            SyntaxKind::OperatorDecl => match token_kinds(node).as_slice() {
+                [keyword, Token::INT(precedence), Token::OPERATOR(operator), ..] => {
+                    Statement::OperatorDecl {
+                        associativity: match keyword {
+                            Token::INFIXL => Associativity::Left,
+                            Token::INFIXR => Associativity::Right,
+                            _ => Associativity::None,
+                        },
+                        precedence: *precedence as u8,
+                        operator: operator.clone(),
+                    }
+                }
+                _ => return None,
+            },
+            SyntaxKind::ImportStmt => match token_kinds(node).as_slice() {
+                [Token::IMPORT, Token::STRING(path), Token::AS, Token::IDENT(alias), ..] => {
+                    Statement::Import {
+                        path: path.clone(),
+                        alias: alias.clone(),
+                    }
+                }
+                _ => return None,
+            },
+            SyntaxKind::ExportStmt => {
+                let mut stmt = node.children().into_iter().find_map(Stmt::cast)?.to_ast()?;
+                // `///` コメントは `export` の前に書く
+                if let Some(doc @ None) = stmt.doc_mut() {
+                    *doc = self.doc_comment();
+                }
+                Statement::Export(Box::new(stmt))
+            }
+            _ => return None,
+        })
+    }
+}
+
+impl Expr {
+    pub fn kind(&self) -> SyntaxKind {
+        self.0.kind()
+    }
+
+    /// 前置・中置・後置演算子と代入演算子
+    pub fn operator(&self) -> Option<Token> {
+        match self.0.kind() {
+            SyntaxKind::PrefixExpr
+            | SyntaxKind::InfixExpr
+            | SyntaxKind::PostfixExpr
+            | SyntaxKind::AssignExpr => token_kinds(&self.0).into_iter().next(),
+            _ => None,
+        }
+    }
+
+    /// 直下の部分式（ブロック内の文や引数の中身は含まない）
+    pub fn operands(&self) -> Vec<Expr> {
+        child_exprs(&self.0)
+    }
+
+    pub fn to_ast(&self) -> Option<Expression> {
+        let node = &self.0;
+        let tokens = token_kinds(node);
+        let exprs = child_exprs(node);
+        let lower =
+            |i: usize| -> Option<Box<Expression>> { Some(Box::new(exprs.get(i)?.to_ast()?)) };
+        Some(match node.kind() {
+            SyntaxKind::IntLit => match tokens.first()? {
+                Token::INT(val) => Expression::Int(*val),
+                _ => return None,
+            },
+            SyntaxKind::BoolLit => Expression::Boolean(tokens.first()? == &Token::TRUE),
+            SyntaxKind::StrLit => match tokens.first()? {
+                Token::STRING(val) => Expression::Str(val.clone()),
+                _ => return None,
+            },
+            SyntaxKind::Ident => Expression::Ident(ident_token(node)?),
+            SyntaxKind::ParenExpr => Expression::Grouped(lower(0)?),
+            SyntaxKind::IfExpr => Expression::If {
+                condition: lower(0)?,
+                consequence: lower(1)?,
+                alternative: match exprs.get(2) {
+                    Some(alt) => Some(Box::new(alt.to_ast()?)),
+                    None => None,
+                },
+            },
+            SyntaxKind::PrefixExpr => Expression::Prefix {
+                operator: tokens.first()?.clone(),
+                right: lower(0)?,
+            },
+            SyntaxKind::InfixExpr => Expression::Infix {
+                operator: tokens.first()?.clone(),
+                left: lower(0)?,
+                right: lower(1)?,
+            },
+            SyntaxKind::PostfixExpr => Expression::Postfix {
+                operator: tokens.first()?.clone(),
+                left: lower(0)?,
+            },
+            SyntaxKind::BlockExpr => Expression::Block(
+                node.children()
+                    .into_iter()
+                    .filter_map(Stmt::cast)
+                    .filter_map(|stmt| stmt.to_ast())
+                    .collect(),
+            ),
+            SyntaxKind::FnExpr => Expression::Function {
+                parameters: lower_parameters(node)?,
+                return_type: lower_type(node)?,
+                body: lower(exprs.len().checked_sub(1)?)?,
+            },
+            SyntaxKind::MacroExpr => Expression::MacroLiteral {
+                parameters: lower_parameters(node)?,
+                body: lower(exprs.len().checked_sub(1)?)?,
+            },
+            SyntaxKind::WhileExpr => Expression::While {
+                condition: lower(0)?,
+                body: lower(1)?,
+            },
+            SyntaxKind::ForExpr => Expression::For {
+                variable: ident_token(node)?,
+                iterable: lower(0)?,
+                body: lower(1)?,
+            },
+            SyntaxKind::MatchExpr => Expression::Match {
+                subject: lower(0)?,
+                arms: node
+                    .children()
+                    .iter()
+                    .filter(|child| child.kind() == SyntaxKind::MatchArm)
+                    .map(lower_match_arm)
+                    .collect::<Option<Vec<MatchArm>>>()?,
+            },
+            SyntaxKind::CallExpr => Expression::Call {
+                function: lower(0)?,
+                arguments: node
+                    .children()
+                    .iter()
+                    .filter(|child| child.kind() == SyntaxKind::Arg)
+                    .map(lower_argument)
+                    .collect::<Option<Vec<Argument>>>()?,
+            },
+            SyntaxKind::TernaryExpr => Expression::Ternary {
+                condition: lower(0)?,
+                consequence: lower(1)?,
+                alternative: lower(2)?,
+            },
+            SyntaxKind::PipeExpr => Expression::Pipe {
+                left: lower(0)?,
+                right: lower(1)?,
+            },
+            SyntaxKind::AssignExpr => Expression::Assign {
+                operator: tokens.first()?.clone(),
+                target: lower(0)?,
+                value: lower(1)?,
+            },
+            SyntaxKind::MemberExpr => Expression::Member {
+                object: lower(0)?,
+                name: ident_token(node)?,
+            },
+            _ => return None,
+        })
+    }
+}
+
+impl Pat {
+    pub fn to_ast(&self) -> Option<Pattern> {
+        let node = &self.0;
+        let patterns = || -> Option<Vec<Pattern>> {
+            node.children()
+                .into_iter()
+                .filter_map(Pat::cast)
+                .map(|pat| pat.to_ast())
+                .collect()
+        };
+        Some(match node.kind() {
+            SyntaxKind::WildcardPat => Pattern::Wildcard,
+            SyntaxKind::IdentPat => Pattern::Ident(ident_token(node)?),
+            SyntaxKind::LiteralPat => match token_kinds(node).as_slice() {
+                [Token::MINUS, Token::INT(val)] => Pattern::Int(-val),
+                [Token::INT(val)] => Pattern::Int(*val),
+                [Token::TRUE] => Pattern::Boolean(true),
+                [Token::FALSE] => Pattern::Boolean(false),
+                [Token::STRING(val)] => Pattern::Str(val.clone()),
+                _ => return None,
+            },
+            SyntaxKind::ArrayPat => Pattern::Array {
+                elements: patterns()?,
+                rest: match token_kinds(node).as_slice() {
+                    [.., Token::ELLIPSIS, Token::IDENT(rest), Token::RBRACKET] => {
+                        Some(rest.clone())
+                    }
+                    _ => None,
+                },
+            },
+            SyntaxKind::HashPat => {
+                let mut patterns = patterns()?.into_iter();
+                let mut pairs = vec![];
+                while let (Some(key), Some(value)) = (patterns.next(), patterns.next()) {
+                    pairs.push((key, value));
+                }
+                Pattern::Hash(pairs)
+            }
+            _ => return None,
+        })
+    }
+}
+
+impl Ty {
+    pub fn to_ast(&self) -> Option<TypeExpr> {
+        let node = &self.0;
+        let types = node
+            .children()
+            .into_iter()
+            .filter_map(Ty::cast)
+            .map(|ty| ty.to_ast())
+            .collect::<Option<Vec<TypeExpr>>>()?;
+        let mut types = types.into_iter();
+        let mut next = || types.next().map(Box::new);
+        Some(match node.kind() {
+            SyntaxKind::NamedType => TypeExpr::Named(ident_token(node)?),
+            SyntaxKind::ArrayType => TypeExpr::Array(next()?),
+            SyntaxKind::HashType => TypeExpr::Hash {
+                key: next()?,
+                value: next()?,
+            },
+            SyntaxKind::FnType => {
+                let mut parameters: Vec<TypeExpr> =
+                    std::iter::from_fn(|| next().map(|ty| *ty)).collect();
+                TypeExpr::Function {
+                    return_type: Box::new(parameters.pop()?),
+                    parameters,
+                }
+            }
+            SyntaxKind::OptionalType => TypeExpr::Optional(next()?),
+            SyntaxKind::ParenType => *next()?,
+            _ => return None,
+        })
+    }
+}
+
+fn token_kinds(node: &SyntaxNode) -> Vec<Token> {
+    node.tokens()
+        .iter()
+        .filter_map(|token| token.token().cloned())
+        .collect()
+}
+
+fn ident_token(node: &SyntaxNode) -> Option<String> {
+    token_kinds(node).into_iter().find_map(|token| match token {
+        Token::IDENT(name) => Some(name),
+        _ => None,
+    })
+}
+
+fn child_exprs(node: &SyntaxNode) -> Vec<Expr> {
+    node.children().into_iter().filter_map(Expr::cast).collect()
+}
+
+fn first_expr(node: &SyntaxNode) -> Option<Expression> {
+    child_exprs(node).first()?.to_ast()
+}
+
+/// 直下の型の節点（仮引数の中の型は含まない）
+fn child_type(node: &SyntaxNode) -> Option<Ty> {
+    node.children().into_iter().find_map(Ty::cast)
+}
+
+/// 直下の型の注釈（なければ `Some(None)`、下ろせなければ `None`）
+fn lower_type(node: &SyntaxNode) -> Option<Option<TypeExpr>> {
+    match child_type(node) {
+        Some(ty) => Some(Some(ty.to_ast()?)),
+        None => Some(None),
+    }
+}
+
+fn lower_parameters(node: &SyntaxNode) -> Option<Vec<Parameter>> {
+    node.children()
+        .iter()
+        .filter(|child| child.kind() == SyntaxKind::Param)
+        .map(|param| {
+            Some(Parameter {
+                name: ident_token(param)?,
+                annotation: lower_type(param)?,
+                default: match child_exprs(param).first() {
+                    Some(default) => Some(default.to_ast()?),
+                    None => None,
+                },
+                rest: token_kinds(param).contains(&Token::ELLIPSIS),
+            })
+        })
+        .collect()
+}
+
+fn lower_argument(node: &SyntaxNode) -> Option<Argument> {
+    let value = first_expr(node)?;
+    Some(match token_kinds(node).as_slice() {
+        [Token::IDENT(name), Token::COLON, ..] => Argument::Keyword(name.clone(), value),
+        _ => Argument::Positional(value),
+    })
+}
+
+fn lower_match_arm(node: &SyntaxNode) -> Option<MatchArm> {
+    let pattern = node.children().into_iter().find_map(Pat::cast)?.to_ast()?;
+    let exprs = child_exprs(node);
+    let (guard, body) = match exprs.as_slice() {
+        [guard, body] => (Some(guard.to_ast()?), body.to_ast()?),
+        [body] => (None, body.to_ast()?),
+        _ => return None,
+    };
+    Some(MatchArm {
+        pattern,
+        guard,
+        body,
+    })
+}
+
+#[test]
+fn test_lossless_text() {
+    let input = [
+        "let x = 5;",
+        "  let add = fn(a, b = 1) {\n    a + b;\n  } ;\n\n",
+        "match ( x ) { [a, _] if a > 0 => a , {\"k\": -1} => 0, _ => { 1 } }",
+        "(x, y) => x |> f(y: 2)   ",
+        "let = ; ) }} x + ;",
+        "\"unterminated",
+        "",
+    ];
+    for src in input.iter() {
+        let parse = parse(src);
+        assert_eq!(parse.root.text(), *src);
+        assert_eq!(parse.root.text_range(), Span::new(0, src.chars().count()));
+    }
+}
+
+#[test]
+fn test_cst_shape() {
+    let parse = parse("let x = (1 + 2) * 3;");
+    let stmt = parse.root.children().remove(0);
+    assert_eq!(stmt.kind(), SyntaxKind::LetStmt);
+    assert_eq!(stmt.children()[0].kind(), SyntaxKind::IdentPat);
+    let expr = stmt.children().remove(1);
+    assert_eq!(expr.kind(), SyntaxKind::InfixExpr);
+    assert_eq!(expr.text(), "(1 + 2) * 3");
+    assert_eq!(expr.text_range(), Span::new(8, 19));
+    let paren = expr.children().remove(0);
+    assert_eq!(paren.kind(), SyntaxKind::ParenExpr);
+    assert_eq!(paren.parent(), Some(expr.clone()));
+    assert_eq!(paren.ancestors().len(), 3);
+    assert_eq!(
+        Stmt::cast(stmt.clone()).unwrap().name(),
+        Some("x".to_string())
+    );
+    assert_eq!(Expr::cast(expr).unwrap().operator(), Some(Token::ASTERISK));
+    let texts: Vec<String> = stmt.tokens().iter().map(|t| t.text().to_string()).collect();
+    assert_eq!(texts, vec!["let", "=", ";"]);
+}
+
+#[test]
+fn test_typed_view_to_ast() {
+    let input = [
+        "let x = 5; return x;",
+        "fn f(a, b = 10, ...rest) { a + b } f(1, b: 2);",
+        "if (a) { 1 } else if (b) { 2 } else { -3 }",
+        "while (x < 10) { x; break; } for (i in xs) { continue; }",
+        "match (x) { [a, _] if a > 0 => a, {\"k\": -1} => 0, _ => { 1 } }",
+        "(x, y) => x + y; |a, b = 2| a ** b; x |> f(1); c ? a : b;",
+        "infixl 65 <+>; a <+> b;",
+        "/// Max.\nconst n = 3; x = y += n * 2;",
+        "import \"./m.monkey\" as m; /// Doc.\nexport fn f() { m.g(1).h } export let x = 1;",
+        "let a = 1; let = 2; let b = a;",
+        "let x: int? = 5; let [a, b]: [{string: int}] = xs; const n: [int] = ys;",
+        "fn f(a: int, b: [string] = xs, ...c: [(fn(int) -> bool)?]) -> fn() -> int? { |d: int?| d }",
+        "let g = (a: int, b, c: [string]?) => a;",
+        "let h = (a, b: int = 1, c = b * 2, ...r: [int]) => r;",
+        "/// One.\n// plain\n///\nlet a = 1; /// Two.\n fn g() { /// Three.\n let c = a; c }",
+    ];
+    for src in input.iter() {
+        let parse = parse(src);
+        assert_eq!(parse.source_file().to_ast(), parse.program, "{}", src);
+    }
+}
+
+#[test]
+fn test_error_node() {
+    let parse = parse("let a = 1; let = 2; let b = a;");
+    let kinds: Vec<SyntaxKind> = parse.root.children().iter().map(|n| n.kind()).collect();
+    assert_eq!(
+        kinds,
+        vec![SyntaxKind::LetStmt, SyntaxKind::Error, SyntaxKind::LetStmt]
+    );
+    assert_eq!(parse.root.children()[1].text(), "let = 2;");
+    assert_eq!(parse.errors.len(), 1);
+}
+
+#[test]
+fn test_deep_tree() {
+    let parse = parse(&format!("a{}", " ** a".repeat(1000)));
+    assert!(parse.errors.is_empty());
+    assert_eq!(parse.root.children()[0].kind(), SyntaxKind::ExprStmt);
+}
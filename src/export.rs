@@ -0,0 +1,1327 @@
+//! 構文木をJSONとS式に書き出し、JSONから読み込む（`export` フィーチャ）
+//!
+//! # JSONの形式
+//!
+//! プログラムは文の配列。文・式・パターンはどれも `"type"` で種類を表すオブジェクトで、
+//! 省略できる子は `null` になる。演算子は記号の文字列（`"+"`, `"<+>"` など）で、
+//! 組み込みの演算子かユーザー定義の演算子かは記号から決まる。
+//!
+//! | 文 | フィールド |
+//! |---|---|
+//! | `Let` | `pattern`: パターン, `annotation`: 型, `value`: 式, `doc`: 文字列 |
+//! | `Const` | `name`: 文字列, `annotation`: 型, `value`: 式, `doc`: 文字列 |
+//! | `Return` | `value`: 式 |
+//! | `Expr` | `expr`: 式 |
+//! | `FnDecl` | `name`: 文字列, `parameters`: 仮引数の配列, `return_type`: 型, `body`: 式, `doc`: 文字列 |
+//! | `Break`, `Continue` | なし |
+//! | `OperatorDecl` | `associativity`: `"left"`/`"right"`/`"none"`, `precedence`: 数値, `operator`: 記号 |
+//! | `Import` | `path`: 文字列, `alias`: 文字列 |
+//! | `Export` | `statement`: 文 |
+//!
+//! | 式 | フィールド |
+//! |---|---|
+//! | `Int` | `value`: 数値 |
+//! | `Ident` | `name`: 文字列 |
+//! | `Boolean` | `value`: 真偽値 |
+//! | `Str` | `value`: 文字列 |
+//! | `Grouped` | `expr`: 式 |
+//! | `If` | `condition`, `consequence`: 式, `alternative`: 式か `null` |
+//! | `Prefix` | `operator`: 記号, `right`: 式 |
+//! | `Infix` | `operator`: 記号, `left`, `right`: 式 |
+//! | `Postfix` | `operator`: 記号, `left`: 式 |
+//! | `Block` | `statements`: 文の配列 |
+//! | `Function` | `parameters`: 仮引数の配列, `return_type`: 型, `body`: 式 |
+//! | `MacroLiteral` | `parameters`: 仮引数の配列, `body`: 式 |
+//! | `While` | `condition`, `body`: 式 |
+//! | `For` | `variable`: 文字列, `iterable`, `body`: 式 |
+//! | `Match` | `subject`: 式, `arms`: 腕の配列 |
+//! | `Call` | `function`: 式, `arguments`: 実引数の配列 |
+//! | `Ternary` | `condition`, `consequence`, `alternative`: 式 |
+//! | `Pipe` | `left`, `right`: 式 |
+//! | `Assign` | `operator`: `"="` か `"+="` などの記号, `target`, `value`: 式 |
+//! | `Member` | `object`: 式, `name`: 文字列 |
+//!
+//! `doc` と型の注釈（`annotation`, `return_type`）は書いてあるときだけ書き出し、
+//! 読み込むときは省略と `null` を同じに扱う。
+//!
+//! 仮引数は `{"name", "annotation": 型, "default": 式か null, "rest": 真偽値}`、
+//! 実引数は `{"type": "Positional", "value"}` か `{"type": "Keyword", "name", "value"}`、
+//! 腕は `{"pattern", "guard": 式か null, "body"}`。
+//! パターンは `Wildcard`、`Ident`（`name`）、`Int`・`Boolean`・`Str`（`value`）、
+//! `Array`（`elements`: パターンの配列, `rest`: 文字列か `null`）、`Hash`（`pairs`: `[キー, 値]` の配列）。
+//! 型は `Named`（`name`）、`Array`（`element`）、`Hash`（`key`, `value`）、
+//! `Function`（`parameters`: 型の配列, `return_type`）、`Optional`（`inner`）。
+//!
+//! 読み込むときは同じ形式を受け付け、構文木を組み立てたあと `validate::validate_program` で
+//! 構文解析器が作らない形（前置演算子でない記号の `Prefix` など）を誤りにする。
+//!
+//! # S式の形式
+//!
+//! 文は1つずつ1行に書く。整数・真偽値・文字列・識別子はそのまま、それ以外は
+//! `(let x (infix + 1 y))` のように先頭に種類を置いたリストになる。
+//! `///` コメントは `(let x 1 (doc "..."))` のように文の最後に付ける。
+//! 型の注釈は `(let (: x (optional int)) 1)` のように名前と組にし、戻り値の型は `(returns bool)` と書く。
+use crate::ast::{Argument, Expression, MatchArm, Parameter, Pattern, Statement, TypeExpr};
+use crate::json::{quote, Json};
+use crate::operator::Associativity;
+use crate::validate::{operator_token, validate_program};
+
+/// プログラムをJSONの値にする
+pub fn program_to_json(program: &[Statement]) -> Json {
+    Json::Array(program.iter().map(statement_to_json).collect())
+}
+
+pub fn statement_to_json(statement: &Statement) -> Json {
+    match statement {
+        Statement::Let {
+            pattern,
+            annotation,
+            expr,
+            doc,
+        } => {
+            let mut fields = vec![
+                ("type", Json::string("Let")),
+                ("pattern", pattern_to_json(pattern)),
+            ];
+            annotated_json(&mut fields, "annotation", annotation);
+            fields.push(("value", expression_to_json(expr)));
+            documented_json(fields, doc)
+        }
+        Statement::Const {
+            name,
+            annotation,
+            expr,
+            doc,
+        } => {
+            let mut fields = vec![
+                ("type", Json::string("Const")),
+                ("name", Json::string(name)),
+            ];
+            annotated_json(&mut fields, "annotation", annotation);
+            fields.push(("value", expression_to_json(expr)));
+            documented_json(fields, doc)
+        }
+        Statement::Return(expr) => Json::object(vec![
+            ("type", Json::string("Return")),
+            ("value", expression_to_json(expr)),
+        ]),
+        Statement::Expr(expr) => Json::object(vec![
+            ("type", Json::string("Expr")),
+            ("expr", expression_to_json(expr)),
+        ]),
+        Statement::FnDecl {
+            name,
+            parameters,
+            return_type,
+            body,
+            doc,
+        } => {
+            let mut fields = vec![
+                ("type", Json::string("FnDecl")),
+                ("name", Json::string(name)),
+                ("parameters", parameters_to_json(parameters)),
+            ];
+            annotated_json(&mut fields, "return_type", return_type);
+            fields.push(("body", expression_to_json(body)));
+            documented_json(fields, doc)
+        }
+        Statement::Break => Json::object(vec![("type", Json::string("Break"))]),
+        Statement::Continue => Json::object(vec![("type", Json::string("Continue"))]),
+        Statement::OperatorDecl {
+            associativity,
+            precedence,
+            operator,
+        } => Json::object(vec![
+            ("type", Json::string("OperatorDecl")),
+            (
+                "associativity",
+                Json::string(associativity_name(*associativity)),
+            ),
+            ("precedence", Json::Number(i64::from(*precedence))),
+            ("operator", Json::string(operator)),
+        ]),
+        Statement::Import { path, alias } => Json::object(vec![
+            ("type", Json::string("Import")),
+            ("path", Json::string(path)),
+            ("alias", Json::string(alias)),
+        ]),
+        Statement::Export(stmt) => Json::object(vec![
+            ("type", Json::string("Export")),
+            ("statement", statement_to_json(stmt)),
+        ]),
+    }
+}
+
+pub fn expression_to_json(expression: &Expression) -> Json {
+    let node = |kind: &str, mut fields: Vec<(&str, Json)>| {
+        fields.insert(0, ("type", Json::string(kind)));
+        Json::object(fields)
+    };
+    match expression {
+        Expression::Int(value) => node("Int", vec![("value", Json::Number(*value))]),
+        Expression::Ident(name) => node("Ident", vec![("name", Json::string(name))]),
+        Expression::Boolean(value) => node("Boolean", vec![("value", Json::Bool(*value))]),
+        Expression::Str(value) => node("Str", vec![("value", Json::string(value))]),
+        Expression::Grouped(expr) => node("Grouped", vec![("expr", expression_to_json(expr))]),
+        Expression::If {
+            condition,
+            consequence,
+            alternative,
+        } => node(
+            "If",
+            vec![
+                ("condition", expression_to_json(condition)),
+                ("consequence", expression_to_json(consequence)),
+                ("alternative", optional(alternative.as_deref())),
+            ],
+        ),
+        Expression::Prefix { operator, right } => node(
+            "Prefix",
+            vec![
+                ("operator", Json::String(operator.to_string())),
+                ("right", expression_to_json(right)),
+            ],
+        ),
+        Expression::Infix {
+            operator,
+            left,
+            right,
+        } => node(
+            "Infix",
+            vec![
+                ("operator", Json::String(operator.to_string())),
+                ("left", expression_to_json(left)),
+                ("right", expression_to_json(right)),
+            ],
+        ),
+        Expression::Postfix { operator, left } => node(
+            "Postfix",
+            vec![
+                ("operator", Json::String(operator.to_string())),
+                ("left", expression_to_json(left)),
+            ],
+        ),
+        Expression::Block(statements) => {
+            node("Block", vec![("statements", program_to_json(statements))])
+        }
+        Expression::Function {
+            parameters,
+            return_type,
+            body,
+        } => {
+            let mut fields = vec![("parameters", parameters_to_json(parameters))];
+            annotated_json(&mut fields, "return_type", return_type);
+            fields.push(("body", expression_to_json(body)));
+            node("Function", fields)
+        }
+        Expression::MacroLiteral { parameters, body } => node(
+            "MacroLiteral",
+            vec![
+                ("parameters", parameters_to_json(parameters)),
+                ("body", expression_to_json(body)),
+            ],
+        ),
+        Expression::While { condition, body } => node(
+            "While",
+            vec![
+                ("condition", expression_to_json(condition)),
+                ("body", expression_to_json(body)),
+            ],
+        ),
+        Expression::For {
+            variable,
+            iterable,
+            body,
+        } => node(
+            "For",
+            vec![
+                ("variable", Json::string(variable)),
+                ("iterable", expression_to_json(iterable)),
+                ("body", expression_to_json(body)),
+            ],
+        ),
+        Expression::Match { subject, arms } => node(
+            "Match",
+            vec![
+                ("subject", expression_to_json(subject)),
+                ("arms", Json::Array(arms.iter().map(arm_to_json).collect())),
+            ],
+        ),
+        Expression::Call {
+            function,
+            arguments,
+        } => node(
+            "Call",
+            vec![
+                ("function", expression_to_json(function)),
+                (
+                    "arguments",
+                    Json::Array(arguments.iter().map(argument_to_json).collect()),
+                ),
+            ],
+        ),
+        Expression::Ternary {
+            condition,
+            consequence,
+            alternative,
+        } => node(
+            "Ternary",
+            vec![
+                ("condition", expression_to_json(condition)),
+                ("consequence", expression_to_json(consequence)),
+                ("alternative", expression_to_json(alternative)),
+            ],
+        ),
+        Expression::Pipe { left, right } => node(
+            "Pipe",
+            vec![
+                ("left", expression_to_json(left)),
+                ("right", expression_to_json(right)),
+            ],
+        ),
+        Expression::Assign {
+            operator,
+            target,
+            value,
+        } => node(
+            "Assign",
+            vec![
+                ("operator", Json::String(operator.to_string())),
+                ("target", expression_to_json(target)),
+                ("value", expression_to_json(value)),
+            ],
+        ),
+        Expression::Member { object, name } => node(
+            "Member",
+            vec![
+                ("object", expression_to_json(object)),
+                ("name", Json::string(name)),
+            ],
+        ),
+    }
+}
+
+pub fn pattern_to_json(pattern: &Pattern) -> Json {
+    match pattern {
+        Pattern::Wildcard => Json::object(vec![("type", Json::string("Wildcard"))]),
+        Pattern::Ident(name) => Json::object(vec![
+            ("type", Json::string("Ident")),
+            ("name", Json::string(name)),
+        ]),
+        Pattern::Int(value) => Json::object(vec![
+            ("type", Json::string("Int")),
+            ("value", Json::Number(*value)),
+        ]),
+        Pattern::Boolean(value) => Json::object(vec![
+            ("type", Json::string("Boolean")),
+            ("value", Json::Bool(*value)),
+        ]),
+        Pattern::Str(value) => Json::object(vec![
+            ("type", Json::string("Str")),
+            ("value", Json::string(value)),
+        ]),
+        Pattern::Array { elements, rest } => Json::object(vec![
+            ("type", Json::string("Array")),
+            (
+                "elements",
+                Json::Array(elements.iter().map(pattern_to_json).collect()),
+            ),
+            ("rest", rest.as_deref().map_or(Json::Null, Json::string)),
+        ]),
+        Pattern::Hash(pairs) => Json::object(vec![
+            ("type", Json::string("Hash")),
+            (
+                "pairs",
+                Json::Array(
+                    pairs
+                        .iter()
+                        .map(|(key, value)| {
+                            Json::Array(vec![pattern_to_json(key), pattern_to_json(value)])
+                        })
+                        .collect(),
+                ),
+            ),
+        ]),
+    }
+}
+
+pub fn type_to_json(ty: &TypeExpr) -> Json {
+    match ty {
+        TypeExpr::Named(name) => Json::object(vec![
+            ("type", Json::string("Named")),
+            ("name", Json::string(name)),
+        ]),
+        TypeExpr::Array(element) => Json::object(vec![
+            ("type", Json::string("Array")),
+            ("element", type_to_json(element)),
+        ]),
+        TypeExpr::Hash { key, value } => Json::object(vec![
+            ("type", Json::string("Hash")),
+            ("key", type_to_json(key)),
+            ("value", type_to_json(value)),
+        ]),
+        TypeExpr::Function {
+            parameters,
+            return_type,
+        } => Json::object(vec![
+            ("type", Json::string("Function")),
+            (
+                "parameters",
+                Json::Array(parameters.iter().map(type_to_json).collect()),
+            ),
+            ("return_type", type_to_json(return_type)),
+        ]),
+        TypeExpr::Optional(inner) => Json::object(vec![
+            ("type", Json::string("Optional")),
+            ("inner", type_to_json(inner)),
+        ]),
+    }
+}
+
+fn optional(expression: Option<&Expression>) -> Json {
+    expression.map_or(Json::Null, expression_to_json)
+}
+
+/// `///` コメントがあれば `doc` を加える
+fn documented_json(mut fields: Vec<(&str, Json)>, doc: &Option<String>) -> Json {
+    if let Some(doc) = doc {
+        fields.push(("doc", Json::string(doc)));
+    }
+    Json::object(fields)
+}
+
+/// 型の注釈があれば `key` として加える
+fn annotated_json<'a>(fields: &mut Vec<(&'a str, Json)>, key: &'a str, ty: &Option<TypeExpr>) {
+    if let Some(ty) = ty {
+        fields.push((key, type_to_json(ty)));
+    }
+}
+
+fn parameters_to_json(parameters: &[Parameter]) -> Json {
+    Json::Array(
+        parameters
+            .iter()
+            .map(|parameter| {
+                let mut fields = vec![("name", Json::string(&parameter.name))];
+                annotated_json(&mut fields, "annotation", &parameter.annotation);
+                fields.push(("default", optional(parameter.default.as_ref())));
+                fields.push(("rest", Json::Bool(parameter.rest)));
+                Json::object(fields)
+            })
+            .collect(),
+    )
+}
+
+fn argument_to_json(argument: &Argument) -> Json {
+    match argument {
+        Argument::Positional(value) => Json::object(vec![
+            ("type", Json::string("Positional")),
+            ("value", expression_to_json(value)),
+        ]),
+        Argument::Keyword(name, value) => Json::object(vec![
+            ("type", Json::string("Keyword")),
+            ("name", Json::string(name)),
+            ("value", expression_to_json(value)),
+        ]),
+    }
+}
+
+fn arm_to_json(arm: &MatchArm) -> Json {
+    Json::object(vec![
+        ("pattern", pattern_to_json(&arm.pattern)),
+        ("guard", optional(arm.guard.as_ref())),
+        ("body", expression_to_json(&arm.body)),
+    ])
+}
+
+fn associativity_name(associativity: Associativity) -> &'static str {
+    match associativity {
+        Associativity::Left => "left",
+        Associativity::Right => "right",
+        Associativity::None => "none",
+    }
+}
+
+/// プログラムをS式にする（1行に1文）
+pub fn program_to_sexp(program: &[Statement]) -> String {
+    program
+        .iter()
+        .map(|statement| statement_to_sexp(statement) + "\n")
+        .collect()
+}
+
+pub fn statement_to_sexp(statement: &Statement) -> String {
+    match statement {
+        Statement::Let {
+            pattern,
+            annotation,
+            expr,
+            doc,
+        } => documented_sexp(
+            "let",
+            vec![
+                annotated_sexp(pattern_to_sexp(pattern), annotation),
+                expression_to_sexp(expr),
+            ],
+            doc,
+        ),
+        Statement::Const {
+            name,
+            annotation,
+            expr,
+            doc,
+        } => documented_sexp(
+            "const",
+            vec![
+                annotated_sexp(name.clone(), annotation),
+                expression_to_sexp(expr),
+            ],
+            doc,
+        ),
+        Statement::Return(expr) => list("return", vec![expression_to_sexp(expr)]),
+        Statement::Expr(expr) => list("expr", vec![expression_to_sexp(expr)]),
+        Statement::FnDecl {
+            name,
+            parameters,
+            return_type,
+            body,
+            doc,
+        } => {
+            let mut items = vec![name.clone(), parameters_to_sexp(parameters)];
+            items.extend(returns_sexp(return_type));
+            items.push(expression_to_sexp(body));
+            documented_sexp("fn-decl", items, doc)
+        }
+        Statement::Break => list("break", vec![]),
+        Statement::Continue => list("continue", vec![]),
+        Statement::OperatorDecl {
+            associativity,
+            precedence,
+            operator,
+        } => list(
+            "operator",
+            vec![
+                associativity_name(*associativity).to_string(),
+                precedence.to_string(),
+                quote(operator),
+            ],
+        ),
+        Statement::Import { path, alias } => list("import", vec![quote(path), alias.clone()]),
+        Statement::Export(stmt) => list("export", vec![statement_to_sexp(stmt)]),
+    }
+}
+
+pub fn expression_to_sexp(expression: &Expression) -> String {
+    match expression {
+        Expression::Int(value) => value.to_string(),
+        Expression::Ident(name) => name.clone(),
+        Expression::Boolean(value) => value.to_string(),
+        Expression::Str(value) => quote(value),
+        Expression::Grouped(expr) => list("group", vec![expression_to_sexp(expr)]),
+        Expression::If {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            let mut items = vec![
+                expression_to_sexp(condition),
+                expression_to_sexp(consequence),
+            ];
+            items.extend(alternative.iter().map(|alt| expression_to_sexp(alt)));
+            list("if", items)
+        }
+        Expression::Prefix { operator, right } => list(
+            "prefix",
+            vec![operator.to_string(), expression_to_sexp(right)],
+        ),
+        Expression::Infix {
+            operator,
+            left,
+            right,
+        } => list(
+            "infix",
+            vec![
+                operator.to_string(),
+                expression_to_sexp(left),
+                expression_to_sexp(right),
+            ],
+        ),
+        Expression::Postfix { operator, left } => list(
+            "postfix",
+            vec![operator.to_string(), expression_to_sexp(left)],
+        ),
+        Expression::Block(statements) => {
+            list("block", statements.iter().map(statement_to_sexp).collect())
+        }
+        Expression::Function {
+            parameters,
+            return_type,
+            body,
+        } => {
+            let mut items = vec![parameters_to_sexp(parameters)];
+            items.extend(returns_sexp(return_type));
+            items.push(expression_to_sexp(body));
+            list("fn", items)
+        }
+        Expression::MacroLiteral { parameters, body } => list(
+            "macro",
+            vec![parameters_to_sexp(parameters), expression_to_sexp(body)],
+        ),
+        Expression::While { condition, body } => list(
+            "while",
+            vec![expression_to_sexp(condition), expression_to_sexp(body)],
+        ),
+        Expression::For {
+            variable,
+            iterable,
+            body,
+        } => list(
+            "for",
+            vec![
+                variable.clone(),
+                expression_to_sexp(iterable),
+                expression_to_sexp(body),
+            ],
+        ),
+        Expression::Match { subject, arms } => {
+            let mut items = vec![expression_to_sexp(subject)];
+            items.extend(arms.iter().map(arm_to_sexp));
+            list("match", items)
+        }
+        Expression::Call {
+            function,
+            arguments,
+        } => {
+            let mut items = vec![expression_to_sexp(function)];
+            items.extend(arguments.iter().map(|argument| match argument {
+                Argument::Positional(value) => expression_to_sexp(value),
+                Argument::Keyword(name, value) => {
+                    list("kw", vec![name.clone(), expression_to_sexp(value)])
+                }
+            }));
+            list("call", items)
+        }
+        Expression::Ternary {
+            condition,
+            consequence,
+            alternative,
+        } => list(
+            "ternary",
+            vec![
+                expression_to_sexp(condition),
+                expression_to_sexp(consequence),
+                expression_to_sexp(alternative),
+            ],
+        ),
+        Expression::Pipe { left, right } => list(
+            "pipe",
+            vec![expression_to_sexp(left), expression_to_sexp(right)],
+        ),
+        Expression::Assign {
+            operator,
+            target,
+            value,
+        } => list(
+            "assign",
+            vec![
+                operator.to_string(),
+                expression_to_sexp(target),
+                expression_to_sexp(value),
+            ],
+        ),
+        Expression::Member { object, name } => {
+            list("member", vec![expression_to_sexp(object), name.clone()])
+        }
+    }
+}
+
+pub fn pattern_to_sexp(pattern: &Pattern) -> String {
+    match pattern {
+        Pattern::Wildcard => "_".to_string(),
+        Pattern::Ident(name) => name.clone(),
+        Pattern::Int(value) => value.to_string(),
+        Pattern::Boolean(value) => value.to_string(),
+        Pattern::Str(value) => quote(value),
+        Pattern::Array { elements, rest } => {
+            let mut items: Vec<String> = elements.iter().map(pattern_to_sexp).collect();
+            if let Some(rest) = rest {
+                items.push(list("rest", vec![rest.clone()]));
+            }
+            list("array", items)
+        }
+        Pattern::Hash(pairs) => list(
+            "hash",
+            pairs
+                .iter()
+                .map(|(key, value)| {
+                    format!("({} {})", pattern_to_sexp(key), pattern_to_sexp(value))
+                })
+                .collect(),
+        ),
+    }
+}
+
+/// `int`, `(array int)`, `(hash string int)`, `(fn (int string) bool)`, `(optional int)`
+pub fn type_to_sexp(ty: &TypeExpr) -> String {
+    match ty {
+        TypeExpr::Named(name) => name.clone(),
+        TypeExpr::Array(element) => list("array", vec![type_to_sexp(element)]),
+        TypeExpr::Hash { key, value } => list("hash", vec![type_to_sexp(key), type_to_sexp(value)]),
+        TypeExpr::Function {
+            parameters,
+            return_type,
+        } => {
+            let parameters: Vec<String> = parameters.iter().map(type_to_sexp).collect();
+            list(
+                "fn",
+                vec![
+                    format!("({})", parameters.join(" ")),
+                    type_to_sexp(return_type),
+                ],
+            )
+        }
+        TypeExpr::Optional(inner) => list("optional", vec![type_to_sexp(inner)]),
+    }
+}
+
+/// 型の注釈があれば `(: item type)` にする
+fn annotated_sexp(item: String, annotation: &Option<TypeExpr>) -> String {
+    match annotation {
+        Some(ty) => list(":", vec![item, type_to_sexp(ty)]),
+        None => item,
+    }
+}
+
+/// 戻り値の型があれば `(returns type)`
+fn returns_sexp(return_type: &Option<TypeExpr>) -> Option<String> {
+    return_type
+        .as_ref()
+        .map(|ty| list("returns", vec![type_to_sexp(ty)]))
+}
+
+/// `///` コメントがあれば最後に `(doc "...")` を付ける
+fn documented_sexp(head: &str, mut items: Vec<String>, doc: &Option<String>) -> String {
+    if let Some(doc) = doc {
+        items.push(list("doc", vec![quote(doc)]));
+    }
+    list(head, items)
+}
+
+fn list(head: &str, items: Vec<String>) -> String {
+    let mut out = format!("({}", head);
+    for item in items {
+        out.push(' ');
+        out.push_str(&item);
+    }
+    out.push(')');
+    out
+}
+
+/// `(params a (default b 10) (rest xs))` 型の注釈は `(: a int)` のように名前と組にする
+fn parameters_to_sexp(parameters: &[Parameter]) -> String {
+    list(
+        "params",
+        parameters
+            .iter()
+            .map(|parameter| {
+                let name = annotated_sexp(parameter.name.clone(), &parameter.annotation);
+                match (&parameter.default, parameter.rest) {
+                    (_, true) => list("rest", vec![name]),
+                    (Some(default), false) => {
+                        list("default", vec![name, expression_to_sexp(default)])
+                    }
+                    (None, false) => name,
+                }
+            })
+            .collect(),
+    )
+}
+
+/// `(arm pattern body)` ガードがあれば `(arm pattern (if guard) body)`
+fn arm_to_sexp(arm: &MatchArm) -> String {
+    let mut items = vec![pattern_to_sexp(&arm.pattern)];
+    items.extend(
+        arm.guard
+            .iter()
+            .map(|guard| list("if", vec![expression_to_sexp(guard)])),
+    );
+    items.push(expression_to_sexp(&arm.body));
+    list("arm", items)
+}
+
+/// JSONの文字列からプログラムを読み込む
+pub fn parse_json_program(input: &str) -> Result<Vec<Statement>, String> {
+    program_from_json(&Json::parse(input)?)
+}
+
+/// JSONの値をプログラムにする。構文解析器が受け付けない構文木は誤り
+pub fn program_from_json(value: &Json) -> Result<Vec<Statement>, String> {
+    let program = Node::root(value)
+        .items()?
+        .iter()
+        .map(statement_from_json)
+        .collect::<Result<Vec<_>, _>>()?;
+    let errors = validate_program(&program);
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+    Ok(program)
+}
+
+/// 読み込み中の値と、誤りの説明に使うその位置（`$[0].value.left` など）
+struct Node<'a> {
+    value: &'a Json,
+    path: String,
+}
+
+impl<'a> Node<'a> {
+    fn root(value: &'a Json) -> Node<'a> {
+        Node {
+            value,
+            path: "$".to_string(),
+        }
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("{}: {}", self.path, message)
+    }
+
+    fn expected(&self, kind: &str) -> String {
+        self.error(&format!("expected {}, got {}", kind, self.value.kind()))
+    }
+
+    fn field(&self, key: &str) -> Result<Node<'a>, String> {
+        if !matches!(self.value, Json::Object(_)) {
+            return Err(self.expected("object"));
+        }
+        match self.value.get(key) {
+            Some(value) => Ok(Node {
+                value,
+                path: format!("{}.{}", self.path, key),
+            }),
+            None => Err(self.error(&format!("missing field {}", key))),
+        }
+    }
+
+    /// `null` なら `None`
+    fn optional(&self, key: &str) -> Result<Option<Node<'a>>, String> {
+        let node = self.field(key)?;
+        Ok(match node.value {
+            Json::Null => None,
+            _ => Some(node),
+        })
+    }
+
+    fn items(&self) -> Result<Vec<Node<'a>>, String> {
+        match self.value {
+            Json::Array(items) => Ok(items
+                .iter()
+                .enumerate()
+                .map(|(i, value)| Node {
+                    value,
+                    path: format!("{}[{}]", self.path, i),
+                })
+                .collect()),
+            _ => Err(self.expected("array")),
+        }
+    }
+
+    fn string(&self) -> Result<String, String> {
+        match self.value {
+            Json::String(value) => Ok(value.clone()),
+            _ => Err(self.expected("string")),
+        }
+    }
+
+    fn number(&self) -> Result<i64, String> {
+        match self.value {
+            Json::Number(value) => Ok(*value),
+            _ => Err(self.expected("number")),
+        }
+    }
+
+    fn boolean(&self) -> Result<bool, String> {
+        match self.value {
+            Json::Bool(value) => Ok(*value),
+            _ => Err(self.expected("boolean")),
+        }
+    }
+
+    fn kind(&self) -> Result<String, String> {
+        self.field("type")?.string()
+    }
+
+    fn string_field(&self, key: &str) -> Result<String, String> {
+        self.field(key)?.string()
+    }
+
+    fn expression_field(&self, key: &str) -> Result<Box<Expression>, String> {
+        Ok(Box::new(expression_from_json(&self.field(key)?)?))
+    }
+
+    /// 省略できる `doc`（省略と `null` は `None`）
+    fn doc(&self) -> Result<Option<String>, String> {
+        match self.value.get("doc") {
+            None | Some(Json::Null) => Ok(None),
+            Some(_) => Ok(Some(self.string_field("doc")?)),
+        }
+    }
+
+    /// 省略できる型の注釈（省略と `null` は `None`）
+    fn type_annotation(&self, key: &str) -> Result<Option<TypeExpr>, String> {
+        match self.value.get(key) {
+            None | Some(Json::Null) => Ok(None),
+            Some(_) => Ok(Some(type_from_json(&self.field(key)?)?)),
+        }
+    }
+
+    fn type_field(&self, key: &str) -> Result<Box<TypeExpr>, String> {
+        Ok(Box::new(type_from_json(&self.field(key)?)?))
+    }
+
+    fn operator_field(&self, key: &str) -> Result<crate::token::Token, String> {
+        Ok(operator_token(&self.string_field(key)?))
+    }
+}
+
+fn statement_from_json(node: &Node) -> Result<Statement, String> {
+    Ok(match node.kind()?.as_str() {
+        "Let" => Statement::Let {
+            pattern: pattern_from_json(&node.field("pattern")?)?,
+            annotation: node.type_annotation("annotation")?,
+            expr: *node.expression_field("value")?,
+            doc: node.doc()?,
+        },
+        "Const" => Statement::Const {
+            name: node.string_field("name")?,
+            annotation: node.type_annotation("annotation")?,
+            expr: *node.expression_field("value")?,
+            doc: node.doc()?,
+        },
+        "Return" => Statement::Return(*node.expression_field("value")?),
+        "Expr" => Statement::Expr(*node.expression_field("expr")?),
+        "FnDecl" => Statement::FnDecl {
+            name: node.string_field("name")?,
+            parameters: parameters_from_json(&node.field("parameters")?)?,
+            return_type: node.type_annotation("return_type")?,
+            body: *node.expression_field("body")?,
+            doc: node.doc()?,
+        },
+        "Break" => Statement::Break,
+        "Continue" => Statement::Continue,
+        "OperatorDecl" => {
+            let associativity = node.field("associativity")?;
+            let precedence = node.field("precedence")?;
+            Statement::OperatorDecl {
+                associativity: match associativity.string()?.as_str() {
+                    "left" => Associativity::Left,
+                    "right" => Associativity::Right,
+                    "none" => Associativity::None,
+                    other => {
+                        return Err(associativity.error(&format!(
+                            "unknown associativity {}, expected left, right or none",
+                            quote(other)
+                        )))
+                    }
+                },
+                precedence: match precedence.number()? {
+                    value if (0..=i64::from(u8::MAX)).contains(&value) => value as u8,
+                    value => {
+                        return Err(precedence.error(&format!(
+                            "expected precedence between 0 and 255, got {}",
+                            value
+                        )))
+                    }
+                },
+                operator: node.string_field("operator")?,
+            }
+        }
+        "Import" => Statement::Import {
+            path: node.string_field("path")?,
+            alias: node.string_field("alias")?,
+        },
+        "Export" => Statement::Export(Box::new(statement_from_json(&node.field("statement")?)?)),
+        other => return Err(node.error(&format!("unknown statement type {}", quote(other)))),
+    })
+}
+
+fn expression_from_json(node: &Node) -> Result<Expression, String> {
+    Ok(match node.kind()?.as_str() {
+        "Int" => Expression::Int(node.field("value")?.number()?),
+        "Ident" => Expression::Ident(node.string_field("name")?),
+        "Boolean" => Expression::Boolean(node.field("value")?.boolean()?),
+        "Str" => Expression::Str(node.string_field("value")?),
+        "Grouped" => Expression::Grouped(node.expression_field("expr")?),
+        "If" => Expression::If {
+            condition: node.expression_field("condition")?,
+            consequence: node.expression_field("consequence")?,
+            alternative: match node.optional("alternative")? {
+                Some(alternative) => Some(Box::new(expression_from_json(&alternative)?)),
+                None => None,
+            },
+        },
+        "Prefix" => Expression::Prefix {
+            operator: node.operator_field("operator")?,
+            right: node.expression_field("right")?,
+        },
+        "Infix" => Expression::Infix {
+            operator: node.operator_field("operator")?,
+            left: node.expression_field("left")?,
+            right: node.expression_field("right")?,
+        },
+        "Postfix" => Expression::Postfix {
+            operator: node.operator_field("operator")?,
+            left: node.expression_field("left")?,
+        },
+        "Block" => Expression::Block(
+            node.field("statements")?
+                .items()?
+                .iter()
+                .map(statement_from_json)
+                .collect::<Result<_, _>>()?,
+        ),
+        "Function" => Expression::Function {
+            parameters: parameters_from_json(&node.field("parameters")?)?,
+            return_type: node.type_annotation("return_type")?,
+            body: node.expression_field("body")?,
+        },
+        "MacroLiteral" => Expression::MacroLiteral {
+            parameters: parameters_from_json(&node.field("parameters")?)?,
+            body: node.expression_field("body")?,
+        },
+        "While" => Expression::While {
+            condition: node.expression_field("condition")?,
+            body: node.expression_field("body")?,
+        },
+        "For" => Expression::For {
+            variable: node.string_field("variable")?,
+            iterable: node.expression_field("iterable")?,
+            body: node.expression_field("body")?,
+        },
+        "Match" => Expression::Match {
+            subject: node.expression_field("subject")?,
+            arms: node
+                .field("arms")?
+                .items()?
+                .iter()
+                .map(arm_from_json)
+                .collect::<Result<_, _>>()?,
+        },
+        "Call" => Expression::Call {
+            function: node.expression_field("function")?,
+            arguments: node
+                .field("arguments")?
+                .items()?
+                .iter()
+                .map(argument_from_json)
+                .collect::<Result<_, _>>()?,
+        },
+        "Ternary" => Expression::Ternary {
+            condition: node.expression_field("condition")?,
+            consequence: node.expression_field("consequence")?,
+            alternative: node.expression_field("alternative")?,
+        },
+        "Pipe" => Expression::Pipe {
+            left: node.expression_field("left")?,
+            right: node.expression_field("right")?,
+        },
+        "Assign" => Expression::Assign {
+            operator: node.operator_field("operator")?,
+            target: node.expression_field("target")?,
+            value: node.expression_field("value")?,
+        },
+        "Member" => Expression::Member {
+            object: node.expression_field("object")?,
+            name: node.string_field("name")?,
+        },
+        other => return Err(node.error(&format!("unknown expression type {}", quote(other)))),
+    })
+}
+
+fn pattern_from_json(node: &Node) -> Result<Pattern, String> {
+    Ok(match node.kind()?.as_str() {
+        "Wildcard" => Pattern::Wildcard,
+        "Ident" => Pattern::Ident(node.string_field("name")?),
+        "Int" => Pattern::Int(node.field("value")?.number()?),
+        "Boolean" => Pattern::Boolean(node.field("value")?.boolean()?),
+        "Str" => Pattern::Str(node.string_field("value")?),
+        "Array" => Pattern::Array {
+            elements: node
+                .field("elements")?
+                .items()?
+                .iter()
+                .map(pattern_from_json)
+                .collect::<Result<_, _>>()?,
+            rest: match node.optional("rest")? {
+                Some(rest) => Some(rest.string()?),
+                None => None,
+            },
+        },
+        "Hash" => Pattern::Hash(
+            node.field("pairs")?
+                .items()?
+                .iter()
+                .map(|pair| match pair.items()?.as_slice() {
+                    [key, value] => Ok((pattern_from_json(key)?, pattern_from_json(value)?)),
+                    _ => Err(pair.error("expected [key, value] pair")),
+                })
+                .collect::<Result<_, _>>()?,
+        ),
+        other => return Err(node.error(&format!("unknown pattern type {}", quote(other)))),
+    })
+}
+
+fn type_from_json(node: &Node) -> Result<TypeExpr, String> {
+    Ok(match node.kind()?.as_str() {
+        "Named" => TypeExpr::Named(node.string_field("name")?),
+        "Array" => TypeExpr::Array(node.type_field("element")?),
+        "Hash" => TypeExpr::Hash {
+            key: node.type_field("key")?,
+            value: node.type_field("value")?,
+        },
+        "Function" => TypeExpr::Function {
+            parameters: node
+                .field("parameters")?
+                .items()?
+                .iter()
+                .map(type_from_json)
+                .collect::<Result<_, _>>()?,
+            return_type: node.type_field("return_type")?,
+        },
+        "Optional" => TypeExpr::Optional(node.type_field("inner")?),
+        other => return Err(node.error(&format!("unknown type expression {}", quote(other)))),
+    })
+}
+
+fn parameters_from_json(node: &Node) -> Result<Vec<Parameter>, String> {
+    node.items()?
+        .iter()
+        .map(|parameter| {
+            Ok(Parameter {
+                name: parameter.string_field("name")?,
+                annotation: parameter.type_annotation("annotation")?,
+                default: match parameter.optional("default")? {
+                    Some(default) => Some(expression_from_json(&default)?),
+                    None => None,
+                },
+                rest: parameter.field("rest")?.boolean()?,
+            })
+        })
+        .collect()
+}
+
+fn argument_from_json(node: &Node) -> Result<Argument, String> {
+    match node.kind()?.as_str() {
+        "Positional" => Ok(Argument::Positional(*node.expression_field("value")?)),
+        "Keyword" => Ok(Argument::Keyword(
+            node.string_field("name")?,
+            *node.expression_field("value")?,
+        )),
+        other => Err(node.error(&format!("unknown argument type {}", quote(other)))),
+    }
+}
+
+fn arm_from_json(node: &Node) -> Result<MatchArm, String> {
+    Ok(MatchArm {
+        pattern: pattern_from_json(&node.field("pattern")?)?,
+        guard: match node.optional("guard")? {
+            Some(guard) => Some(expression_from_json(&guard)?),
+            None => None,
+        },
+        body: *node.expression_field("body")?,
+    })
+}
+
+#[cfg(test)]
+fn parse(input: &str) -> Vec<Statement> {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    let mut parser = Parser::new(Lexer::new(input));
+    let program = parser.parse_program();
+    assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+    program
+}
+
+#[test]
+fn test_export_json() {
+    let program = parse("let x = -a + f(1, b: \"s\"); infixl 65 <+>; x <+> y;");
+    let expected = concat!(
+        r#"[{"type":"Let","pattern":{"type":"Ident","name":"x"},"#,
+        r#""value":{"type":"Infix","operator":"+","#,
+        r#""left":{"type":"Prefix","operator":"-","right":{"type":"Ident","name":"a"}},"#,
+        r#""right":{"type":"Call","function":{"type":"Ident","name":"f"},"arguments":["#,
+        r#"{"type":"Positional","value":{"type":"Int","value":1}},"#,
+        r#"{"type":"Keyword","name":"b","value":{"type":"Str","value":"s"}}]}}},"#,
+        r#"{"type":"OperatorDecl","associativity":"left","precedence":65,"operator":"<+>"},"#,
+        r#"{"type":"Expr","expr":{"type":"Infix","operator":"<+>","#,
+        r#""left":{"type":"Ident","name":"x"},"right":{"type":"Ident","name":"y"}}}]"#,
+    );
+    assert_eq!(program_to_json(&program).to_string(), expected);
+}
+
+#[test]
+fn test_export_json_patterns() {
+    let program = parse("match (v) { [1, _] if ok => 1, {\"k\": n} => n };");
+    let arms = match &program[0] {
+        Statement::Expr(Expression::Match { arms, .. }) => arms,
+        statement => panic!("{:?}", statement),
+    };
+    assert_eq!(
+        arm_to_json(&arms[0]).to_string(),
+        concat!(
+            r#"{"pattern":{"type":"Array","elements":[{"type":"Int","value":1},{"type":"Wildcard"}],"rest":null},"#,
+            r#""guard":{"type":"Ident","name":"ok"},"body":{"type":"Int","value":1}}"#,
+        )
+    );
+    assert_eq!(
+        pattern_to_json(&arms[1].pattern).to_string(),
+        r#"{"type":"Hash","pairs":[[{"type":"Str","value":"k"},{"type":"Ident","name":"n"}]]}"#
+    );
+}
+
+#[test]
+fn test_export_json_types() {
+    let program = parse("const n: int = 1;");
+    assert_eq!(
+        program_to_json(&program).to_string(),
+        r#"[{"type":"Const","name":"n","annotation":{"type":"Named","name":"int"},"value":{"type":"Int","value":1}}]"#
+    );
+    let program = parse("fn f(a: [int]?, b) -> fn(string) -> bool { a }");
+    assert_eq!(
+        program_to_json(&program).to_string(),
+        concat!(
+            r#"[{"type":"FnDecl","name":"f","parameters":["#,
+            r#"{"name":"a","annotation":{"type":"Optional","inner":{"type":"Array","element":{"type":"Named","name":"int"}}},"default":null,"rest":false},"#,
+            r#"{"name":"b","default":null,"rest":false}],"#,
+            r#""return_type":{"type":"Function","parameters":[{"type":"Named","name":"string"}],"return_type":{"type":"Named","name":"bool"}},"#,
+            r#""body":{"type":"Block","statements":[{"type":"Expr","expr":{"type":"Ident","name":"a"}}]}}]"#,
+        )
+    );
+}
+
+#[test]
+fn test_export_long_chain() {
+    let program = parse(&format!("a{}", " + a".repeat(200)));
+    let json = program_to_json(&program).to_string();
+    assert_eq!(json.matches(r#""type":"Infix""#).count(), 200);
+    assert_eq!(program_to_sexp(&program).matches("(infix + ").count(), 200);
+}
+
+#[test]
+fn test_export_sexp() {
+    let tests = [
+        ("let x = 1 + 2 * y;", "(let x (infix + 1 (infix * 2 y)))\n"),
+        (
+            "/// Says \"hi\".\nfn f() { 1 }",
+            "(fn-decl f (params) (block (expr 1)) (doc \"Says \\\"hi\\\".\"))\n",
+        ),
+        (
+            "fn f(a, b = 2, ...c) { if (a) { b } else { !c } }",
+            "(fn-decl f (params a (default b 2) (rest c)) (block (expr (if a (block (expr b)) (block (expr (prefix ! c)))))))\n",
+        ),
+        (
+            "match (x) { [a, _] if a > 0 => a, _ => \"no\\n\" };",
+            "(expr (match x (arm (array a _) (if (infix > a 0)) a) (arm _ \"no\\n\")))\n",
+        ),
+        (
+            "let [a, ...r] = xs; let {\"k\": [_]} = h;",
+            "(let (array a (rest r)) xs)\n(let (hash (\"k\" (array _))) h)\n",
+        ),
+        (
+            "x |> f(2, k: (1)) ? a : b;",
+            "(expr (pipe x (ternary (call f 2 (kw k (group 1))) a b)))\n",
+        ),
+        (
+            "for (i in xs) { break; continue; } infixr 70 <^>;",
+            "(expr (for i xs (block (break) (continue))))\n(operator right 70 \"<^>\")\n",
+        ),
+        (
+            "let x: int? = fn(a: [int], ...r: {string: int}) -> fn(int) -> bool { a };",
+            "(let (: x (optional int)) (fn (params (: a (array int)) (rest (: r (hash string int)))) (returns (fn (int) bool)) (block (expr a))))\n",
+        ),
+        ("const n: [int] = xs;", "(const (: n (array int)) xs)\n"),
+    ];
+    for (input, expected) in tests.iter() {
+        assert_eq!(&program_to_sexp(&parse(input)), expected, "{}", input);
+    }
+}
+
+#[test]
+fn test_import_round_trip() {
+    let input = "infixr 70 <^>; /// Doc.\n fn f(a, b = 2, ...c) { for (x in c) { if (x) { break } } a <^> b }
+        let g = |x| match (x) { [1, _] if x => -x, {\"k\": n} => n, _ => (x) |> f(k: 1) };
+        while (g(1) ? true : false) { continue; } return \"s\\n\";
+        /// Max.\nconst n = 3; n /= x -= 1;
+        import \"./m.monkey\" as m; /// Doc.\nexport fn h() { m.f(1).g }
+        let t: {string: [int?]} = fn(a: int, ...r: [(fn() -> int)?]) -> bool { a };
+        const u: int = 1;";
+    let mut parser = crate::parser::Parser::new(crate::lexer::Lexer::new(input));
+    let program = parser.parse_program();
+    let text = program_to_json(&program).pretty();
+    assert_eq!(parse_json_program(&text), Ok(program));
+
+    let mut generator = crate::printer::Generator {
+        state: 0x9e37_79b9_7f4a_7c15,
+    };
+    for _ in 0..200 {
+        let program: Vec<Statement> = (0..3).map(|_| generator.top_level_statement(4)).collect();
+        let text = program_to_json(&program).to_string();
+        assert_eq!(parse_json_program(&text), Ok(program), "{}", text);
+    }
+}
+
+#[test]
+fn test_import_errors() {
+    let tests = [
+        ("{}", "$: expected array, got object"),
+        (
+            r#"[{"type":"Let","pattern":{"type":"Ident","name":"x"}}]"#,
+            "$[0]: missing field value",
+        ),
+        (
+            r#"[{"type":"Expr","expr":{"type":"Prefix","operator":"*","right":{"type":"Int","value":1}}}]"#,
+            "* is not a prefix operator",
+        ),
+        (
+            r#"[{"type":"Expr","expr":{"type":"Infix","operator":"<+>","left":{"type":"Int","value":1},"right":{"type":"Int","value":2}}}]"#,
+            "<+> is not an infix operator",
+        ),
+        (
+            r#"[{"type":"Expr","expr":{"type":"Assign","operator":"==","target":{"type":"Ident","name":"x"},"value":{"type":"Int","value":2}}}]"#,
+            "== is not an assignment operator",
+        ),
+        (
+            r#"[{"type":"Expr","expr":{"type":"Call","function":{"type":"Ident","name":"f"},"arguments":[{"type":"Spread"}]}}]"#,
+            r#"$[0].expr.arguments[0]: unknown argument type "Spread""#,
+        ),
+        (
+            r#"[{"type":"Let","pattern":{"type":"Ident","name":"x"},"annotation":{"type":"Array","item":{"type":"Named","name":"int"}},"value":{"type":"Int","value":1}}]"#,
+            "$[0].annotation: missing field element",
+        ),
+        (
+            r#"[{"type":"Let","pattern":{"type":"Ident","name":"x"},"annotation":{"type":"Tuple"},"value":{"type":"Int","value":1}}]"#,
+            r#"$[0].annotation: unknown type expression "Tuple""#,
+        ),
+        (
+            r#"[{"type":"OperatorDecl","associativity":"left","precedence":300,"operator":"<+>"}]"#,
+            "$[0].precedence: expected precedence between 0 and 255, got 300",
+        ),
+        (
+            r#"[{"type":"Break"},{"type":"Let","pattern":{"type":"Ident","name":"1x"},"value":{"type":"Boolean","value":1}}]"#,
+            "$[1].value.value: expected boolean, got number",
+        ),
+        (
+            r#"[{"type":"Break"},{"type":"Let","pattern":{"type":"Ident","name":"1x"},"value":{"type":"Boolean","value":true}}]"#,
+            "break outside of loop\n\"1x\" is not an identifier",
+        ),
+        ("[1,", "unexpected end of input at offset 3"),
+    ];
+    for (input, expected) in tests.iter() {
+        assert_eq!(
+            parse_json_program(input),
+            Err(expected.to_string()),
+            "{}",
+            input
+        );
+    }
+}
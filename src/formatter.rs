@@ -0,0 +1,253 @@
+//! ソースの整形（`monkey fmt`）
+//!
+//! 最上位の文ごとに整形機で書き直し、文の間のコメントと空行のまとまりはそのまま残す。
+//! 内部にコメントや構文エラーを含む文は、書き直すと失われるものがあるので元の文字列のまま出す。
+//! コメントの周りだけを書き直すことはしないので、関数の本体などにコメントを1つでも書くと、
+//! その最上位の文全体が整形されない。
+//!
+//! 読み飛ばした誤りの部分や余分なトークンは、前後の空白とともに元のまま出す。
+use crate::ast::Statement;
+use crate::cst::{self, SyntaxElement, SyntaxKind, SyntaxNode};
+use crate::printer::{PrintOptions, Printer};
+
+/// ソースを整形する（整形済みのソースを渡すと同じものを返す）
+pub fn format_source(input: &str, options: PrintOptions) -> String {
+    let parse = cst::parse(input);
+    let mut printer = Printer::new(options);
+    let mut statements = parse.program.iter();
+    let elements = parse.root.children_with_tokens();
+    let verbatim = verbatim_elements(&elements);
+    let mut out = String::new();
+    let mut gap = String::new();
+    for (element, verbatim) in elements.iter().zip(verbatim) {
+        let printed = match element {
+            SyntaxElement::Node(node) if node.kind().is_statement() => {
+                // 演算子の宣言を整形機に反映するため、元のまま出す文も一度書き出す
+                let printed = statements
+                    .next()
+                    .map(|stmt| printer.print_statement(&without_doc_comment(stmt)));
+                match is_verbatim(node) {
+                    true => None,
+                    false => printed,
+                }
+            }
+            _ => None,
+        };
+        let text = match element {
+            SyntaxElement::Token(token) => token.text().to_string(),
+            SyntaxElement::Node(node) => node.text(),
+        };
+        // 元のまま出す要素の前後の空白も元のまま出すので、`gap` は空になっている
+        if verbatim {
+            if !out.is_empty() || !is_trivia(element) {
+                out.push_str(&text);
+            }
+            continue;
+        }
+        if is_trivia(element) {
+            gap.push_str(&text);
+            continue;
+        }
+        // 元のまま出した空白の後ろには区切りを足さない
+        if !gap.is_empty() || !out.ends_with(char::is_whitespace) {
+            write_gap(&mut out, &gap);
+        }
+        gap.clear();
+        out.push_str(&printed.unwrap_or(text));
+    }
+    write_gap(&mut out, &gap);
+    out.truncate(out.trim_end().len());
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out
+}
+
+/// `///` コメントは文の前の空白として元のまま残るので、整形機には書かせない
+fn without_doc_comment(stmt: &Statement) -> Statement {
+    let mut stmt = stmt.clone();
+    if let Some(doc) = stmt.doc_mut() {
+        *doc = None;
+    }
+    stmt
+}
+
+fn is_trivia(element: &SyntaxElement) -> bool {
+    matches!(element, SyntaxElement::Token(token) if token.is_trivia())
+}
+
+/// 最上位の要素のうち、区切りも含めて元のまま出すもの
+///
+/// 文でない要素と、その直前の `;` で終わらない文（整形すると `;` が付く）をひと続きとし、
+/// その前後の空白も含める。
+fn verbatim_elements(elements: &[SyntaxElement]) -> Vec<bool> {
+    let mut verbatim = vec![false; elements.len()];
+    let mut next = false;
+    for (i, element) in elements.iter().enumerate().rev() {
+        verbatim[i] = match element {
+            _ if is_trivia(element) => next,
+            SyntaxElement::Node(node) if node.kind().is_statement() => {
+                next && !node.text().ends_with(';')
+            }
+            _ => true,
+        };
+        if !is_trivia(element) {
+            next = verbatim[i];
+        }
+    }
+    let mut previous = false;
+    for (i, element) in elements.iter().enumerate() {
+        match is_trivia(element) {
+            true => verbatim[i] |= previous,
+            false => previous = verbatim[i],
+        }
+    }
+    verbatim
+}
+
+/// 書き直さずに元の文字列のまま出す文か
+fn is_verbatim(node: &SyntaxNode) -> bool {
+    node.children_with_tokens()
+        .into_iter()
+        .any(|element| match element {
+            SyntaxElement::Token(token) => token.is_trivia() && token.text().contains("//"),
+            SyntaxElement::Node(node) => node.kind() == SyntaxKind::Error || is_verbatim(&node),
+        })
+}
+
+/// 文と文の間の空白を、コメントと空行1つ分までの区切りに直して書き出す
+///
+/// 文と同じ行に続くコメントはその行の末尾に残す。
+fn write_gap(out: &mut String, gap: &str) {
+    let mut newlines = 0;
+    for (i, line) in gap.split('\n').enumerate() {
+        if i > 0 {
+            newlines += 1;
+        }
+        let comment = line.trim();
+        if comment.is_empty() {
+            continue;
+        }
+        write_separator(out, newlines);
+        out.push_str(comment);
+        newlines = 0;
+    }
+    // 同じ行に並んでいた文も1行に1文にする
+    write_separator(out, newlines.max(1));
+}
+
+fn write_separator(out: &mut String, newlines: usize) {
+    if out.is_empty() {
+        return;
+    }
+    match newlines {
+        0 => out.push(' '),
+        1 => out.push('\n'),
+        _ => out.push_str("\n\n"),
+    }
+}
+
+#[cfg(test)]
+fn format(input: &str) -> String {
+    format_source(input, PrintOptions::default())
+}
+
+#[test]
+fn test_format_source() {
+    let input =
+        "// header\n\n\n\nlet   x=1;let y = x+2;  // trailing\n\n// about f\nfn f(a){a*2}\nf(x)";
+    let expected =
+        "// header\n\nlet x = 1;\nlet y = x + 2; // trailing\n\n// about f\nfn f(a) { a * 2; }\nf(x);\n";
+    assert_eq!(format(input), expected);
+}
+
+#[test]
+fn test_format_keeps_statements_with_comments() {
+    let input = "let f = fn(x) {\n  // double it\n  x*2\n};\nlet  y=1;";
+    assert_eq!(
+        format(input),
+        "let f = fn(x) {\n  // double it\n  x*2\n};\nlet y = 1;\n"
+    );
+}
+
+#[test]
+fn test_format_keeps_invalid_regions() {
+    let input = "let  a=1;\nlet = 2 ;\nfn g() { let b = ; b }\nlet c=a;";
+    assert_eq!(
+        format(input),
+        "let a = 1;\nlet = 2 ;\nfn g() { let b = ; b }\nlet c = a;\n"
+    );
+    // 読み飛ばした部分と、その前の `;` のない文は区切りも変えない
+    for input in ["foo ) bar;", "f(a) }", "{ let a = 1; a };"].iter() {
+        assert_eq!(format(input), format!("{}\n", input));
+    }
+    assert_eq!(
+        format("let  a=1; ) x;  let b=2;\n\n\n\nb"),
+        "let a = 1; ) x;  let b = 2;\n\nb;\n"
+    );
+}
+
+#[test]
+fn test_format_idempotent() {
+    let input = [
+        "",
+        "// only a comment",
+        "  let a = 1;\n\n\n  // c\n  a  ",
+        "infixl 105 <+>; a <+> (b * c);",
+        "let = ; x\n\n\n// end\n",
+        "if (a) { 1 } else { 2 } // note\n// next\nwhile (x) { x = x; }",
+        "let s = \"a // not a comment\";",
+        "/// doc\n//// not doc\nlet x=1; ///  stray\n\n///\nfn f(){ /// inner\n let a = 1; a }",
+        "import  \"./a.monkey\"as a;\n/// Doc.\nexport   fn f(){ a . b(1) }",
+        "let x:{string:[int]}?=h; fn f(a:int,...r:[(fn()->int)?])->bool{ a }",
+        "const   LIMIT :int=10;",
+        "let g=(a:int,b)=>a;",
+    ];
+    for src in input.iter() {
+        let once = format(src);
+        assert_eq!(format(&once), once, "{:?}", src);
+    }
+}
+
+#[test]
+fn test_format_doc_comments() {
+    let input = "/// The answer.\n///\n/// Really.\nlet   x=42;\n/// Doubles.\nfn f(a){a*2}";
+    assert_eq!(
+        format(input),
+        "/// The answer.\n///\n/// Really.\nlet x = 42;\n/// Doubles.\nfn f(a) { a * 2; }\n"
+    );
+}
+
+#[test]
+fn test_format_long_chains() {
+    use crate::parser::{DEFAULT_MAX_CHAIN, STACK_PER_LEVEL};
+    // 整形機も演算子1つごとに再帰するので、上限の長さの連鎖を辿れるだけのスタックを用意する
+    let run = || {
+        for input in [
+            format!("a{};", " + a".repeat(300)),
+            format!("{}x;", "!".repeat(300)),
+            format!("a{};", " + a".repeat(DEFAULT_MAX_CHAIN)),
+        ]
+        .iter()
+        {
+            let once = format(input);
+            assert!(cst::parse(&once).errors.is_empty());
+            assert_eq!(format(&once), once);
+        }
+        // 演算子が多すぎる式は構文エラーになり、元のまま残る
+        for input in [
+            format!("a{};", " + a".repeat(3000)),
+            format!("{}x;", "- ".repeat(20000)),
+        ]
+        .iter()
+        {
+            assert_eq!(format(input), format!("{}\n", input));
+        }
+    };
+    std::thread::Builder::new()
+        .stack_size(DEFAULT_MAX_CHAIN * STACK_PER_LEVEL)
+        .spawn(run)
+        .unwrap()
+        .join()
+        .unwrap();
+}
@@ -0,0 +1,75 @@
+use crate::ast::Statement;
+
+/// トップレベルの関数宣言をプログラムの先頭へ移動する
+///
+/// 宣言同士の相対的な順序と、それ以外の文の順序は保たれるので、
+/// 相互再帰する関数は定義順に関係なく互いを参照できる。`export fn` も宣言として扱う。
+pub fn hoist_declarations(program: Vec<Statement>) -> Result<Vec<Statement>, String> {
+    let (mut declarations, rest): (Vec<Statement>, Vec<Statement>) =
+        program.into_iter().partition(is_fn_declaration);
+    let mut names: Vec<&str> = vec![];
+    for decl in declarations.iter() {
+        for name in decl.bindings() {
+            if names.contains(&name) {
+                return Err(format!("duplicate function declaration {}", name));
+            }
+            names.push(name);
+        }
+    }
+    declarations.extend(rest);
+    Ok(declarations)
+}
+
+fn is_fn_declaration(stmt: &Statement) -> bool {
+    match stmt {
+        Statement::FnDecl { .. } => true,
+        Statement::Export(stmt) => is_fn_declaration(stmt),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+fn parse(input: &str) -> Vec<Statement> {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    let mut lex = Lexer::new(input);
+    let mut parser = Parser::new(&mut lex);
+    parser.parse_program()
+}
+
+#[test]
+fn test_hoist_declarations() {
+    let input = r"
+is_even(10);
+fn is_even(n) { if (n == 0) { true } else { is_odd(n - 1) } }
+let x = 1;
+export fn is_odd(n) { if (n == 0) { false } else { is_even(n - 1) } }
+";
+    let program = hoist_declarations(parse(input)).unwrap();
+    let names: Vec<String> = program
+        .iter()
+        .map(|stmt| match stmt {
+            Statement::FnDecl { name, .. } => name.clone(),
+            Statement::Export(stmt) => format!("export {}", stmt.bindings()[0]),
+            stmt => stmt.to_string(),
+        })
+        .collect();
+    assert_eq!(
+        names,
+        vec!["is_even", "export is_odd", "is_even(10)", "Let x=1"]
+    );
+}
+
+#[test]
+fn test_hoist_duplicate_declarations() {
+    let program = parse("fn f() { 1 } fn g() { 2 } fn f() { 3 }");
+    assert_eq!(
+        hoist_declarations(program),
+        Err("duplicate function declaration f".to_string())
+    );
+    let program = parse("export fn f() { 1 } fn f() { 3 }");
+    assert_eq!(
+        hoist_declarations(program),
+        Err("duplicate function declaration f".to_string())
+    );
+}
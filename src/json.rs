@@ -0,0 +1,422 @@
+//! 構文木の書き出しと読み込みに使う最小限のJSON（数値は整数のみ）
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<Json>),
+    /// キーの順序を保つ
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    pub fn string(value: &str) -> Json {
+        Json::String(value.to_string())
+    }
+
+    /// 2字下げで整形した文字列
+    pub fn pretty(&self) -> String {
+        let mut out = String::new();
+        self.write_pretty(0, &mut out);
+        out
+    }
+
+    fn write_pretty(&self, depth: usize, out: &mut String) {
+        let indent = |depth: usize| "  ".repeat(depth);
+        match self {
+            Json::Array(items) if !items.is_empty() => {
+                out.push_str("[\n");
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(",\n");
+                    }
+                    out.push_str(&indent(depth + 1));
+                    item.write_pretty(depth + 1, out);
+                }
+                out.push('\n');
+                out.push_str(&indent(depth));
+                out.push(']');
+            }
+            Json::Object(fields) if !fields.is_empty() => {
+                out.push_str("{\n");
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(",\n");
+                    }
+                    out.push_str(&indent(depth + 1));
+                    out.push_str(&quote(key));
+                    out.push_str(": ");
+                    value.write_pretty(depth + 1, out);
+                }
+                out.push('\n');
+                out.push_str(&indent(depth));
+                out.push('}');
+            }
+            value => out.push_str(&value.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(value) => write!(f, "{}", value),
+            Json::String(value) => write!(f, "{}", quote(value)),
+            Json::Array(items) => {
+                let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+                write!(f, "[{}]", items.join(","))
+            }
+            Json::Object(fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(key, value)| format!("{}:{}", quote(key), value))
+                    .collect();
+                write!(f, "{{{}}}", fields.join(","))
+            }
+        }
+    }
+}
+
+impl Json {
+    /// JSONの文字列を読み込む
+    pub fn parse(input: &str) -> Result<Json, String> {
+        let mut reader = Reader {
+            chars: input.chars().collect(),
+            position: 0,
+            depth: 0,
+        };
+        let value = reader.read_value()?;
+        reader.skip_white_space();
+        match reader.peek() {
+            None => Ok(value),
+            Some(ch) => Err(reader.error(&format!("unexpected {:?} after value", ch))),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// 誤りの説明に使う値の種類
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Json::Null => "null",
+            Json::Bool(_) => "boolean",
+            Json::Number(_) => "number",
+            Json::String(_) => "string",
+            Json::Array(_) => "array",
+            Json::Object(_) => "object",
+        }
+    }
+}
+
+/// 配列・オブジェクトの入れ子の深さの上限（書き出した構文木は1段が数段になるので、構文木の上限の4倍）
+pub const MAX_DEPTH: usize = 1024;
+
+struct Reader {
+    chars: Vec<char>,
+    position: usize,
+    /// 読んでいる配列・オブジェクトの入れ子の深さ
+    depth: usize,
+}
+
+impl Reader {
+    fn error(&self, message: &str) -> String {
+        format!("{} at offset {}", message, self.position)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn skip_white_space(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.peek() {
+            Some(ch) if ch == expected => {
+                self.position += 1;
+                Ok(())
+            }
+            Some(ch) => Err(self.error(&format!("expected {:?}, got {:?}", expected, ch))),
+            None => Err(self.error(&format!("expected {:?}, got end of input", expected))),
+        }
+    }
+
+    fn read_value(&mut self) -> Result<Json, String> {
+        self.skip_white_space();
+        match self.peek() {
+            Some('n') => self.read_keyword("null", Json::Null),
+            Some('t') => self.read_keyword("true", Json::Bool(true)),
+            Some('f') => self.read_keyword("false", Json::Bool(false)),
+            Some('"') => Ok(Json::String(self.read_string()?)),
+            Some('[') => self.read_nested(Reader::read_array),
+            Some('{') => self.read_nested(Reader::read_object),
+            Some(ch) if ch == '-' || ch.is_ascii_digit() => self.read_number(),
+            Some(ch) => Err(self.error(&format!("unexpected {:?}", ch))),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    /// 配列・オブジェクトを読む。深すぎる入力はスタックを使い切る前に誤りにする
+    fn read_nested(
+        &mut self,
+        read: fn(&mut Reader) -> Result<Json, String>,
+    ) -> Result<Json, String> {
+        if self.depth >= MAX_DEPTH {
+            return Err(self.error(&format!("nested too deeply (limit {})", MAX_DEPTH)));
+        }
+        self.depth += 1;
+        let value = read(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn read_keyword(&mut self, keyword: &str, value: Json) -> Result<Json, String> {
+        for expected in keyword.chars() {
+            self.expect(expected)?;
+        }
+        Ok(value)
+    }
+
+    fn read_number(&mut self) -> Result<Json, String> {
+        let start = self.position;
+        if self.peek() == Some('-') {
+            self.position += 1;
+        }
+        while self.peek().is_some_and(|ch| ch.is_ascii_digit()) {
+            self.position += 1;
+        }
+        if matches!(self.peek(), Some('.' | 'e' | 'E')) {
+            return Err(self.error("only integer numbers are supported"));
+        }
+        let text: String = self.chars[start..self.position].iter().collect();
+        text.parse()
+            .map(Json::Number)
+            .map_err(|_| self.error(&format!("invalid number {}", text)))
+    }
+
+    fn read_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut value = String::new();
+        loop {
+            let ch = self
+                .peek()
+                .ok_or_else(|| self.error("unterminated string"))?;
+            self.position += 1;
+            match ch {
+                '"' => return Ok(value),
+                '\\' => {
+                    let escaped = self
+                        .peek()
+                        .ok_or_else(|| self.error("unterminated string"))?;
+                    self.position += 1;
+                    value.push(match escaped {
+                        '"' => '"',
+                        '\\' => '\\',
+                        '/' => '/',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => self.read_unicode_escape()?,
+                        ch => return Err(self.error(&format!("invalid escape \\{}", ch))),
+                    });
+                }
+                ch if (ch as u32) < 0x20 => {
+                    return Err(self.error("control character in string"));
+                }
+                ch => value.push(ch),
+            }
+        }
+    }
+
+    /// `\uXXXX`（サロゲートペアは2つ続けて読む）
+    fn read_unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.read_hex4()?;
+        let code = if (0xd800..0xdc00).contains(&high) {
+            self.expect('\\')?;
+            self.expect('u')?;
+            let low = self.read_hex4()?;
+            if !(0xdc00..0xe000).contains(&low) {
+                return Err(self.error("invalid surrogate pair"));
+            }
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+        std::char::from_u32(code).ok_or_else(|| self.error("invalid unicode escape"))
+    }
+
+    fn read_hex4(&mut self) -> Result<u32, String> {
+        let digits: String = self.chars.iter().skip(self.position).take(4).collect();
+        match u32::from_str_radix(&digits, 16) {
+            // `from_str_radix` は先頭の `+` も受け付けるので、4文字とも16進の数字か確かめる
+            Ok(code) if digits.len() == 4 && digits.chars().all(|ch| ch.is_ascii_hexdigit()) => {
+                self.position += 4;
+                Ok(code)
+            }
+            _ => Err(self.error("invalid unicode escape")),
+        }
+    }
+
+    fn read_array(&mut self) -> Result<Json, String> {
+        self.expect('[')?;
+        let mut items = vec![];
+        self.skip_white_space();
+        if self.peek() == Some(']') {
+            self.position += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.read_value()?);
+            self.skip_white_space();
+            match self.peek() {
+                Some(',') => self.position += 1,
+                _ => {
+                    self.expect(']')?;
+                    return Ok(Json::Array(items));
+                }
+            }
+        }
+    }
+
+    fn read_object(&mut self) -> Result<Json, String> {
+        self.expect('{')?;
+        let mut fields: Vec<(String, Json)> = vec![];
+        self.skip_white_space();
+        if self.peek() == Some('}') {
+            self.position += 1;
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.skip_white_space();
+            let key = self.read_string()?;
+            if fields.iter().any(|(k, _)| k == &key) {
+                return Err(self.error(&format!("duplicate key {}", quote(&key))));
+            }
+            self.skip_white_space();
+            self.expect(':')?;
+            fields.push((key, self.read_value()?));
+            self.skip_white_space();
+            match self.peek() {
+                Some(',') => self.position += 1,
+                _ => {
+                    self.expect('}')?;
+                    return Ok(Json::Object(fields));
+                }
+            }
+        }
+    }
+}
+
+/// JSONの文字列リテラル
+pub fn quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for ch in value.chars() {
+        match ch {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => quoted.push(ch),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[test]
+fn test_json_display() {
+    let value = Json::object(vec![
+        ("type", Json::string("Str")),
+        ("value", Json::string("a\"b\n\u{1}")),
+        ("items", Json::Array(vec![Json::Number(-1), Json::Null])),
+        ("empty", Json::Object(vec![])),
+    ]);
+    assert_eq!(
+        value.to_string(),
+        r#"{"type":"Str","value":"a\"b\n\u0001","items":[-1,null],"empty":{}}"#
+    );
+    assert_eq!(
+        Json::Array(vec![Json::Bool(true), Json::Array(vec![])]).pretty(),
+        "[\n  true,\n  []\n]"
+    );
+}
+
+#[test]
+fn test_json_parse() {
+    let input =
+        r#" { "a" : [1, -20, true, false, null], "s": "x\"\\\n\u00e9\ud83d\ude00", "o": {} } "#;
+    let value = Json::parse(input).unwrap();
+    assert_eq!(
+        value,
+        Json::object(vec![
+            (
+                "a",
+                Json::Array(vec![
+                    Json::Number(1),
+                    Json::Number(-20),
+                    Json::Bool(true),
+                    Json::Bool(false),
+                    Json::Null,
+                ])
+            ),
+            ("s", Json::string("x\"\\\n\u{e9}\u{1f600}")),
+            ("o", Json::Object(vec![])),
+        ])
+    );
+    assert_eq!(Json::parse(&value.to_string()), Ok(value.clone()));
+    assert_eq!(Json::parse(&value.pretty()), Ok(value));
+
+    let errors = [
+        ("[1, 2", "expected ']', got end of input at offset 5"),
+        ("1.5", "only integer numbers are supported at offset 1"),
+        ("{\"a\": 1, \"a\": 2}", "duplicate key \"a\" at offset 12"),
+        ("\"\\x\"", "invalid escape \\x at offset 3"),
+        ("[] x", "unexpected 'x' after value at offset 3"),
+        ("nul", "expected 'l', got end of input at offset 3"),
+        ("\"\\u+041\"", "invalid unicode escape at offset 3"),
+        ("\"\\u04\"", "invalid unicode escape at offset 3"),
+    ];
+    for (input, expected) in errors.iter() {
+        assert_eq!(Json::parse(input), Err(expected.to_string()), "{}", input);
+    }
+}
+
+#[test]
+fn test_json_nesting_limit() {
+    let nested = |n: usize| format!("{}0{}", "[{\"a\":".repeat(n / 2), "}]".repeat(n / 2));
+    assert!(Json::parse(&nested(MAX_DEPTH)).is_ok());
+    for input in [nested(MAX_DEPTH + 2), "[".repeat(100000)].iter() {
+        let error = Json::parse(input).unwrap_err();
+        assert!(
+            error.starts_with("nested too deeply (limit 1024)"),
+            "{}",
+            error
+        );
+    }
+}
@@ -1,238 +1,739 @@
-use crate::token::*;
-use std::iter::FromIterator;
-
-/// 字句分析器
-pub struct Lexer {
-    /// 入力文字列
-    input: Vec<char>,
-    /// 入力における現在位置
-    position: usize,
-    /// これから読み込む位置
-    read_position: usize,
-    ///　現在検査中の文字
-    ch: char,
-}
-/// 字句分析器
-impl Lexer {
-    /// 次のトークンを返す
-    pub fn next_token(&mut self) -> Token {
-        let tok: Token;
-        self.skip_white_space();
-        tok = match self.ch {
-            '=' => match self.peek_char() {
-                '=' => {
-                    self.read_char();
-                    Token::EQ
-                }
-                _ => Token::ASSIGN,
-            },
-            ',' => Token::COMMA,
-            ';' => Token::SEMICOLON,
-            '+' => Token::PLUS,
-            '(' => Token::LPAREN,
-            ')' => Token::RPAREN,
-            '{' => Token::LBRACE,
-            '}' => Token::RBRACE,
-            '!' => match self.peek_char() {
-                '=' => {
-                    self.read_char();
-                    Token::NOTEQ
-                }
-                _ => Token::BANG,
-            },
-            '-' => Token::MINUS,
-            '/' => Token::SLASH,
-            '*' => Token::ASTERISK,
-            '<' => Token::LT,
-            '>' => Token::GT,
-            '\u{0}' => Token::EOF,
-            _ => {
-                if self.is_letter() {
-                    return self.lookup_ident();
-                } else if self.is_digit() {
-                    return self.lookup_number();
-                } else {
-                    Token::ILLEGAL(self.ch)
-                }
-            }
-        };
-        self.read_char();
-        return tok;
-    }
-    /// 1文字読み込む
-    fn read_char(&mut self) {
-        if self.read_position >= self.input.len() {
-            self.ch = '\u{0}';
-        } else {
-            self.ch = self.input[self.read_position];
-        }
-        self.position = self.read_position;
-        self.read_position += 1;
-    }
-    /// 1文字見る（positionは進めない）
-    fn peek_char(&self) -> char {
-        self.input[self.read_position]
-    }
-
-    /// 英字判定
-    fn is_letter(&self) -> bool {
-        self.ch.is_alphabetic()
-    }
-
-    /// 数字判定
-    fn is_digit(&self) -> bool {
-        self.ch.is_ascii_digit()
-    }
-    /// 識別子 読み込み
-    fn lookup_ident(&mut self) -> Token {
-        let start_position = self.position;
-        while self.is_letter() {
-            self.read_char();
-        }
-        let ident = String::from_iter(&self.input[start_position..self.position]);
-        match &*ident {
-            "fn" => Token::FUNCTION,
-            "let" => Token::LET,
-            "if" => Token::IF,
-            "else" => Token::ELSE,
-            "return" => Token::RETURN,
-            "true" => Token::TRUE,
-            "false" => Token::FALSE,
-            _ => Token::IDENT(ident),
-        }
-    }
-    /// 数字 読み込み
-    fn lookup_number(&mut self) -> Token {
-        let start_position = self.position;
-        while self.is_digit() {
-            self.read_char();
-        }
-        let number_str = String::from_iter(&self.input[start_position..self.position]);
-        match number_str.parse() {
-            Ok(number) => Token::INT(number),
-            //エラーはありえない
-            Err(_) => Token::ILLEGAL(self.input[start_position]),
-        }
-    }
-    fn skip_white_space(&mut self) {
-        while self.ch.is_ascii_whitespace() {
-            self.read_char();
-        }
-    }
-    /// 字句解析器の生成
-    pub fn new(input: &str) -> Lexer {
-        let mut lex = Lexer {
-            input: input.chars().collect(),
-            position: 0,
-            read_position: 0,
-            ch: 0 as char,
-        };
-        lex.read_char();
-        return lex;
-    }
-}
-
-#[test]
-fn test_next_token() {
-    let input = r"
-let five = 5;
-let ten = 10;
-
-let add = fn(x,y) {
-    x + y;
-};
-let result = add(five, ten);
-!-/*5;
-5 < 10 > 5;
-
-if (5 < 10) {
-    return true;
-} else {
-    return false;
-}
-
-10 == 10;
-10 != 9;
-";
-    let tests = [
-        Token::LET,
-        Token::IDENT("five".to_string()),
-        Token::ASSIGN,
-        Token::INT(5),
-        Token::SEMICOLON,
-        Token::LET,
-        Token::IDENT("ten".to_string()),
-        Token::ASSIGN,
-        Token::INT(10),
-        Token::SEMICOLON,
-        Token::LET,
-        Token::IDENT("add".to_string()),
-        Token::ASSIGN,
-        Token::FUNCTION,
-        Token::LPAREN,
-        Token::IDENT("x".to_string()),
-        Token::COMMA,
-        Token::IDENT("y".to_string()),
-        Token::RPAREN,
-        Token::LBRACE,
-        Token::IDENT("x".to_string()),
-        Token::PLUS,
-        Token::IDENT("y".to_string()),
-        Token::SEMICOLON,
-        Token::RBRACE,
-        Token::SEMICOLON,
-        Token::LET,
-        Token::IDENT("result".to_string()),
-        Token::ASSIGN,
-        Token::IDENT("add".to_string()),
-        Token::LPAREN,
-        Token::IDENT("five".to_string()),
-        Token::COMMA,
-        Token::IDENT("ten".to_string()),
-        Token::RPAREN,
-        Token::SEMICOLON,
-        Token::BANG,
-        Token::MINUS,
-        Token::SLASH,
-        Token::ASTERISK,
-        Token::INT(5),
-        Token::SEMICOLON,
-        Token::INT(5),
-        Token::LT,
-        Token::INT(10),
-        Token::GT,
-        Token::INT(5),
-        Token::SEMICOLON,
-        Token::IF,
-        Token::LPAREN,
-        Token::INT(5),
-        Token::LT,
-        Token::INT(10),
-        Token::RPAREN,
-        Token::LBRACE,
-        Token::RETURN,
-        Token::TRUE,
-        Token::SEMICOLON,
-        Token::RBRACE,
-        Token::ELSE,
-        Token::LBRACE,
-        Token::RETURN,
-        Token::FALSE,
-        Token::SEMICOLON,
-        Token::RBRACE,
-        Token::INT(10),
-        Token::EQ,
-        Token::INT(10),
-        Token::SEMICOLON,
-        Token::INT(10),
-        Token::NOTEQ,
-        Token::INT(9),
-        Token::SEMICOLON,
-        Token::EOF,
-    ];
-    let mut lex = Lexer::new(input);
-    for test in tests.iter() {
-        let tok = lex.next_token();
-        assert_eq!(tok, *test);
-    }
-}
+use crate::token::*;
+use std::iter::FromIterator;
+
+/// 字句分析器
+pub struct Lexer {
+    /// 入力文字列
+    input: Vec<char>,
+    /// 入力における現在位置
+    position: usize,
+    /// これから読み込む位置
+    read_position: usize,
+    ///　現在検査中の文字
+    ch: char,
+    /// 利用者定義の演算子記号（長いものから順に照合する）
+    operators: Vec<String>,
+    /// 結合性宣言 `infixl 65 <+>` の何トークン目まで読んだか
+    fixity_decl: usize,
+    /// 最後に返したトークンの範囲
+    span: Span,
+    /// 最後に返したトークンの直前の `///` コメント
+    doc_comment: Option<String>,
+    /// 最後に返したトークンの前に、空行や普通のコメントで離れた `///` コメントがあった
+    detached_doc_comment: bool,
+}
+/// 字句分析器
+impl Lexer {
+    /// 次のトークンを返す
+    pub fn next_token(&mut self) -> Token {
+        let trivia_start = self.position.min(self.input.len());
+        self.skip_white_space();
+        let start = self.position.min(self.input.len());
+        let trivia = String::from_iter(&self.input[trivia_start..start]);
+        self.doc_comment = doc_comment(&trivia);
+        self.detached_doc_comment = has_detached_doc_comment(&trivia);
+        let tok = if self.fixity_decl == 2 && is_symbol(self.ch) {
+            self.read_operator_declaration()
+        } else if let Some(symbol) = self.match_operator() {
+            for _ in symbol.chars() {
+                self.read_char();
+            }
+            Token::OPERATOR(symbol)
+        } else {
+            self.read_token()
+        };
+        self.fixity_decl = match (self.fixity_decl, &tok) {
+            (_, Token::INFIXL) | (_, Token::INFIXR) | (_, Token::INFIX) => 1,
+            (1, Token::INT(_)) => 2,
+            _ => 0,
+        };
+        self.span = Span::new(start, self.position.min(self.input.len()));
+        tok
+    }
+    /// 最後に返したトークンの範囲（トークン間の空白は含まない）
+    pub fn span(&self) -> Span {
+        self.span
+    }
+    /// 最後に返したトークンの直前にある `///` コメントの本文（行ごとに改行で区切る）
+    pub fn doc_comment(&self) -> Option<&str> {
+        self.doc_comment.as_deref()
+    }
+    /// 最後に返したトークンの前に、`doc_comment` に含めなかった `///` コメントがあるか
+    pub fn detached_doc_comment(&self) -> bool {
+        self.detached_doc_comment
+    }
+    /// 利用者定義の演算子記号を登録する
+    ///
+    /// 登録した記号は、この字句解析器がこれ以降に読む入力全体で1つのトークンになる。
+    /// `//` を含む記号はコメントの始まりと区別できないので登録しない。
+    pub fn add_operator(&mut self, symbol: &str) {
+        if !Lexer::contains_comment_marker(symbol) && !self.operators.iter().any(|op| op == symbol)
+        {
+            self.operators.push(symbol.to_string());
+            self.operators
+                .sort_by_key(|op| std::cmp::Reverse(op.chars().count()));
+        }
+    }
+    /// 現在位置から始まる登録済みの演算子記号
+    fn match_operator(&self) -> Option<String> {
+        let rest = &self.input[self.position.min(self.input.len())..];
+        self.operators
+            .iter()
+            .find(|op| {
+                let op: Vec<char> = op.chars().collect();
+                rest.starts_with(&op)
+            })
+            .cloned()
+    }
+    /// 結合性宣言の演算子記号 読み込み（組み込みの記号でなければ登録する）
+    fn read_operator_declaration(&mut self) -> Token {
+        let start_position = self.position;
+        while is_symbol(self.ch) {
+            self.read_char();
+        }
+        let symbol = String::from_iter(&self.input[start_position..self.position]);
+        // `//` を含む記号は登録せず、構文解析器に誤りとして報告させる
+        if Lexer::contains_comment_marker(&symbol) {
+            return Token::OPERATOR(symbol);
+        }
+        if let Some(tok) = Lexer::builtin_symbol(&symbol) {
+            return tok;
+        }
+        self.add_operator(&symbol);
+        Token::OPERATOR(symbol)
+    }
+    /// `symbol` が組み込みの記号1つ分ならそのトークン
+    pub fn builtin_symbol(symbol: &str) -> Option<Token> {
+        let mut lex = Lexer::new(symbol);
+        match (lex.read_token(), lex.read_token()) {
+            (Token::ILLEGAL(_), _) | (Token::EOF, _) => None,
+            (tok, Token::EOF) => Some(tok),
+            _ => None,
+        }
+    }
+    /// `name` が識別子として字句解析されるか（キーワードは除く）
+    pub fn is_identifier(name: &str) -> bool {
+        let mut lex = Lexer::new(name);
+        lex.next_token() == Token::IDENT(name.to_string()) && lex.next_token() == Token::EOF
+    }
+    /// `symbol` が利用者定義の演算子として宣言できる記号か
+    pub fn is_operator_symbol(symbol: &str) -> bool {
+        !symbol.is_empty()
+            && symbol.chars().all(is_symbol)
+            && Lexer::builtin_symbol(symbol).is_none()
+            && !Lexer::contains_comment_marker(symbol)
+    }
+    /// `symbol` の途中からコメント `//` が始まるか
+    pub fn contains_comment_marker(symbol: &str) -> bool {
+        symbol.contains("//")
+    }
+    /// 組み込みのトークンを1つ読み込む
+    fn read_token(&mut self) -> Token {
+        self.skip_white_space();
+        let tok = match self.ch {
+            '=' => match self.peek_char() {
+                '=' => {
+                    self.read_char();
+                    Token::EQ
+                }
+                '>' => {
+                    self.read_char();
+                    Token::FATARROW
+                }
+                _ => Token::ASSIGN,
+            },
+            ',' => Token::COMMA,
+            ';' => Token::SEMICOLON,
+            '+' => match self.peek_char() {
+                '=' => {
+                    self.read_char();
+                    Token::PLUSASSIGN
+                }
+                _ => Token::PLUS,
+            },
+            '(' => Token::LPAREN,
+            ')' => Token::RPAREN,
+            '{' => Token::LBRACE,
+            '}' => Token::RBRACE,
+            '[' => Token::LBRACKET,
+            ']' => Token::RBRACKET,
+            ':' => Token::COLON,
+            '?' => Token::QUESTION,
+            '|' => match self.peek_char() {
+                '>' => {
+                    self.read_char();
+                    Token::PIPELINE
+                }
+                _ => Token::PIPE,
+            },
+            '.' => match (self.peek_char(), self.input.get(self.read_position + 1)) {
+                ('.', Some('.')) => {
+                    self.read_char();
+                    self.read_char();
+                    Token::ELLIPSIS
+                }
+                _ => Token::DOT,
+            },
+            '"' => self.read_string(),
+            '!' => match self.peek_char() {
+                '=' => {
+                    self.read_char();
+                    Token::NOTEQ
+                }
+                _ => Token::BANG,
+            },
+            '-' => match self.peek_char() {
+                '=' => {
+                    self.read_char();
+                    Token::MINUSASSIGN
+                }
+                '>' => {
+                    self.read_char();
+                    Token::ARROW
+                }
+                _ => Token::MINUS,
+            },
+            '/' => match self.peek_char() {
+                '=' => {
+                    self.read_char();
+                    Token::SLASHASSIGN
+                }
+                _ => Token::SLASH,
+            },
+            '*' => match self.peek_char() {
+                '*' => {
+                    self.read_char();
+                    Token::POWER
+                }
+                '=' => {
+                    self.read_char();
+                    Token::ASTERISKASSIGN
+                }
+                _ => Token::ASTERISK,
+            },
+            '<' => match self.peek_char() {
+                '<' => {
+                    self.read_char();
+                    Token::LSHIFT
+                }
+                _ => Token::LT,
+            },
+            '>' => match self.peek_char() {
+                '>' => {
+                    self.read_char();
+                    Token::RSHIFT
+                }
+                _ => Token::GT,
+            },
+            '&' => Token::AMPERSAND,
+            '^' => Token::CARET,
+            '~' => Token::TILDE,
+            '\u{0}' => Token::EOF,
+            _ => {
+                if self.is_letter() {
+                    return self.lookup_ident();
+                } else if self.is_digit() {
+                    return self.lookup_number();
+                } else {
+                    Token::ILLEGAL(self.ch)
+                }
+            }
+        };
+        self.read_char();
+        tok
+    }
+    /// 1文字読み込む
+    fn read_char(&mut self) {
+        if self.read_position >= self.input.len() {
+            self.ch = '\u{0}';
+        } else {
+            self.ch = self.input[self.read_position];
+        }
+        self.position = self.read_position;
+        self.read_position += 1;
+    }
+    /// 1文字見る（positionは進めない）
+    fn peek_char(&self) -> char {
+        match self.input.get(self.read_position) {
+            Some(ch) => *ch,
+            None => '\u{0}',
+        }
+    }
+
+    /// 英字判定
+    fn is_letter(&self) -> bool {
+        self.ch.is_alphabetic() || self.ch == '_'
+    }
+
+    /// 数字判定
+    fn is_digit(&self) -> bool {
+        self.ch.is_ascii_digit()
+    }
+    /// 識別子 読み込み
+    fn lookup_ident(&mut self) -> Token {
+        let start_position = self.position;
+        while self.is_letter() {
+            self.read_char();
+        }
+        let ident = String::from_iter(&self.input[start_position..self.position]);
+        match &*ident {
+            "fn" => Token::FUNCTION,
+            "let" => Token::LET,
+            "const" => Token::CONST,
+            "if" => Token::IF,
+            "else" => Token::ELSE,
+            "return" => Token::RETURN,
+            "true" => Token::TRUE,
+            "false" => Token::FALSE,
+            "while" => Token::WHILE,
+            "for" => Token::FOR,
+            "in" => Token::IN,
+            "break" => Token::BREAK,
+            "continue" => Token::CONTINUE,
+            "match" => Token::MATCH,
+            "macro" => Token::MACRO,
+            "import" => Token::IMPORT,
+            "export" => Token::EXPORT,
+            "as" => Token::AS,
+            "_" => Token::UNDERSCORE,
+            "infixl" => Token::INFIXL,
+            "infixr" => Token::INFIXR,
+            "infix" => Token::INFIX,
+            _ => Token::IDENT(ident),
+        }
+    }
+    /// 数字 読み込み
+    fn lookup_number(&mut self) -> Token {
+        let start_position = self.position;
+        while self.is_digit() {
+            self.read_char();
+        }
+        let number_str = String::from_iter(&self.input[start_position..self.position]);
+        match number_str.parse() {
+            Ok(number) => Token::INT(number),
+            //エラーはありえない
+            Err(_) => Token::ILLEGAL(self.input[start_position]),
+        }
+    }
+    /// 文字列 読み込み（終端の `"` で止まる）
+    fn read_string(&mut self) -> Token {
+        let start_position = self.position;
+        let mut value = String::new();
+        loop {
+            self.read_char();
+            match self.ch {
+                '"' => return Token::STRING(value),
+                '\u{0}' => return Token::ILLEGAL(self.input[start_position]),
+                '\\' => {
+                    self.read_char();
+                    match self.ch {
+                        'n' => value.push('\n'),
+                        't' => value.push('\t'),
+                        '\u{0}' => return Token::ILLEGAL(self.input[start_position]),
+                        ch => value.push(ch),
+                    }
+                }
+                ch => value.push(ch),
+            }
+        }
+    }
+    /// 空白と `//` から行末までのコメントを読み飛ばす
+    fn skip_white_space(&mut self) {
+        loop {
+            if self.ch.is_ascii_whitespace() {
+                self.read_char();
+            } else if self.ch == '/' && self.peek_char() == '/' {
+                while self.ch != '\n' && self.ch != '\u{0}' {
+                    self.read_char();
+                }
+            } else {
+                break;
+            }
+        }
+    }
+    /// 字句解析器の生成
+    pub fn new(input: &str) -> Lexer {
+        let mut lex = Lexer {
+            input: input.chars().collect(),
+            position: 0,
+            read_position: 0,
+            ch: 0 as char,
+            operators: vec![],
+            fixity_decl: 0,
+            span: Span::default(),
+            doc_comment: None,
+            detached_doc_comment: false,
+        };
+        lex.read_char();
+        lex
+    }
+}
+
+/// トークン間の空白のうち、トークンの直前に続く `///` コメントの行の本文
+///
+/// 空行や普通のコメントを挟んだそれより前の `///` コメントは含めない。
+/// `////` で始まる行は普通のコメントとして扱う。`///` の直後の空白1つは本文に含めない。
+pub fn doc_comment(trivia: &str) -> Option<String> {
+    let mut lines: Vec<&str> = attached_lines(trivia).filter_map(doc_line).collect();
+    lines.reverse();
+    match lines.is_empty() {
+        true => None,
+        false => Some(lines.join("\n")),
+    }
+}
+
+/// トークン間の空白に、`doc_comment` に含めない `///` コメントがあるか
+pub fn has_detached_doc_comment(trivia: &str) -> bool {
+    let attached = attached_lines(trivia).filter_map(doc_line).count();
+    trivia.split('\n').filter_map(doc_line).count() > attached
+}
+
+/// トークンの直前の行から、`///` コメントの行が続くだけ遡る（トークンの前の字下げは除く）
+fn attached_lines(trivia: &str) -> impl Iterator<Item = &str> {
+    let mut lines = trivia.rsplit('\n').peekable();
+    if lines.peek().is_some_and(|line| line.trim().is_empty()) {
+        lines.next();
+    }
+    lines.take_while(|line| doc_line(line).is_some())
+}
+
+/// `///` コメントの行ならその本文
+fn doc_line(line: &str) -> Option<&str> {
+    let line = line.trim();
+    match line.strip_prefix("///") {
+        Some(body) if !body.starts_with('/') => Some(body.strip_prefix(' ').unwrap_or(body)),
+        _ => None,
+    }
+}
+
+/// 演算子記号を構成しうる文字か
+fn is_symbol(ch: char) -> bool {
+    "!$%&*+-./<=>?@^|~:".contains(ch)
+}
+
+#[test]
+fn test_next_token() {
+    let input = r"
+let five = 5;
+let ten = 10;
+
+let add = fn(x,y) {
+    x + y;
+};
+let result = add(five, ten);
+!-/*5;
+5 < 10 > 5;
+
+if (5 < 10) {
+    return true;
+} else {
+    return false;
+}
+
+10 == 10;
+10 != 9;
+";
+    let tests = [
+        Token::LET,
+        Token::IDENT("five".to_string()),
+        Token::ASSIGN,
+        Token::INT(5),
+        Token::SEMICOLON,
+        Token::LET,
+        Token::IDENT("ten".to_string()),
+        Token::ASSIGN,
+        Token::INT(10),
+        Token::SEMICOLON,
+        Token::LET,
+        Token::IDENT("add".to_string()),
+        Token::ASSIGN,
+        Token::FUNCTION,
+        Token::LPAREN,
+        Token::IDENT("x".to_string()),
+        Token::COMMA,
+        Token::IDENT("y".to_string()),
+        Token::RPAREN,
+        Token::LBRACE,
+        Token::IDENT("x".to_string()),
+        Token::PLUS,
+        Token::IDENT("y".to_string()),
+        Token::SEMICOLON,
+        Token::RBRACE,
+        Token::SEMICOLON,
+        Token::LET,
+        Token::IDENT("result".to_string()),
+        Token::ASSIGN,
+        Token::IDENT("add".to_string()),
+        Token::LPAREN,
+        Token::IDENT("five".to_string()),
+        Token::COMMA,
+        Token::IDENT("ten".to_string()),
+        Token::RPAREN,
+        Token::SEMICOLON,
+        Token::BANG,
+        Token::MINUS,
+        Token::SLASH,
+        Token::ASTERISK,
+        Token::INT(5),
+        Token::SEMICOLON,
+        Token::INT(5),
+        Token::LT,
+        Token::INT(10),
+        Token::GT,
+        Token::INT(5),
+        Token::SEMICOLON,
+        Token::IF,
+        Token::LPAREN,
+        Token::INT(5),
+        Token::LT,
+        Token::INT(10),
+        Token::RPAREN,
+        Token::LBRACE,
+        Token::RETURN,
+        Token::TRUE,
+        Token::SEMICOLON,
+        Token::RBRACE,
+        Token::ELSE,
+        Token::LBRACE,
+        Token::RETURN,
+        Token::FALSE,
+        Token::SEMICOLON,
+        Token::RBRACE,
+        Token::INT(10),
+        Token::EQ,
+        Token::INT(10),
+        Token::SEMICOLON,
+        Token::INT(10),
+        Token::NOTEQ,
+        Token::INT(9),
+        Token::SEMICOLON,
+        Token::EOF,
+    ];
+    let mut lex = Lexer::new(input);
+    for test in tests.iter() {
+        let tok = lex.next_token();
+        assert_eq!(tok, *test);
+    }
+}
+
+#[test]
+fn test_loop_keywords() {
+    let input = "while for in break continue";
+    let tests = [
+        Token::WHILE,
+        Token::FOR,
+        Token::IN,
+        Token::BREAK,
+        Token::CONTINUE,
+        Token::EOF,
+    ];
+    let mut lex = Lexer::new(input);
+    for test in tests.iter() {
+        assert_eq!(lex.next_token(), *test);
+    }
+}
+
+#[test]
+fn test_assignment_tokens() {
+    let input = "const x = 1; x += 2; x -= 3; x *= x ** 2; x /= 4;";
+    let tokens: Vec<Token> = {
+        let mut lex = Lexer::new(input);
+        std::iter::from_fn(|| match lex.next_token() {
+            Token::EOF => None,
+            tok => Some(tok),
+        })
+        .filter(|tok| !matches!(tok, Token::IDENT(_) | Token::INT(_) | Token::SEMICOLON))
+        .collect()
+    };
+    assert_eq!(
+        tokens,
+        vec![
+            Token::CONST,
+            Token::ASSIGN,
+            Token::PLUSASSIGN,
+            Token::MINUSASSIGN,
+            Token::ASTERISKASSIGN,
+            Token::POWER,
+            Token::SLASHASSIGN,
+        ]
+    );
+}
+
+#[test]
+fn test_type_annotation_tokens() {
+    let mut lex = Lexer::new("fn(a: [int]) -> bool? x - > y");
+    let tokens: Vec<Token> = std::iter::from_fn(|| match lex.next_token() {
+        Token::EOF => None,
+        tok => Some(tok),
+    })
+    .collect();
+    assert_eq!(
+        tokens[7..],
+        [
+            Token::RPAREN,
+            Token::ARROW,
+            Token::IDENT("bool".to_string()),
+            Token::QUESTION,
+            Token::IDENT("x".to_string()),
+            Token::MINUS,
+            Token::GT,
+            Token::IDENT("y".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_macro_token() {
+    let mut lex = Lexer::new("macro(a) { quote(a) } macros");
+    assert_eq!(lex.next_token(), Token::MACRO);
+    assert_eq!(lex.next_token(), Token::LPAREN);
+    for _ in 0..8 {
+        lex.next_token();
+    }
+    assert_eq!(lex.next_token(), Token::IDENT("macros".to_string()));
+}
+
+#[test]
+fn test_match_tokens() {
+    let input = r#"match (x) { [a, _b] => "a\"b", {"k": v} => v, _ => 0 }"#;
+    let tests = [
+        Token::MATCH,
+        Token::LPAREN,
+        Token::IDENT("x".to_string()),
+        Token::RPAREN,
+        Token::LBRACE,
+        Token::LBRACKET,
+        Token::IDENT("a".to_string()),
+        Token::COMMA,
+        Token::IDENT("_b".to_string()),
+        Token::RBRACKET,
+        Token::FATARROW,
+        Token::STRING("a\"b".to_string()),
+        Token::COMMA,
+        Token::LBRACE,
+        Token::STRING("k".to_string()),
+        Token::COLON,
+        Token::IDENT("v".to_string()),
+        Token::RBRACE,
+        Token::FATARROW,
+        Token::IDENT("v".to_string()),
+        Token::COMMA,
+        Token::UNDERSCORE,
+        Token::FATARROW,
+        Token::INT(0),
+        Token::RBRACE,
+        Token::EOF,
+    ];
+    let mut lex = Lexer::new(input);
+    for test in tests.iter() {
+        assert_eq!(lex.next_token(), *test);
+    }
+}
+
+#[test]
+fn test_operator_tokens() {
+    let input = "a ** b * c & d | e ^ ~f << g >> h |> i";
+    let tests = [
+        Token::IDENT("a".to_string()),
+        Token::POWER,
+        Token::IDENT("b".to_string()),
+        Token::ASTERISK,
+        Token::IDENT("c".to_string()),
+        Token::AMPERSAND,
+        Token::IDENT("d".to_string()),
+        Token::PIPE,
+        Token::IDENT("e".to_string()),
+        Token::CARET,
+        Token::TILDE,
+        Token::IDENT("f".to_string()),
+        Token::LSHIFT,
+        Token::IDENT("g".to_string()),
+        Token::RSHIFT,
+        Token::IDENT("h".to_string()),
+        Token::PIPELINE,
+        Token::IDENT("i".to_string()),
+        Token::EOF,
+    ];
+    let mut lex = Lexer::new(input);
+    for test in tests.iter() {
+        assert_eq!(lex.next_token(), *test);
+    }
+}
+
+#[test]
+fn test_operator_declaration() {
+    let input = "infixl 65 <+>; a <+> b < c; infix 30 ==;";
+    let tests = [
+        Token::INFIXL,
+        Token::INT(65),
+        Token::OPERATOR("<+>".to_string()),
+        Token::SEMICOLON,
+        Token::IDENT("a".to_string()),
+        Token::OPERATOR("<+>".to_string()),
+        Token::IDENT("b".to_string()),
+        Token::LT,
+        Token::IDENT("c".to_string()),
+        Token::SEMICOLON,
+        Token::INFIX,
+        Token::INT(30),
+        Token::EQ,
+        Token::SEMICOLON,
+        Token::EOF,
+    ];
+    let mut lex = Lexer::new(input);
+    for test in tests.iter() {
+        assert_eq!(lex.next_token(), *test);
+    }
+}
+
+#[test]
+fn test_token_span() {
+    let mut lex = Lexer::new("let  x = \"hi\";");
+    let expected = [(0, 3), (5, 6), (7, 8), (9, 13), (13, 14), (14, 14)];
+    for (start, end) in expected.iter() {
+        lex.next_token();
+        assert_eq!(lex.span(), Span::new(*start, *end));
+    }
+}
+
+#[test]
+fn test_line_comment() {
+    let mut lex = Lexer::new("let x = 1; // one\n// whole line\nx / 2 //");
+    let expected = [
+        Token::LET,
+        Token::IDENT("x".to_string()),
+        Token::ASSIGN,
+        Token::INT(1),
+        Token::SEMICOLON,
+        Token::IDENT("x".to_string()),
+        Token::SLASH,
+        Token::INT(2),
+        Token::EOF,
+    ];
+    for tok in expected.iter() {
+        assert_eq!(&lex.next_token(), tok);
+    }
+}
+
+#[test]
+fn test_doc_comment() {
+    let mut lex = Lexer::new("/// Adds one.\n///\n///   Indented.\nlet //// not doc\n// plain\nx");
+    assert_eq!(lex.next_token(), Token::LET);
+    assert_eq!(lex.doc_comment(), Some("Adds one.\n\n  Indented."));
+    assert_eq!(lex.next_token(), Token::IDENT("x".to_string()));
+    assert_eq!(lex.doc_comment(), None);
+    assert_eq!(
+        doc_comment(" /// trailing\n  /// two"),
+        Some("trailing\ntwo".to_string())
+    );
+    // 空行や普通のコメントで離れた `///` は、トークンの説明に含めない
+    let mut lex = Lexer::new("/// a\n\n// note\n/// b\nlet x\n/// c\n\ny");
+    assert_eq!(lex.next_token(), Token::LET);
+    assert_eq!(lex.doc_comment(), Some("b"));
+    assert!(lex.detached_doc_comment());
+    lex.next_token();
+    assert!(!lex.detached_doc_comment());
+    assert_eq!(lex.next_token(), Token::IDENT("y".to_string()));
+    assert_eq!(lex.doc_comment(), None);
+    assert!(lex.detached_doc_comment());
+}
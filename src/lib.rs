@@ -1,5 +1,22 @@
 pub mod ast;
+pub mod builder;
+pub mod const_check;
+pub mod cst;
+#[cfg(feature = "export")]
+pub mod export;
+pub mod formatter;
+pub mod hoist;
+#[cfg(feature = "export")]
+pub mod json;
 pub mod lexer;
+pub mod macro_expand;
+pub mod match_check;
+pub mod module;
+pub mod node_id;
+pub mod operator;
 pub mod parser;
+pub mod printer;
 pub mod repl;
 pub mod token;
+pub mod token_source;
+pub mod validate;
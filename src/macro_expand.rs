@@ -0,0 +1,561 @@
+//! マクロの定義と展開
+//!
+//! 評価の前に、構文木を2段階で書き換える。
+//! 1. `MacroEnv::define_macros` がトップレベルの `let name = macro(...) { ... };` を取り除いて集める
+//! 2. `expand_macros` がマクロの呼び出しを、実引数を `quote` した構文木を渡して本体を評価した結果で置き換える
+//!
+//! マクロ本体は展開時に小さな評価器で評価する。扱えるのは整数・真偽値・文字列・`quote` した構文木と、
+//! `let`、`return`、`if`、三項演算子、前置・中置の算術と比較、`quote(...)` の呼び出しまで。
+//! `quote` の中の `unquote(expr)` は、`expr` を評価した値を構文木にしたものに置き換わる。
+//! `quote` の中にさらに `quote` を書くと、内側の `quote` の中の `unquote` はそのまま残る
+//! （`unquote` の中に `unquote` を重ねた分だけ外側の `quote` で評価する）。
+use crate::ast::{fold_expression, Fold};
+use crate::ast::{Argument, Expression, Parameter, Pattern, Statement};
+use crate::token::Token;
+use std::collections::HashMap;
+
+/// マクロが別のマクロを呼ぶ展開の深さの上限（自分自身を展開し続けるマクロを止める）
+const MAX_EXPANSION_DEPTH: usize = 100;
+
+/// 展開時に評価した値
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum MacroValue {
+    Int(i64),
+    Boolean(bool),
+    Str(String),
+    /// `quote(expr)` の結果
+    Quote(Expression),
+    Null,
+}
+
+impl MacroValue {
+    fn type_name(&self) -> &'static str {
+        match self {
+            MacroValue::Int(_) => "integer",
+            MacroValue::Boolean(_) => "boolean",
+            MacroValue::Str(_) => "string",
+            MacroValue::Quote(_) => "quote",
+            MacroValue::Null => "null",
+        }
+    }
+
+    fn is_truthy(&self) -> bool {
+        !matches!(self, MacroValue::Boolean(false) | MacroValue::Null)
+    }
+
+    /// `unquote` で構文木に埋め込む形にする
+    fn into_expression(self) -> Result<Expression, String> {
+        match self {
+            // 負の数は `-` と正の整数で表すので、符号を反転できない最小値は書けない
+            MacroValue::Int(i64::MIN) => Err(format!("cannot unquote integer {}", i64::MIN)),
+            MacroValue::Int(value) if value < 0 => Ok(Expression::Prefix {
+                operator: Token::MINUS,
+                right: Box::new(Expression::Int(-value)),
+            }),
+            MacroValue::Int(value) => Ok(Expression::Int(value)),
+            MacroValue::Boolean(value) => Ok(Expression::Boolean(value)),
+            MacroValue::Str(value) => Ok(Expression::Str(value)),
+            MacroValue::Quote(expr) => Ok(expr),
+            MacroValue::Null => Err("cannot unquote null".to_string()),
+        }
+    }
+}
+
+/// 定義済みのマクロ
+#[derive(Debug, Default, Clone)]
+pub struct MacroEnv {
+    macros: HashMap<String, (Vec<Parameter>, Expression)>,
+}
+
+impl MacroEnv {
+    pub fn new() -> MacroEnv {
+        MacroEnv::default()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.macros.contains_key(name)
+    }
+
+    /// トップレベルのマクロ定義を登録し、プログラムから取り除く
+    pub fn define_macros(&mut self, program: &mut Vec<Statement>) {
+        let macros = &mut self.macros;
+        program.retain(|stmt| match stmt {
+            Statement::Let {
+                pattern: Pattern::Ident(identifier),
+                expr: Expression::MacroLiteral { parameters, body },
+                ..
+            } => {
+                macros.insert(identifier.clone(), (parameters.clone(), *body.clone()));
+                false
+            }
+            _ => true,
+        });
+    }
+}
+
+/// マクロの呼び出しをすべて展開する
+pub fn expand_macros(program: Vec<Statement>, env: &MacroEnv) -> Result<Vec<Statement>, String> {
+    let mut expander = Expander {
+        env,
+        depth: 0,
+        error: None,
+    };
+    let program: Vec<Statement> = program
+        .into_iter()
+        .map(|stmt| expander.fold_statement(stmt))
+        .collect();
+    match expander.error {
+        Some(error) => Err(error),
+        None => Ok(program),
+    }
+}
+
+struct Expander<'a> {
+    env: &'a MacroEnv,
+    depth: usize,
+    /// 最初の誤り（以降は書き換えずにそのまま返す）
+    error: Option<String>,
+}
+
+impl Expander<'_> {
+    fn expand_call(&self, name: &str, arguments: &[Argument]) -> Result<Expression, String> {
+        let (parameters, body) = &self.env.macros[name];
+        if arguments.len() > parameters.len() {
+            return Err(format!(
+                "macro {} takes {} arguments, got {}",
+                name,
+                parameters.len(),
+                arguments.len()
+            ));
+        }
+        let mut evaluator = Evaluator {
+            env: HashMap::new(),
+        };
+        for (i, parameter) in parameters.iter().enumerate() {
+            let argument = match (arguments.get(i), &parameter.default) {
+                (Some(Argument::Positional(value)), _) => value,
+                (Some(Argument::Keyword(keyword, _)), _) => {
+                    return Err(format!(
+                        "macro {} does not take keyword argument {}",
+                        name, keyword
+                    ))
+                }
+                (None, Some(default)) => default,
+                (None, None) => {
+                    return Err(format!(
+                        "macro {} is missing argument {}",
+                        name, parameter.name
+                    ))
+                }
+            };
+            evaluator
+                .env
+                .insert(parameter.name.clone(), MacroValue::Quote(argument.clone()));
+        }
+        match evaluator.eval(body) {
+            Ok(value) | Err(Unwind::Return(value)) => match value {
+                MacroValue::Quote(expr) => Ok(expr),
+                value => Err(format!(
+                    "macro {} must return a quoted expression, got {}",
+                    name,
+                    value.type_name()
+                )),
+            },
+            Err(Unwind::Error(error)) => Err(format!("in macro {}: {}", name, error)),
+        }
+    }
+}
+
+impl Fold for Expander<'_> {
+    fn fold_expression(&mut self, expr: Expression) -> Expression {
+        if self.error.is_some() {
+            return expr;
+        }
+        let name = match &expr {
+            Expression::Call { function, .. } => match &**function {
+                Expression::Ident(name) if self.env.contains(name) => name.clone(),
+                _ => return fold_expression(self, expr),
+            },
+            _ => return fold_expression(self, expr),
+        };
+        if self.depth == MAX_EXPANSION_DEPTH {
+            self.error = Some(format!(
+                "expansion of macro {} exceeded {} levels",
+                name, MAX_EXPANSION_DEPTH
+            ));
+            return expr;
+        }
+        let arguments = match &expr {
+            Expression::Call { arguments, .. } => arguments,
+            _ => unreachable!(),
+        };
+        match self.expand_call(&name, arguments) {
+            // 展開結果に含まれるマクロの呼び出しも続けて展開する
+            Ok(expanded) => {
+                self.depth += 1;
+                let expanded = self.fold_expression(expanded);
+                self.depth -= 1;
+                expanded
+            }
+            Err(error) => {
+                self.error = Some(error);
+                expr
+            }
+        }
+    }
+}
+
+/// 評価を途中で抜ける理由
+enum Unwind {
+    Return(MacroValue),
+    Error(String),
+}
+
+fn error<T>(message: String) -> Result<T, Unwind> {
+    Err(Unwind::Error(message))
+}
+
+/// マクロ本体の評価器
+struct Evaluator {
+    env: HashMap<String, MacroValue>,
+}
+
+impl Evaluator {
+    fn eval(&mut self, expr: &Expression) -> Result<MacroValue, Unwind> {
+        match expr {
+            Expression::Int(value) => Ok(MacroValue::Int(*value)),
+            Expression::Boolean(value) => Ok(MacroValue::Boolean(*value)),
+            Expression::Str(value) => Ok(MacroValue::Str(value.clone())),
+            Expression::Ident(name) => match self.env.get(name) {
+                Some(value) => Ok(value.clone()),
+                None => error(format!("identifier {} is not defined", name)),
+            },
+            Expression::Grouped(inner) => self.eval(inner),
+            Expression::Prefix { operator, right } => {
+                let right = self.eval(right)?;
+                match (operator, right) {
+                    (Token::BANG, right) => Ok(MacroValue::Boolean(!right.is_truthy())),
+                    (Token::MINUS, MacroValue::Int(value)) => match value.checked_neg() {
+                        Some(value) => Ok(MacroValue::Int(value)),
+                        None => error(format!("integer overflow in -{}", value)),
+                    },
+                    (operator, right) => error(format!(
+                        "unsupported operator {} for {}",
+                        operator,
+                        right.type_name()
+                    )),
+                }
+            }
+            Expression::Infix {
+                operator,
+                left,
+                right,
+            } => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                eval_infix(operator, left, right)
+            }
+            Expression::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                if self.eval(condition)?.is_truthy() {
+                    self.eval(consequence)
+                } else if let Some(alternative) = alternative {
+                    self.eval(alternative)
+                } else {
+                    Ok(MacroValue::Null)
+                }
+            }
+            Expression::Ternary {
+                condition,
+                consequence,
+                alternative,
+            } => match self.eval(condition)?.is_truthy() {
+                true => self.eval(consequence),
+                false => self.eval(alternative),
+            },
+            Expression::Block(statements) => {
+                let mut value = MacroValue::Null;
+                for stmt in statements {
+                    value = self.eval_statement(stmt)?;
+                }
+                Ok(value)
+            }
+            Expression::Call {
+                function,
+                arguments,
+            } if **function == Expression::Ident("quote".to_string()) => match &arguments[..] {
+                [Argument::Positional(quoted)] => {
+                    let mut unquoter = Unquoter {
+                        evaluator: self,
+                        depth: 0,
+                        error: None,
+                    };
+                    let quoted = unquoter.fold_expression(quoted.clone());
+                    match unquoter.error {
+                        Some(unwind) => Err(unwind),
+                        None => Ok(MacroValue::Quote(quoted)),
+                    }
+                }
+                _ => error("quote takes exactly one argument".to_string()),
+            },
+            expr => error(format!("cannot evaluate {} during macro expansion", expr)),
+        }
+    }
+
+    fn eval_statement(&mut self, stmt: &Statement) -> Result<MacroValue, Unwind> {
+        match stmt {
+            Statement::Let {
+                pattern: Pattern::Ident(identifier),
+                expr,
+                ..
+            }
+            | Statement::Const {
+                name: identifier,
+                expr,
+                ..
+            } => {
+                let value = self.eval(expr)?;
+                self.env.insert(identifier.clone(), value);
+                Ok(MacroValue::Null)
+            }
+            Statement::Return(expr) => Err(Unwind::Return(self.eval(expr)?)),
+            Statement::Expr(expr) => self.eval(expr),
+            stmt => error(format!("cannot evaluate {} during macro expansion", stmt)),
+        }
+    }
+}
+
+fn eval_infix(operator: &Token, left: MacroValue, right: MacroValue) -> Result<MacroValue, Unwind> {
+    use MacroValue::{Boolean, Int, Str};
+    let value = match (operator, &left, &right) {
+        (Token::PLUS, Int(a), Int(b)) => a.checked_add(*b).map(Int),
+        (Token::MINUS, Int(a), Int(b)) => a.checked_sub(*b).map(Int),
+        (Token::ASTERISK, Int(a), Int(b)) => a.checked_mul(*b).map(Int),
+        (Token::SLASH, Int(_), Int(0)) => return error("division by zero".to_string()),
+        (Token::SLASH, Int(a), Int(b)) => a.checked_div(*b).map(Int),
+        (Token::LT, Int(a), Int(b)) => Some(Boolean(a < b)),
+        (Token::GT, Int(a), Int(b)) => Some(Boolean(a > b)),
+        (Token::PLUS, Str(a), Str(b)) => Some(Str(format!("{}{}", a, b))),
+        // 同じ種類の値どうしは、`quote` した構文木も含めて等しさを比べられる
+        (Token::EQ, _, _) if left.type_name() == right.type_name() => Some(Boolean(left == right)),
+        (Token::NOTEQ, _, _) if left.type_name() == right.type_name() => {
+            Some(Boolean(left != right))
+        }
+        _ => {
+            return error(format!(
+                "unsupported operator {} for {} and {}",
+                operator,
+                left.type_name(),
+                right.type_name()
+            ))
+        }
+    };
+    match value {
+        Some(value) => Ok(value),
+        None => error(format!(
+            "integer overflow in {:?} {} {:?}",
+            left, operator, right
+        )),
+    }
+}
+
+/// `quote` の中の `unquote(expr)` を評価結果の構文木に置き換える
+struct Unquoter<'e> {
+    evaluator: &'e mut Evaluator,
+    /// 評価している `quote` の中に入れ子にした `quote` の深さ
+    depth: usize,
+    error: Option<Unwind>,
+}
+
+impl Fold for Unquoter<'_> {
+    fn fold_expression(&mut self, expr: Expression) -> Expression {
+        if self.error.is_some() {
+            return expr;
+        }
+        let (quote, unquote) = match &expr {
+            Expression::Call { function, .. } => (
+                **function == Expression::Ident("quote".to_string()),
+                **function == Expression::Ident("unquote".to_string()),
+            ),
+            _ => (false, false),
+        };
+        // 内側の `quote` の `unquote` はその `quote` を評価するときまで残し、中だけを1段浅く辿る
+        if quote || (unquote && self.depth > 0) {
+            let depth = self.depth;
+            self.depth = if quote { depth + 1 } else { depth - 1 };
+            let expr = fold_expression(self, expr);
+            self.depth = depth;
+            return expr;
+        }
+        let argument = match &expr {
+            Expression::Call { arguments, .. } if unquote => match &arguments[..] {
+                [Argument::Positional(argument)] => argument,
+                _ => {
+                    self.error = Some(Unwind::Error(
+                        "unquote takes exactly one argument".to_string(),
+                    ));
+                    return expr;
+                }
+            },
+            _ => return fold_expression(self, expr),
+        };
+        let value = match self.evaluator.eval(argument) {
+            Ok(value) | Err(Unwind::Return(value)) => value,
+            Err(unwind) => {
+                self.error = Some(unwind);
+                return expr;
+            }
+        };
+        match value.into_expression() {
+            Ok(expanded) => expanded,
+            Err(message) => {
+                self.error = Some(Unwind::Error(message));
+                expr
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn parse(input: &str) -> Vec<Statement> {
+    let mut parser = crate::parser::Parser::new(crate::lexer::Lexer::new(input));
+    let program = parser.parse_program();
+    assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+    program
+}
+
+#[cfg(test)]
+fn expand(input: &str) -> Result<Vec<Statement>, String> {
+    let mut program = parse(input);
+    let mut env = MacroEnv::new();
+    env.define_macros(&mut program);
+    expand_macros(program, &env)
+}
+
+#[test]
+fn test_define_macros() {
+    let mut program = parse(
+        "let number = 1; let function = fn(x, y) { x + y }; let mymacro = macro(x, y) { x + y; };",
+    );
+    let mut env = MacroEnv::new();
+    env.define_macros(&mut program);
+    assert_eq!(program.len(), 2);
+    assert!(!env.contains("number"));
+    assert!(!env.contains("function"));
+    assert_eq!(
+        env.macros["mymacro"],
+        (
+            vec![Parameter::new("x"), Parameter::new("y")],
+            Expression::Block(vec![Statement::Expr(Expression::Infix {
+                operator: Token::PLUS,
+                left: Box::new(Expression::Ident("x".to_string())),
+                right: Box::new(Expression::Ident("y".to_string())),
+            })])
+        )
+    );
+}
+
+#[test]
+fn test_expand_macros() {
+    let tests = [
+        (
+            "let infixExpression = macro() { quote(1 + 2); }; infixExpression();",
+            "(1 + 2)",
+        ),
+        (
+            "let reverse = macro(a, b) { quote(unquote(b) - unquote(a)); }; reverse(2 + 2, 10 - 5);",
+            "(10 - 5) - (2 + 2)",
+        ),
+        (
+            "let unless = macro(cond, cons, alt) {
+                quote(if (!(unquote(cond))) { unquote(cons); } else { unquote(alt); });
+            };
+            unless(10 > 5, puts(\"not greater\"), puts(\"greater\"));",
+            "if (!(10 > 5)) { puts(\"not greater\") } else { puts(\"greater\") }",
+        ),
+        (
+            "let scale = macro(x, by = 2) { let n = 2 * 3; quote(unquote(n) * unquote(x) * unquote(by)); };
+            scale(y); scale(y, 10);",
+            "(6 * y) * 2; 6 * y * 10",
+        ),
+        (
+            "let sign = macro(x) { if (x == quote(0)) { return quote(0); } quote(unquote(0 - 3) + unquote(x)) };
+            fn f() { sign(a) }",
+            "fn f() { -3 + a }",
+        ),
+        (
+            "let inc = macro(x) { quote(unquote(x) + 1) };
+            let twice = macro(x) { quote(inc(inc(unquote(x)))) };
+            let g = fn(a) { twice(a) * 2 };",
+            "let g = fn(a) { ((a + 1) + 1) * 2 };",
+        ),
+        (
+            "let greet = macro() { let s = \"hi\"; quote(unquote(s + \"!\")) }; greet();",
+            "\"hi!\"",
+        ),
+        (
+            "let m = macro(x) { let n = 1; quote(quote(unquote(x) + unquote(unquote(n)))) }; m(y);",
+            "quote(unquote(x) + unquote(1))",
+        ),
+    ];
+    for (input, expected) in tests.iter() {
+        let expanded: Vec<String> = expand(input)
+            .unwrap()
+            .iter()
+            .map(|stmt| stmt.to_string())
+            .collect();
+        let expected: Vec<String> = parse(expected)
+            .iter()
+            .map(|stmt| stmt.to_string())
+            .collect();
+        assert_eq!(expanded, expected, "{}", input);
+    }
+}
+
+#[test]
+fn test_expand_macro_errors() {
+    let tests = [
+        (
+            "let m = macro(a) { quote(a) }; m(1, 2);",
+            "macro m takes 1 arguments, got 2",
+        ),
+        (
+            "let m = macro(a) { quote(a) }; m();",
+            "macro m is missing argument a",
+        ),
+        (
+            "let m = macro(a) { quote(a) }; m(a: 1);",
+            "macro m does not take keyword argument a",
+        ),
+        (
+            "let m = macro() { 1 + 2 }; m();",
+            "macro m must return a quoted expression, got integer",
+        ),
+        (
+            "let m = macro() { quote(unquote(x)) }; m();",
+            "in macro m: identifier x is not defined",
+        ),
+        (
+            "let m = macro() { quote(unquote(f(1))) }; m();",
+            "in macro m: cannot evaluate f(1) during macro expansion",
+        ),
+        (
+            "let m = macro() { quote(unquote(1 / 0)) }; m();",
+            "in macro m: division by zero",
+        ),
+        (
+            "let m = macro() { quote(unquote(0 - 9223372036854775807 - 1)) }; m();",
+            "in macro m: cannot unquote integer -9223372036854775808",
+        ),
+        (
+            "let forever = macro() { quote(forever()) }; forever();",
+            "expansion of macro forever exceeded 100 levels",
+        ),
+    ];
+    for (input, expected) in tests.iter() {
+        assert_eq!(expand(input), Err(expected.to_string()), "{}", input);
+    }
+}
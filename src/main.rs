@@ -1,9 +1,155 @@
-use monkey::repl::start;
-use std::io;
-
-fn main() -> io::Result<()> {
-    let input = io::stdin();
-    let output = io::stdout();
-    start(input.lock(), output.lock())?;
-    Ok(())
-}
+use monkey::const_check;
+#[cfg(feature = "export")]
+use monkey::export::{program_to_json, program_to_sexp};
+use monkey::formatter::format_source;
+use monkey::lexer::Lexer;
+use monkey::parser::Parser;
+use monkey::printer::PrintOptions;
+use monkey::repl::start;
+use std::env;
+use std::fs;
+use std::io;
+use std::process;
+
+#[cfg(feature = "export")]
+const USAGE: &str =
+    "usage: monkey [fmt [--check] <file>... | check <file>... | ast [--format json|sexp] <file>]";
+#[cfg(not(feature = "export"))]
+const USAGE: &str = "usage: monkey [fmt [--check] <file>... | check <file>...]";
+
+/// `fmt` が整形しない部分の説明（`fmt` の使い方とともに出す）
+const FMT_NOTE: &str =
+    "fmt leaves top-level statements that contain comments or syntax errors as written";
+
+fn main() -> io::Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        None => {
+            let input = io::stdin();
+            let output = io::stdout();
+            start(input.lock(), output.lock())?;
+        }
+        Some("fmt") => process::exit(fmt(&args[1..])),
+        Some("check") => process::exit(check(&args[1..])),
+        #[cfg(feature = "export")]
+        Some("ast") => process::exit(ast(&args[1..])),
+        Some(command) => {
+            eprintln!("unknown command {}\n{}", command, USAGE);
+            process::exit(2);
+        }
+    }
+    Ok(())
+}
+
+/// ファイルを整形して書き戻す。`--check` なら書き戻さず、未整形のファイルがあれば1を返す
+///
+/// コメントか構文エラーを含む最上位の文は元のまま残す
+fn fmt(args: &[String]) -> i32 {
+    let check = args.iter().any(|arg| arg == "--check");
+    let files: Vec<&String> = args.iter().filter(|arg| *arg != "--check").collect();
+    if files.is_empty() || files.iter().any(|file| file.starts_with("--")) {
+        eprintln!("{}\n{}", USAGE, FMT_NOTE);
+        return 2;
+    }
+    let mut status = 0;
+    for path in files {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(error) => {
+                eprintln!("{}: {}", path, error);
+                status = 2;
+                continue;
+            }
+        };
+        let formatted = format_source(&source, PrintOptions::default());
+        if formatted == source {
+            continue;
+        }
+        if check {
+            println!("{} is not formatted", path);
+            status = status.max(1);
+        } else if let Err(error) = fs::write(path, formatted) {
+            eprintln!("{}: {}", path, error);
+            status = 2;
+        }
+    }
+    status
+}
+
+/// ファイルを静的に検査する。構文エラーか `const` への代入・宣言し直しがあれば1を返す
+///
+/// 置き場所の誤った `///` コメントは警告として出す
+fn check(args: &[String]) -> i32 {
+    if args.is_empty() || args.iter().any(|arg| arg.starts_with("--")) {
+        eprintln!("{}", USAGE);
+        return 2;
+    }
+    let mut status = 0;
+    for path in args {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(error) => {
+                eprintln!("{}: {}", path, error);
+                status = 2;
+                continue;
+            }
+        };
+        let mut parser = Parser::new(Lexer::new(&source));
+        let program = parser.parse_program();
+        let spans = parser.span_map();
+        let violations = const_check::check_program(&program);
+        for error in parser.errors() {
+            eprintln!("{}: {}", path, error);
+        }
+        for violation in violations.iter() {
+            eprintln!("{}: {}", path, violation.describe(&spans));
+        }
+        for lint in parser.lints() {
+            eprintln!("{}: warning: {}", path, lint);
+        }
+        if !parser.errors().is_empty() || !violations.is_empty() {
+            status = status.max(1);
+        }
+    }
+    status
+}
+
+/// ファイルの構文木をJSONかS式で書き出す。構文エラーがあれば1を返す
+#[cfg(feature = "export")]
+fn ast(args: &[String]) -> i32 {
+    let (format, path) = match args {
+        [path] => ("json", path),
+        [flag, format, path] if flag == "--format" => (format.as_str(), path),
+        _ => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+    if format != "json" && format != "sexp" || path.starts_with("--") {
+        eprintln!("{}", USAGE);
+        return 2;
+    }
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("{}: {}", path, error);
+            return 2;
+        }
+    };
+    let mut parser = Parser::new(Lexer::new(&source));
+    let program = parser.parse_program();
+    if !parser.errors().is_empty() {
+        for error in parser.errors() {
+            eprintln!("{}: {}", path, error);
+        }
+        return 1;
+    }
+    for lint in parser.lints() {
+        eprintln!("{}: warning: {}", path, lint);
+    }
+    match format {
+        "json" => println!("{}", program_to_json(&program).pretty()),
+        _ => print!("{}", program_to_sexp(&program)),
+    }
+    0
+}
@@ -0,0 +1,174 @@
+use crate::ast::{walk_expression, Expression, MatchArm, Pattern, Statement, Visitor};
+use std::fmt;
+
+/// match式に対する警告
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum MatchWarning {
+    /// どの腕にも一致しない値がありうる
+    NonExhaustive { subject: String },
+    /// 手前の腕がすべて受け止めるため到達しない腕（`arm` は先頭の腕を1とする番号）
+    UnreachableArm { subject: String, arm: usize },
+}
+
+impl fmt::Display for MatchWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatchWarning::NonExhaustive { subject } => {
+                write!(f, "non-exhaustive match on {}, add a `_` arm", subject)
+            }
+            MatchWarning::UnreachableArm { subject, arm } => {
+                write!(f, "unreachable arm #{} in match on {}", arm, subject)
+            }
+        }
+    }
+}
+
+/// プログラム中のすべてのmatch式を検査する
+pub fn check_program(program: &[Statement]) -> Vec<MatchWarning> {
+    let mut checker = MatchChecker { warnings: vec![] };
+    for stmt in program {
+        checker.visit_statement(stmt);
+    }
+    checker.warnings
+}
+
+struct MatchChecker {
+    warnings: Vec<MatchWarning>,
+}
+
+impl Visitor<'_> for MatchChecker {
+    fn visit_expression(&mut self, expr: &Expression) {
+        walk_expression(self, expr);
+        if let Expression::Match { subject, arms } = expr {
+            check_arms(&subject.to_string(), arms, &mut self.warnings);
+        }
+    }
+}
+
+fn check_arms(subject: &str, arms: &[MatchArm], warnings: &mut Vec<MatchWarning>) {
+    // ガード付きの腕は失敗しうるので、他の腕を覆うものとしては数えない
+    let mut covering: Vec<&Pattern> = vec![];
+    let mut exhaustive = false;
+    for (i, arm) in arms.iter().enumerate() {
+        if exhaustive || covering.iter().any(|p| covers(p, &arm.pattern)) {
+            warnings.push(MatchWarning::UnreachableArm {
+                subject: subject.to_string(),
+                arm: i + 1,
+            });
+            continue;
+        }
+        if arm.guard.is_none() {
+            covering.push(&arm.pattern);
+            exhaustive = arm.pattern.is_irrefutable()
+                || (covering.contains(&&Pattern::Boolean(true))
+                    && covering.contains(&&Pattern::Boolean(false)));
+        }
+    }
+    if !exhaustive {
+        warnings.push(MatchWarning::NonExhaustive {
+            subject: subject.to_string(),
+        });
+    }
+}
+
+/// `earlier` に一致する値がすべて `later` にも一致する場合、`later` は到達しない
+fn covers(earlier: &Pattern, later: &Pattern) -> bool {
+    match (earlier, later) {
+        (p, _) if p.is_irrefutable() => true,
+        (
+            Pattern::Array {
+                elements: ps,
+                rest: None,
+            },
+            Pattern::Array {
+                elements: qs,
+                rest: None,
+            },
+        ) => ps.len() == qs.len() && ps.iter().zip(qs).all(|(p, q)| covers(p, q)),
+        // `[a, ...rest]` は要素が `a` の分以上あるどの配列にも一致する
+        (
+            Pattern::Array {
+                elements: ps,
+                rest: Some(_),
+            },
+            Pattern::Array { elements: qs, .. },
+        ) => ps.len() <= qs.len() && ps.iter().zip(qs).all(|(p, q)| covers(p, q)),
+        (Pattern::Hash(ps), Pattern::Hash(qs)) => ps.iter().all(|(key, p)| {
+            qs.iter()
+                .any(|(other_key, q)| key == other_key && covers(p, q))
+        }),
+        (p, q) => p == q,
+    }
+}
+
+#[cfg(test)]
+fn check_source(input: &str) -> Vec<MatchWarning> {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    let mut lex = Lexer::new(input);
+    let mut parser = Parser::new(&mut lex);
+    let program = parser.parse_program();
+    check_program(&program)
+}
+
+#[test]
+fn test_exhaustive_match() {
+    let input = [
+        r#"match (x) { 0 => "zero", _ => "other" }"#,
+        "match (x) { true => 1, false => 0 }",
+        "match (x) { [a, b] => a, n => n }",
+        "match (x) { n if n > 0 => n, n => -n }",
+    ];
+    for src in input.iter() {
+        assert_eq!(check_source(src), vec![], "{}", src);
+    }
+}
+
+#[test]
+fn test_non_exhaustive_match() {
+    let input = [
+        "match (x) { 0 => 1 }",
+        "match (x) { [a] => a, [a, b] => b }",
+        "match (x) { [a, b, ...r] => a, [a] => a, [a, ...r] => a }",
+        "match (x) { n if n > 0 => n }",
+        "fn(x) { match (x) { true => 1 } }",
+    ];
+    for src in input.iter() {
+        let warnings = check_source(src);
+        assert_eq!(warnings.len(), 1, "{}", src);
+        assert!(matches!(warnings[0], MatchWarning::NonExhaustive { .. }));
+    }
+}
+
+#[test]
+fn test_unreachable_arm() {
+    let input = [
+        ("match (x) { _ => 0, 1 => 1 }", 2),
+        ("match (x) { n => n, _ => 0 }", 2),
+        ("match (x) { 1 => 1, 1 => 2, _ => 0 }", 2),
+        ("match (x) { [a, _] => a, [1, 2] => 0, _ => 0 }", 2),
+        ("match (x) { [a, ...r] => a, [1, 2, 3] => 0, _ => 0 }", 2),
+        ("match (x) { [] => 0, [...r] => 1, [a] => a, _ => 0 }", 3),
+        (
+            r#"match (x) { {"k": v} => v, {"k": 1, "j": 2} => 0, _ => 0 }"#,
+            2,
+        ),
+        ("match (x) { true => 1, false => 0, _ => 2 }", 3),
+    ];
+    for (src, arm) in input.iter() {
+        let warnings = check_source(src);
+        assert_eq!(
+            warnings,
+            vec![MatchWarning::UnreachableArm {
+                subject: "x".to_string(),
+                arm: *arm
+            }],
+            "{}",
+            src
+        );
+    }
+    assert_eq!(
+        check_source("match (x) { _ => 0, 1 => 1 }")[0].to_string(),
+        "unreachable arm #2 in match on x"
+    );
+}
@@ -0,0 +1,313 @@
+//! モジュールの読み込み
+//!
+//! `import "./util.monkey" as util;` のパスは読み込む側のファイルのディレクトリからの相対パスとして解決し、
+//! `.` と `..` を取り除いたパスで同じモジュールかを判断する。
+//! 読み込んだモジュールは1度だけ構文解析して共有し、循環する `import` はその経路を示して誤りにする。
+use crate::ast::Statement;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+
+/// モジュールのソースを読み出すもの
+pub trait ModuleSource {
+    fn read(&self, path: &Path) -> Result<String, String>;
+}
+
+/// ファイルシステムから読む
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FileSystem;
+
+impl ModuleSource for FileSystem {
+    fn read(&self, path: &Path) -> Result<String, String> {
+        fs::read_to_string(path).map_err(|error| error.to_string())
+    }
+}
+
+/// パスごとのソースの表から読む
+impl ModuleSource for HashMap<PathBuf, String> {
+    fn read(&self, path: &Path) -> Result<String, String> {
+        self.get(path)
+            .cloned()
+            .ok_or_else(|| "no such module".to_string())
+    }
+}
+
+/// 構文解析済みのモジュール
+#[derive(Debug, PartialEq, Eq)]
+pub struct Module {
+    pub path: PathBuf,
+    pub program: Vec<Statement>,
+    /// `import` した名前と、そのモジュールの名前空間（書いた順）
+    pub imports: Vec<(String, Namespace)>,
+}
+
+impl Module {
+    /// `export` した名前と、それを束縛する文（書いた順）
+    pub fn exports(&self) -> Vec<(&str, &Statement)> {
+        self.program
+            .iter()
+            .filter_map(|stmt| match stmt {
+                Statement::Export(decl) => Some(&**decl),
+                _ => None,
+            })
+            .flat_map(|decl| decl.bindings().into_iter().map(move |name| (name, decl)))
+            .collect()
+    }
+
+    /// `import` した名前空間
+    pub fn namespace(&self, alias: &str) -> Option<&Namespace> {
+        self.imports
+            .iter()
+            .find(|(name, _)| name == alias)
+            .map(|(_, namespace)| namespace)
+    }
+}
+
+/// モジュールの `export` を名前で引く値（`util.f` の `util`）
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Namespace {
+    module: Rc<Module>,
+}
+
+impl Namespace {
+    pub fn module(&self) -> &Rc<Module> {
+        &self.module
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.module
+            .exports()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    /// `name` を束縛する `export` の対象の文
+    pub fn get(&self, name: &str) -> Option<&Statement> {
+        self.module
+            .exports()
+            .into_iter()
+            .find(|(exported, _)| *exported == name)
+            .map(|(_, decl)| decl)
+    }
+}
+
+/// モジュールを読み込み、パスごとにキャッシュする
+#[derive(Debug)]
+pub struct ModuleLoader<S> {
+    source: S,
+    cache: HashMap<PathBuf, Rc<Module>>,
+    /// 読み込み中のモジュール（外側から順に）
+    loading: Vec<PathBuf>,
+}
+
+impl<S: ModuleSource> ModuleLoader<S> {
+    pub fn new(source: S) -> ModuleLoader<S> {
+        ModuleLoader {
+            source,
+            cache: HashMap::new(),
+            loading: vec![],
+        }
+    }
+
+    /// `path` のモジュールと、そこから `import` するモジュールをすべて読み込む
+    pub fn load(&mut self, path: &Path) -> Result<Rc<Module>, String> {
+        let path = normalize(path);
+        if let Some(module) = self.cache.get(&path) {
+            return Ok(Rc::clone(module));
+        }
+        if let Some(start) = self.loading.iter().position(|loading| *loading == path) {
+            let cycle: Vec<String> = self.loading[start..]
+                .iter()
+                .chain(Some(&path))
+                .map(|path| path.display().to_string())
+                .collect();
+            return Err(format!("import cycle: {}", cycle.join(" -> ")));
+        }
+        let source = self
+            .source
+            .read(&path)
+            .map_err(|error| format!("{}: {}", path.display(), error))?;
+        let mut parser = Parser::new(Lexer::new(&source));
+        let program = parser.parse_program();
+        if !parser.errors().is_empty() {
+            let errors: Vec<String> = parser
+                .errors()
+                .iter()
+                .map(|error| format!("{}: {}", path.display(), error))
+                .collect();
+            return Err(errors.join("\n"));
+        }
+        self.loading.push(path.clone());
+        let imports = self.load_imports(&path, &program);
+        self.loading.pop();
+        let module = Rc::new(Module {
+            path: path.clone(),
+            program,
+            imports: imports?,
+        });
+        let exports = module.exports();
+        for (i, (name, _)) in exports.iter().enumerate() {
+            if exports[..i].iter().any(|(other, _)| other == name) {
+                return Err(format!(
+                    "{}: {} is exported more than once",
+                    path.display(),
+                    name
+                ));
+            }
+        }
+        self.cache.insert(path, Rc::clone(&module));
+        Ok(module)
+    }
+
+    fn load_imports(
+        &mut self,
+        path: &Path,
+        program: &[Statement],
+    ) -> Result<Vec<(String, Namespace)>, String> {
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut imports: Vec<(String, Namespace)> = vec![];
+        for stmt in program {
+            if let Statement::Import {
+                path: import,
+                alias,
+            } = stmt
+            {
+                if imports.iter().any(|(name, _)| name == alias) {
+                    return Err(format!(
+                        "{}: {} is imported more than once",
+                        path.display(),
+                        alias
+                    ));
+                }
+                let module = self.load(&dir.join(import))?;
+                imports.push((alias.clone(), Namespace { module }));
+            }
+        }
+        Ok(imports)
+    }
+}
+
+/// パスから `.` と `..` を取り除く（ファイルシステムは参照しない）
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => normalized.push(".."),
+            },
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+fn memory_loader(files: &[(&str, &str)]) -> ModuleLoader<HashMap<PathBuf, String>> {
+    ModuleLoader::new(
+        files
+            .iter()
+            .map(|(path, source)| (PathBuf::from(path), source.to_string()))
+            .collect(),
+    )
+}
+
+#[test]
+fn test_load_modules() {
+    let mut loader = memory_loader(&[
+        (
+            "app/main.monkey",
+            r#"import "./lib/math.monkey" as math; import "../shared/util.monkey" as util;
+            math.square(util.base);"#,
+        ),
+        (
+            "app/lib/math.monkey",
+            r#"import "../../shared/./util.monkey" as util;
+            /// Squares n.
+            export fn square(n) { n * n }
+            export let [one, two] = pair;
+            fn helper() { 0 }"#,
+        ),
+        ("shared/util.monkey", "export const base = 10;"),
+    ]);
+    let main = loader.load(Path::new("app/main.monkey")).unwrap();
+    let math = main.namespace("math").unwrap();
+    assert_eq!(math.module().path, PathBuf::from("app/lib/math.monkey"));
+    assert_eq!(math.names(), vec!["square", "one", "two"]);
+    assert!(matches!(
+        math.get("square"),
+        Some(Statement::FnDecl { doc: Some(doc), .. }) if doc == "Squares n."
+    ));
+    assert_eq!(math.get("helper"), None);
+    // 2か所から読み込んだモジュールは同じものを共有する
+    let util = main.namespace("util").unwrap().module();
+    assert!(Rc::ptr_eq(
+        util,
+        math.module().namespace("util").unwrap().module()
+    ));
+    assert!(Rc::ptr_eq(
+        util,
+        &loader.load(Path::new("shared/util.monkey")).unwrap()
+    ));
+}
+
+#[test]
+fn test_import_cycle() {
+    let mut loader = memory_loader(&[
+        ("main.monkey", r#"import "./a.monkey" as a;"#),
+        ("a.monkey", r#"import "./sub/b.monkey" as b;"#),
+        ("sub/b.monkey", r#"import "../a.monkey" as a;"#),
+    ]);
+    assert_eq!(
+        loader.load(Path::new("main.monkey")),
+        Err("import cycle: a.monkey -> sub/b.monkey -> a.monkey".to_string())
+    );
+    let mut loader = memory_loader(&[("self.monkey", r#"import "self.monkey" as me;"#)]);
+    assert_eq!(
+        loader.load(Path::new("./self.monkey")),
+        Err("import cycle: self.monkey -> self.monkey".to_string())
+    );
+}
+
+#[test]
+fn test_load_errors() {
+    let tests = [
+        (
+            r#"import "./missing.monkey" as m;"#,
+            "missing.monkey: no such module",
+        ),
+        (
+            "let = 1;",
+            "main.monkey: expected next token to be IDENT, got = instead",
+        ),
+        (
+            r#"import "./lib.monkey" as l; import "./lib.monkey" as l;"#,
+            "main.monkey: l is imported more than once",
+        ),
+        (
+            "export let x = 1; export fn x() { 2 }",
+            "main.monkey: x is exported more than once",
+        ),
+        (
+            r#"fn f() { import "./lib.monkey" as l; }"#,
+            "main.monkey: import must be at the top level of a module",
+        ),
+    ];
+    for (source, expected) in tests.iter() {
+        let mut loader = memory_loader(&[("main.monkey", source), ("lib.monkey", "")]);
+        assert_eq!(
+            loader.load(Path::new("main.monkey")).map(|_| ()),
+            Err(expected.to_string()),
+            "{}",
+            source
+        );
+    }
+}
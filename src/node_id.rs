@@ -0,0 +1,196 @@
+//! 抽象構文木の節点の番号と位置の表
+//!
+//! 番号は文・式を帰りがけ順（子が先、左から右）に数えたもの。構文解析器が節点を読み終えた順と一致するので、
+//! 構文木そのものを変えずに解析結果を節点ごとに対応付けられる。
+//!
+//! 番号は節点に持たせず、数えるたびに構文木を辿って決める。構文解析器が返した構文木に
+//! 手を加える（マクロの展開や宣言の巻き上げなど）と節点と番号の対応はずれるので、
+//! `SpanMap` などの番号で引く表は手を加える前の構文木にだけ使う。
+use crate::ast::{walk_expression, walk_statement, Expression, Statement, Visitor};
+use crate::cst::SyntaxEvent;
+use crate::token::{Span, Token};
+use std::collections::HashMap;
+use std::fmt;
+
+/// 文・式の番号（`number_nodes` で数えた構文木での帰りがけ順の位置）
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct NodeId(pub usize);
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// 番号で引く文・式
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum NodeRef<'a> {
+    Statement(&'a Statement),
+    Expression(&'a Expression),
+}
+
+impl fmt::Display for NodeRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeRef::Statement(stmt) => write!(f, "{}", stmt),
+            NodeRef::Expression(expr) => write!(f, "{}", expr),
+        }
+    }
+}
+
+/// 番号から入力中の範囲を引く表
+///
+/// アロー関数の本体を包むブロックと式文は、本体の式と同じ範囲を持つ。
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct SpanMap {
+    spans: Vec<Span>,
+}
+
+impl SpanMap {
+    /// 構文解析器の記録から文・式の範囲を取り出す
+    pub fn from_events(tokens: &[(Token, Span)], events: &[SyntaxEvent]) -> SpanMap {
+        let spans = events
+            .iter()
+            .filter(|event| event.kind.is_statement() || event.kind.is_expression())
+            .map(|event| tokens[event.first].1.to(tokens[event.last].1))
+            .collect();
+        SpanMap { spans }
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<Span> {
+        self.spans.get(id.0).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+}
+
+/// プログラム中の文・式を番号順に並べる（`nodes[id.0]` が番号 `id` の節点）
+pub fn number_nodes(program: &[Statement]) -> Vec<NodeRef<'_>> {
+    let mut numbering = Numbering { nodes: vec![] };
+    for stmt in program {
+        numbering.visit_statement(stmt);
+    }
+    numbering.nodes
+}
+
+/// 文・式から番号を引く表（`number_nodes` と同じ数え方）
+///
+/// 節点は位置で見分けるので、表を作った構文木の節点にだけ使える。
+pub struct NodeIds {
+    statements: HashMap<*const Statement, NodeId>,
+    expressions: HashMap<*const Expression, NodeId>,
+}
+
+impl NodeIds {
+    pub fn new(program: &[Statement]) -> NodeIds {
+        let mut ids = NodeIds {
+            statements: HashMap::new(),
+            expressions: HashMap::new(),
+        };
+        for (i, node) in number_nodes(program).into_iter().enumerate() {
+            match node {
+                NodeRef::Statement(stmt) => ids.statements.insert(stmt, NodeId(i)),
+                NodeRef::Expression(expr) => ids.expressions.insert(expr, NodeId(i)),
+            };
+        }
+        ids
+    }
+
+    /// 文の番号（表を作った構文木の文でなければパニックする）
+    pub fn statement(&self, stmt: &Statement) -> NodeId {
+        self.statements[&(stmt as *const Statement)]
+    }
+
+    /// 式の番号（表を作った構文木の式でなければパニックする）
+    pub fn expression(&self, expr: &Expression) -> NodeId {
+        self.expressions[&(expr as *const Expression)]
+    }
+}
+
+struct Numbering<'ast> {
+    nodes: Vec<NodeRef<'ast>>,
+}
+
+impl<'ast> Visitor<'ast> for Numbering<'ast> {
+    fn visit_statement(&mut self, stmt: &'ast Statement) {
+        walk_statement(self, stmt);
+        self.nodes.push(NodeRef::Statement(stmt));
+    }
+
+    fn visit_expression(&mut self, expr: &'ast Expression) {
+        walk_expression(self, expr);
+        self.nodes.push(NodeRef::Expression(expr));
+    }
+}
+
+#[cfg(test)]
+fn parse_with_spans(input: &str) -> (Vec<Statement>, SpanMap) {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    let mut parser = Parser::new(Lexer::new(input));
+    let program = parser.parse_program();
+    (program, parser.span_map())
+}
+
+#[test]
+fn test_span_map() {
+    let input = "let x = 1;\nx + x;";
+    let (program, spans) = parse_with_spans(input);
+    let nodes = number_nodes(&program);
+    assert_eq!(nodes.len(), spans.len());
+    let expected = [
+        ("1", Span::new(8, 9)),
+        ("Let x=1", Span::new(0, 10)),
+        ("x", Span::new(11, 12)),
+        ("x", Span::new(15, 16)),
+        ("(x+x)", Span::new(11, 16)),
+        ("(x+x)", Span::new(11, 17)),
+    ];
+    for (i, (text, span)) in expected.iter().enumerate() {
+        assert_eq!(nodes[i].to_string(), *text);
+        assert_eq!(spans.get(NodeId(i)), Some(*span));
+    }
+    let ids = NodeIds::new(&program);
+    assert_eq!(ids.statement(&program[1]), NodeId(5));
+    match &program[1] {
+        Statement::Expr(expr) => assert_eq!(ids.expression(expr), NodeId(4)),
+        stmt => panic!("unexpected {}", stmt),
+    }
+}
+
+#[test]
+fn test_span_map_matches_source() {
+    let input = [
+        "fn f(a, b = 1 + 2) { if (a) { b } else if (b) { a } }",
+        "let g = (x, y) => x * y; |a| a |> f(b: 2);",
+        "match (x) { [a] if a > 0 => a, _ => { -1 } }",
+        "while (i < 3) { for (x in xs) { continue; } break; }",
+        "let a = ; let b = (1 + 2) * 3; c ? d : e;",
+    ];
+    for src in input.iter() {
+        let (program, spans) = parse_with_spans(src);
+        let nodes = number_nodes(&program);
+        assert_eq!(nodes.len(), spans.len(), "{}", src);
+        let chars: Vec<char> = src.chars().collect();
+        for (i, node) in nodes.iter().enumerate() {
+            let span = spans.get(NodeId(i)).unwrap();
+            let text: String = chars[span.start..span.end].iter().collect();
+            match node {
+                // ブロックは単独の式としては読めない
+                NodeRef::Expression(Expression::Block(_)) | NodeRef::Statement(_) => {}
+                NodeRef::Expression(expr) => assert_eq!(
+                    crate::parser::Parser::parse_expression_only(&text).as_ref(),
+                    Ok(*expr),
+                    "{}",
+                    text
+                ),
+            }
+        }
+    }
+}
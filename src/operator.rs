@@ -0,0 +1,182 @@
+use crate::token::Token;
+use std::collections::HashMap;
+
+/// 演算子の優先順位（大きいほど強く結合する）
+///
+/// 組み込みの段階は10刻みなので、利用者定義の演算子は間に割り込める。
+#[derive(Debug, PartialEq, PartialOrd, Eq, Ord, Hash, Clone, Copy)]
+pub struct Precedence(pub u8);
+
+impl Precedence {
+    pub const LOWEST: Precedence = Precedence(0);
+    pub const ASSIGN: Precedence = Precedence(5);
+    pub const PIPE: Precedence = Precedence(10);
+    pub const TERNARY: Precedence = Precedence(20);
+    pub const EQUALS: Precedence = Precedence(30);
+    pub const LESS_GREATER: Precedence = Precedence(40);
+    pub const BIT_OR: Precedence = Precedence(50);
+    pub const BIT_XOR: Precedence = Precedence(60);
+    pub const BIT_AND: Precedence = Precedence(70);
+    pub const SHIFT: Precedence = Precedence(80);
+    pub const SUM: Precedence = Precedence(90);
+    pub const PRODUCT: Precedence = Precedence(100);
+    pub const PREFIX: Precedence = Precedence(110);
+    pub const POWER: Precedence = Precedence(120);
+    pub const CALL: Precedence = Precedence(130);
+
+    /// 1段低い優先順位（右結合の右辺に使う）
+    pub fn lower(self) -> Precedence {
+        Precedence(self.0.saturating_sub(1))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Associativity {
+    Left,
+    Right,
+    /// `a <=> b <=> c` のような連鎖を許さない
+    None,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Fixity {
+    Prefix,
+    Infix(Associativity),
+    Postfix,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct InfixOperator {
+    pub precedence: Precedence,
+    pub associativity: Associativity,
+}
+
+impl InfixOperator {
+    /// 右辺を読むときの優先順位
+    pub fn right_operand(&self) -> Precedence {
+        match self.associativity {
+            Associativity::Right => self.precedence.lower(),
+            Associativity::Left | Associativity::None => self.precedence,
+        }
+    }
+}
+
+/// 構文解析器が参照する演算子の表
+#[derive(Debug, Clone)]
+pub struct OperatorTable {
+    prefix: HashMap<Token, Precedence>,
+    infix: HashMap<Token, InfixOperator>,
+    postfix: HashMap<Token, Precedence>,
+}
+
+impl OperatorTable {
+    /// 演算子を1つも含まない表
+    pub fn empty() -> OperatorTable {
+        OperatorTable {
+            prefix: HashMap::new(),
+            infix: HashMap::new(),
+            postfix: HashMap::new(),
+        }
+    }
+
+    /// 組み込み演算子をすべて含む表
+    pub fn new() -> OperatorTable {
+        let mut table = OperatorTable::empty();
+        for token in [Token::BANG, Token::MINUS, Token::TILDE].iter() {
+            table.define(token.clone(), Fixity::Prefix, Precedence::PREFIX);
+        }
+        let left = Fixity::Infix(Associativity::Left);
+        let right = Fixity::Infix(Associativity::Right);
+        let infix = [
+            (Token::ASSIGN, right, Precedence::ASSIGN),
+            (Token::PLUSASSIGN, right, Precedence::ASSIGN),
+            (Token::MINUSASSIGN, right, Precedence::ASSIGN),
+            (Token::ASTERISKASSIGN, right, Precedence::ASSIGN),
+            (Token::SLASHASSIGN, right, Precedence::ASSIGN),
+            (Token::PIPELINE, left, Precedence::PIPE),
+            (Token::QUESTION, right, Precedence::TERNARY),
+            (Token::EQ, left, Precedence::EQUALS),
+            (Token::NOTEQ, left, Precedence::EQUALS),
+            (Token::LT, left, Precedence::LESS_GREATER),
+            (Token::GT, left, Precedence::LESS_GREATER),
+            (Token::PIPE, left, Precedence::BIT_OR),
+            (Token::CARET, left, Precedence::BIT_XOR),
+            (Token::AMPERSAND, left, Precedence::BIT_AND),
+            (Token::LSHIFT, left, Precedence::SHIFT),
+            (Token::RSHIFT, left, Precedence::SHIFT),
+            (Token::PLUS, left, Precedence::SUM),
+            (Token::MINUS, left, Precedence::SUM),
+            (Token::ASTERISK, left, Precedence::PRODUCT),
+            (Token::SLASH, left, Precedence::PRODUCT),
+            (Token::POWER, right, Precedence::POWER),
+            (Token::LPAREN, left, Precedence::CALL),
+            (Token::DOT, left, Precedence::CALL),
+        ];
+        for (token, fixity, precedence) in infix.iter() {
+            table.define(token.clone(), *fixity, *precedence);
+        }
+        table
+    }
+
+    /// 演算子を登録する（同じ位置の既存の定義は上書きされる）
+    pub fn define(&mut self, token: Token, fixity: Fixity, precedence: Precedence) {
+        match fixity {
+            Fixity::Prefix => {
+                self.prefix.insert(token, precedence);
+            }
+            Fixity::Infix(associativity) => {
+                self.infix.insert(
+                    token,
+                    InfixOperator {
+                        precedence,
+                        associativity,
+                    },
+                );
+            }
+            Fixity::Postfix => {
+                self.postfix.insert(token, precedence);
+            }
+        }
+    }
+
+    /// 前置演算子なら、その被演算子を読むときの優先順位
+    pub fn prefix(&self, token: &Token) -> Option<Precedence> {
+        self.prefix.get(token).copied()
+    }
+
+    pub fn infix(&self, token: &Token) -> Option<InfixOperator> {
+        self.infix.get(token).copied()
+    }
+
+    pub fn postfix(&self, token: &Token) -> Option<Precedence> {
+        self.postfix.get(token).copied()
+    }
+
+    /// 左辺の後ろに `token` が来たとき、それが左辺を取り込む強さ
+    pub fn binding_power(&self, token: &Token) -> Precedence {
+        match (self.infix(token), self.postfix(token)) {
+            (Some(op), _) => op.precedence,
+            (None, Some(precedence)) => precedence,
+            (None, None) => Precedence::LOWEST,
+        }
+    }
+}
+
+impl Default for OperatorTable {
+    fn default() -> OperatorTable {
+        OperatorTable::new()
+    }
+}
+
+#[test]
+fn test_builtin_operators() {
+    let table = OperatorTable::new();
+    assert_eq!(table.prefix(&Token::MINUS), Some(Precedence::PREFIX));
+    assert_eq!(table.binding_power(&Token::MINUS), Precedence::SUM);
+    assert_eq!(
+        table.infix(&Token::POWER).unwrap().right_operand(),
+        Precedence::POWER.lower()
+    );
+    assert_eq!(table.binding_power(&Token::SEMICOLON), Precedence::LOWEST);
+    assert_eq!(OperatorTable::empty().prefix(&Token::BANG), None);
+}
@@ -1,577 +1,2598 @@
-use crate::ast::{Expression, Statement};
-use crate::lexer::Lexer;
-use crate::token::Token;
-
-type ParseError = String;
-
-pub struct Parser<'a> {
-    lexer: &'a mut Lexer,
-    cur_token: Token,
-    peek_token: Token,
-    errors: Vec<String>,
-}
-
-#[derive(Debug, PartialEq, PartialOrd, Eq, Hash, Clone)]
-enum Precedences {
-    Lowest,
-    Equals,
-    LessGreater,
-    Sum,
-    Product,
-    Prefix,
-}
-
-impl Precedences {
-    fn get(token: &Token) -> Precedences {
-        match token {
-            Token::EQ | Token::NOTEQ => Precedences::Equals,
-            Token::LT | Token::GT => Precedences::LessGreater,
-            Token::PLUS | Token::MINUS => Precedences::Sum,
-            Token::ASTERISK | Token::SLASH => Precedences::Product,
-            _ => Precedences::Lowest,
-        }
-    }
-}
-
-impl<'a> Parser<'a> {
-    pub fn new(lex: &'a mut Lexer) -> Parser<'a> {
-        Parser {
-            lexer: lex,
-            cur_token: Token::EOF,
-            peek_token: Token::EOF,
-            errors: vec![],
-        }
-    }
-
-    fn next_token(&mut self) {
-        self.cur_token = self.peek_token.clone();
-        self.peek_token = self.lexer.next_token();
-    }
-
-    pub fn parse_program(&mut self) -> Vec<Statement> {
-        let mut statements: Vec<Statement> = Vec::new();
-        self.next_token();
-        self.next_token();
-        while self.cur_token != Token::EOF {
-            match self.parse_statement() {
-                Ok(statement) => statements.push(statement),
-                Err(error) => {
-                    while self.cur_token != Token::SEMICOLON && self.cur_token != Token::EOF {
-                        self.next_token();
-                        //とりあえず、、、進める
-                    }
-                    self.errors.push(error)
-                }
-            }
-            self.next_token();
-        }
-        return statements;
-    }
-
-    fn parse_statement(&mut self) -> Result<Statement, ParseError> {
-        match self.cur_token {
-            Token::LET => self.parse_let_statement(),
-            Token::RETURN => self.parse_return_statement(),
-            _ => self.parse_expr_statement(),
-        }
-    }
-
-    fn parse_let_statement(&mut self) -> Result<Statement, ParseError> {
-        let ident_name = self.expect_ident()?;
-        self.expect_peek(Token::ASSIGN)?;
-        self.next_token();
-        let ret_val = Ok(Statement::Let {
-            identifier: ident_name,
-            expr: self.parse_expression(&Precedences::Lowest)?,
-        });
-        self.expect_peek(Token::SEMICOLON)?;
-        ret_val
-    }
-
-    fn parse_return_statement(&mut self) -> Result<Statement, String> {
-        self.next_token();
-        let ret_val = Ok(Statement::Return(
-            self.parse_expression(&Precedences::Lowest)?,
-        ));
-        while self.cur_token != Token::SEMICOLON && self.cur_token != Token::EOF {
-            self.next_token();
-            //とりあえず、、、進める
-        }
-        return ret_val;
-    }
-
-    fn parse_expr_statement(&mut self) -> Result<Statement, ParseError> {
-        let expr = self.parse_expression(&Precedences::Lowest)?;
-        if self.cur_token != Token::SEMICOLON {
-            self.next_token();
-        }
-        Ok(Statement::Expr(expr))
-    }
-
-    fn peek_token_is(&self, tok: &Token) -> bool {
-        match (&tok, &self.peek_token) {
-            (Token::IDENT(_), Token::IDENT(_)) => true,
-            (Token::INT(_), Token::INT(_)) => true,
-            _ => tok == &self.peek_token,
-        }
-    }
-
-    fn cur_token_is(&self, tok: &Token) -> bool {
-        match (&tok, &self.cur_token) {
-            (Token::IDENT(_), Token::IDENT(_)) => true,
-            (Token::INT(_), Token::INT(_)) => true,
-            _ => tok == &self.cur_token,
-        }
-    }
-
-    fn expect_peek(&mut self, tok: Token) -> Result<(), ParseError> {
-        match self.peek_token_is(&tok) {
-            true => {
-                self.next_token();
-                Ok(())
-            }
-            false => Err(format!(
-                "expected next token to be {}, got {} instead",
-                tok, self.peek_token
-            )),
-        }
-    }
-
-    fn expect_cur(&mut self, tok: Token) -> Result<(), ParseError> {
-        match self.cur_token_is(&tok) {
-            true => {
-                self.next_token();
-                Ok(())
-            }
-            false => Err(format!(
-                "expected cur token to be {}, got {} instead",
-                tok, self.cur_token
-            )),
-        }
-    }
-
-    pub fn print_error(&self) {
-        for i in self.errors.iter() {
-            println!("{}", i);
-        }
-    }
-
-    fn parse_expression(&mut self, precedence: &Precedences) -> Result<Expression, ParseError> {
-        println!("left in {:?}",self.cur_token);
-        let mut left = match &self.cur_token {
-            Token::IDENT(ident) => Ok(Expression::Ident(ident.clone())),
-            Token::INT(val) => Ok(Expression::Int(val.clone())),
-            Token::FALSE => Ok(Expression::Boolean(false)),
-            Token::TRUE => Ok(Expression::Boolean(true)),
-            Token::LPAREN => Ok(self.parse_grouped_expression()?),
-            Token::BANG | Token::MINUS => Ok(self.parse_prefix_expression()?),
-            Token::IF => Ok(self.parse_if_expression()?),
-            Token::FUNCTION => Ok(self.parse_function_expression()?),
-            _ => { println!("parse err"); Err(format!("Parse Expression:Unknown token {}", self.cur_token))},
-        }?;
-        while !self.peek_token_is(&Token::SEMICOLON)
-            && precedence < &Precedences::get(&self.peek_token)
-        {
-            println!("right {:?}",self.cur_token);
-            self.next_token();
-            println!("right2 {:?}",self.cur_token);
-            left = self.parse_infix_expression(left)?;
-        }
-        println!("left out {:?}",&left);
-        Ok(left)
-    }
-
-    fn parse_grouped_expression(&mut self) -> Result<Expression, ParseError> {
-        self.next_token();
-        let ret_val = Ok(Expression::Grouped(Box::new(
-            self.parse_expression(&Precedences::Lowest)?,
-        )));
-        self.expect_peek(Token::RPAREN)?;
-        ret_val
-    }
-
-    fn parse_prefix_expression(&mut self) -> Result<Expression, ParseError> {
-        let token = self.cur_token.clone();
-        self.next_token();
-        Ok(Expression::Prefix {
-            operator: token,
-            right: Box::new(self.parse_expression(&Precedences::Prefix)?),
-        })
-    }
-
-    fn parse_infix_expression(&mut self, left: Expression) -> Result<Expression, ParseError> {
-        let precedence = Precedences::get(&self.cur_token);
-        let token = self.cur_token.clone();
-        self.next_token();
-        Ok(Expression::Infix {
-            left: Box::new(left),
-            operator: token,
-            right: Box::new(self.parse_expression(&precedence)?),
-        })
-    }
-
-    fn parse_if_expression(&mut self) -> Result<Expression, ParseError> {
-        self.next_token();
-        self.expect_cur(Token::LPAREN)?;
-        let condition = self.parse_expression(&Precedences::Lowest)?;
-        self.next_token();
-        self.expect_cur(Token::RPAREN)?;
-        self.expect_cur(Token::LBRACE)?;
-        let consequence = self.parse_block_expression()?;
-        if self.cur_token == Token::ELSE {
-            self.next_token();
-            self.expect_cur(Token::LBRACE)?;
-            let alternative = self.parse_block_expression()?;
-            Ok(Expression::If {
-                condition: Box::new(condition),
-                consequence: Box::new(consequence),
-                alternative: Some(Box::new(alternative)),
-            })
-        } else {
-            Ok(Expression::If {
-                condition: Box::new(condition),
-                consequence: Box::new(consequence),
-                alternative: None,
-            })
-        }
-    }
-
-    fn parse_block_expression(&mut self) -> Result<Expression, ParseError> {
-        let mut statements: Vec<Statement> = Vec::new();
-        while self.cur_token != Token::RBRACE && self.cur_token != Token::EOF {
-            println!("1:{:?}",self.cur_token);
-            // self.next_token();
-            match self.parse_statement() {
-                Ok(statement) => statements.push(statement),
-                Err(error) => {
-                    while self.cur_token != Token::RBRACE && self.cur_token != Token::EOF {
-                        println!("err:{:?}",self.cur_token);
-                        self.next_token();
-                        //とりあえず、、、進める
-                    }
-                    self.errors.push(error)
-                }
-            }
-        }
-        self.next_token();
-        println!("2:{:?}",self.cur_token);
-        Ok(Expression::Block(statements))
-    }
-
-    fn parse_function_expression(&mut self) -> Result<Expression, ParseError> {
-        let parameters = self.parse_parameters()?;
-        println!("param {:?}",parameters);
-        self.next_token();
-        let body = self.parse_block_expression()?;
-        println!("body {:?}",body);
-        println!("3:{:?}",self.cur_token);
-        Ok(Expression::Function {
-            parameters: parameters,
-            body: Box::new(body),
-        })
-    }
-
-    fn parse_parameters(&mut self) -> Result<Vec<String>, ParseError> {
-        self.expect_peek(Token::LPAREN)?;
-        let mut parameters = vec![];
-        let mut is_first_token = true;
-        while !self.peek_token_is(&Token::RPAREN) {
-            if !is_first_token {
-                self.expect_peek(Token::COMMA)?;
-            }
-            parameters.push(self.expect_ident()?);
-            is_first_token = false;
-        }
-        self.next_token();
-        Ok(parameters)
-    }
-
-    fn expect_ident(&mut self) -> Result<String, ParseError> {
-        let name = match &self.peek_token {
-            Token::IDENT(n) => n.to_string(),
-            _ => {
-                return Err(format!(
-                    "expected next token to be IDENT, got {} instead",
-                    self.peek_token
-                ))
-            }
-        };
-        self.next_token();
-        Ok(name)
-    }
-}
-
-#[test]
-fn test_let_statements() {
-    let input = r"
-let x = 5;
-let y = 10;
-let foobar = 838383;
-";
-    let mut lex = Lexer::new(input);
-    let mut parser = Parser::new(&mut lex);
-    let program = parser.parse_program();
-    parser.print_error();
-    println!("{:?}", program);
-    assert_eq!(program.len(), 3);
-    let tests: Vec<(&str, i64)> = vec![("x", 5), ("y", 10), ("foobar", 838383)];
-    for test_pair in tests.iter().zip(program.iter()) {
-        println!("{:?}", test_pair.1);
-        if let Statement::Let { identifier, expr } = test_pair.1 {
-            assert_eq!((test_pair.0).0, identifier.to_string());
-            if let Expression::Int(int) = expr {
-                assert_eq!((test_pair.0).1, *int);
-            }
-        }
-    }
-}
-
-#[test]
-fn test_return_statements() {
-    let input = r"
-return 5;
-return 10;
-return 838383;
-";
-    let mut lex = Lexer::new(input);
-    let mut parser = Parser::new(&mut lex);
-    let program = parser.parse_program();
-    assert_eq!(program.len(), 3);
-    assert_eq!(parser.errors.len(), 0);
-}
-
-#[test]
-fn test_parse_errors() {
-    let input = r"
-let x  5;
-let  = 10;
-let  838383;
-";
-    let mut lex = Lexer::new(input);
-    let mut parser = Parser::new(&mut lex);
-    let program = parser.parse_program();
-    parser.print_error();
-    println!("{:?}", program);
-    assert_eq!(program.len(), 0);
-    assert_eq!(parser.errors.len(), 3);
-    parser.print_error();
-}
-
-#[test]
-fn test_identifier_expression() {
-    let input = r"foobar;";
-    let mut lex = Lexer::new(input);
-    let mut parser = Parser::new(&mut lex);
-    let program = parser.parse_program();
-    parser.print_error();
-    assert_eq!(program.len(), 1);
-    assert_eq!(parser.errors.len(), 0);
-    assert_eq!(
-        program[0],
-        Statement::Expr(Expression::Ident("foobar".to_string()))
-    );
-}
-
-#[test]
-fn test_int_literal_expression() {
-    let input = r"5;";
-    let mut lex = Lexer::new(input);
-    let mut parser = Parser::new(&mut lex);
-    let program = parser.parse_program();
-    parser.print_error();
-    assert_eq!(program.len(), 1);
-    assert_eq!(parser.errors.len(), 0);
-    assert_eq!(program[0], Statement::Expr(Expression::Int(5)));
-}
-
-#[test]
-fn test_prefix_expression() {
-    let input = ["!5;", "-15;", "!true;", "!false;"];
-    let test_expr = [
-        Statement::Expr(Expression::Prefix {
-            operator: Token::BANG,
-            right: Box::new(Expression::Int(5)),
-        }),
-        Statement::Expr(Expression::Prefix {
-            operator: Token::MINUS,
-            right: Box::new(Expression::Int(15)),
-        }),
-        Statement::Expr(Expression::Prefix {
-            operator: Token::BANG,
-            right: Box::new(Expression::Boolean(true)),
-        }),
-        Statement::Expr(Expression::Prefix {
-            operator: Token::BANG,
-            right: Box::new(Expression::Boolean(false)),
-        }),
-    ];
-
-    assert_eq!(input.len(), test_expr.len());
-    for i in 0..input.len() {
-        let mut lex = Lexer::new(input[i]);
-        let mut parser = Parser::new(&mut lex);
-        let program = parser.parse_program();
-        parser.print_error();
-        assert_eq!(program.len(), 1);
-        assert_eq!(parser.errors.len(), 0);
-        assert_eq!(program[0], test_expr[i]);
-    }
-}
-
-#[test]
-fn test_infix_expression() {
-    let input = [
-        "5 + 5;", "5 - 5;", "5 * 5;", "5 / 5;", "5 < 5;", "5 > 5;", "5 == 5;", "5 != 5;",
-    ];
-    let test_expr = [
-        Statement::Expr(Expression::Infix {
-            operator: Token::PLUS,
-            left: Box::new(Expression::Int(5)),
-            right: Box::new(Expression::Int(5)),
-        }),
-        Statement::Expr(Expression::Infix {
-            operator: Token::MINUS,
-            left: Box::new(Expression::Int(5)),
-            right: Box::new(Expression::Int(5)),
-        }),
-        Statement::Expr(Expression::Infix {
-            operator: Token::ASTERISK,
-            left: Box::new(Expression::Int(5)),
-            right: Box::new(Expression::Int(5)),
-        }),
-        Statement::Expr(Expression::Infix {
-            operator: Token::SLASH,
-            left: Box::new(Expression::Int(5)),
-            right: Box::new(Expression::Int(5)),
-        }),
-        Statement::Expr(Expression::Infix {
-            operator: Token::LT,
-            left: Box::new(Expression::Int(5)),
-            right: Box::new(Expression::Int(5)),
-        }),
-        Statement::Expr(Expression::Infix {
-            operator: Token::GT,
-            left: Box::new(Expression::Int(5)),
-            right: Box::new(Expression::Int(5)),
-        }),
-        Statement::Expr(Expression::Infix {
-            operator: Token::EQ,
-            left: Box::new(Expression::Int(5)),
-            right: Box::new(Expression::Int(5)),
-        }),
-        Statement::Expr(Expression::Infix {
-            operator: Token::NOTEQ,
-            left: Box::new(Expression::Int(5)),
-            right: Box::new(Expression::Int(5)),
-        }),
-    ];
-
-    for i in 0..8 {
-        let mut lex = Lexer::new(input[i]);
-        let mut parser = Parser::new(&mut lex);
-        let program = parser.parse_program();
-        parser.print_error();
-        println!("{:?}", program);
-        assert_eq!(program.len(), 1);
-        assert_eq!(parser.errors.len(), 0);
-        assert_eq!(program[0], test_expr[i]);
-    }
-}
-
-#[test]
-fn test_operator_precedence_pasing() {
-    let input = [
-        "-a * b;",
-        "!-a;",
-        "a+b+c;",
-        "a+b-c;",
-        "a*b*c;",
-        "a*b/c",
-        "a+b/c;",
-        "a+b*c+d/e-f;",
-        "3+4;-5*5;",
-        "5>4==3<4;",
-        "5<4!=3>4;",
-        "3+4*5==3*1+4*5;",
-        "true",
-        "false",
-        "!true",
-        "3>5 == false",
-        "3<5 == true",
-        "1+ (2+3)+4",
-        "(5+5)*2",
-        "2/(5+5)",
-        "-(5+5)",
-        "!(true==true)",
-    ];
-    let test_expr = [
-        "((-a)*b)",
-        "(!(-a))",
-        "((a+b)+c)",
-        "((a+b)-c)",
-        "((a*b)*c)",
-        "((a*b)/c)",
-        "(a+(b/c))",
-        "(((a+(b*c))+(d/e))-f)",
-        "(3+4)((-5)*5)",
-        "((5>4)==(3<4))",
-        "((5<4)!=(3>4))",
-        "((3+(4*5))==((3*1)+(4*5)))",
-        "true",
-        "false",
-        "(!true)",
-        "((3>5)==false)",
-        "((3<5)==true)",
-        "((1+(2+3))+4)",
-        "((5+5)*2)",
-        "(2/(5+5))",
-        "(-(5+5))",
-        "(!(true==true))",
-    ];
-    assert_eq!(input.len(), test_expr.len());
-    for i in 0..input.len() {
-        let mut lex = Lexer::new(input[i]);
-        let mut parser = Parser::new(&mut lex);
-        let program = parser.parse_program();
-        parser.print_error();
-        assert_eq!(parser.errors.len(), 0);
-        let mut result_expr = String::new();
-        for stmt in program {
-            result_expr.push_str(&stmt.to_string());
-        }
-        println!("{2:} :  {0:?} , {1:?}", result_expr, test_expr[i], i);
-        assert_eq!(result_expr, test_expr[i]);
-    }
-}
-
-#[test]
-fn test_if_expression() {
-    let input = ["if (x < y) { x }", "if (x < y) { x } else { y }"];
-    let test_expr = ["If(x<y){x}", "If(x<y){x}else{y}"];
-    assert_eq!(input.len(), test_expr.len());
-    for i in 0..input.len() {
-        let mut lex = Lexer::new(input[i]);
-        let mut parser = Parser::new(&mut lex);
-        let program = parser.parse_program();
-        parser.print_error();
-        assert_eq!(program.len(), 1);
-        assert_eq!(parser.errors.len(), 0);
-        assert_eq!(program[0].to_string(), test_expr[i]);
-    }
-}
-
-#[test]
-fn test_function_expression() {
-    let input = ["fn(x, y){ x + y; }"];
-    let test_expr = ["fn(x,y){(x+y);}"];
-    assert_eq!(input.len(), test_expr.len());
-    for i in 0..input.len() {
-        let mut lex = Lexer::new(input[i]);
-        let mut parser = Parser::new(&mut lex);
-        let program = parser.parse_program();
-        parser.print_error();
-        assert_eq!(program.len(), 1);
-        assert_eq!(parser.errors.len(), 0);
-        assert_eq!(program[0].to_string(), test_expr[i]);
-    }
-}
+use crate::ast::{Argument, Expression, MatchArm, Parameter, Pattern, Statement, TypeExpr};
+use crate::cst::{SyntaxEvent, SyntaxKind};
+use crate::lexer::Lexer;
+use crate::node_id::{number_nodes, SpanMap};
+use crate::operator::{Associativity, Fixity, InfixOperator, OperatorTable, Precedence};
+use crate::token::{Span, Token};
+use crate::token_source::TokenSource;
+use std::fmt;
+use std::ops::Range;
+
+pub type ParseError = String;
+
+/// 式の入れ子の深さの既定の上限
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// 入れ子1段を読むのに使うスタックの大きさの目安（最適化なしのビルドでの最大）
+///
+/// 構文解析は入れ子1段ごとに再帰する。最適化ありのビルドでは1段あたり4KB程度で済むが、
+/// 最適化なしでは既定の上限まで読むのに2MBを超えるので、スレッドを作って読むときは
+/// 深さの上限にこの値を掛けた大きさのスタックを用意する
+pub const STACK_PER_LEVEL: usize = 10 * 1024;
+
+/// 1つの式の中に続けて書ける演算子の数の既定の上限
+///
+/// 連鎖は再帰せずに読むので入れ子の深さには数えないが、できた構文木を辿る側
+/// （表示・整形・書き出しなど）は演算子1つごとに再帰する。辿る側も1段あたり
+/// `STACK_PER_LEVEL` 程度のスタックを使うので、この上限で構文木の深さを抑える
+pub const DEFAULT_MAX_CHAIN: usize = 1024;
+
+/// 式・文を1つだけ読み込むときのエラー
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum SnippetError {
+    /// 入力が空
+    Empty,
+    /// 構文エラー
+    Syntax(Vec<ParseError>),
+    /// 式・文の後ろに読み残したトークンがある
+    TrailingTokens(Token),
+}
+
+impl fmt::Display for SnippetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnippetError::Empty => write!(f, "empty input"),
+            SnippetError::Syntax(errors) => write!(f, "{}", errors.join("\n")),
+            SnippetError::TrailingTokens(tok) => write!(f, "unexpected trailing token {}", tok),
+        }
+    }
+}
+
+pub struct Parser<S> {
+    source: S,
+    cur_token: Token,
+    peek_token: Token,
+    errors: Vec<String>,
+    loop_depth: usize,
+    /// 読んでいるブロックの入れ子の深さ（`import` と `export` は0のときだけ書ける）
+    block_depth: usize,
+    /// matchのガード内では `(x) =>` をアロー関数として扱わない
+    in_guard: bool,
+    operators: OperatorTable,
+    /// 読み込んだトークンとその範囲（具象構文木の組み立てに使う）
+    tokens: Vec<(Token, Span)>,
+    /// `cur_token` が `tokens` の何番目か
+    cur_index: usize,
+    /// 読み終えた節点の記録
+    events: Vec<SyntaxEvent>,
+    /// 読んでいる式の入れ子の深さ（括弧・ブロック・呼び出しなど）
+    depth: usize,
+    max_depth: usize,
+    /// 読んでいる式までに続けて読んだ演算子の数（外側の式の分も含む）
+    chain: usize,
+    max_chain: usize,
+    /// 入れ子の深さが上限を超えた（ブロック内で回復せずに読むのをやめる）
+    nesting_exceeded: bool,
+    /// まだどの文にも付けていない `///` コメントと、その直後のトークンの番号
+    doc_comments: Vec<(usize, String)>,
+    /// 前に空行や普通のコメントで離れた `///` コメントがあったトークンの番号
+    detached_doc_comments: Vec<usize>,
+}
+
+/// 括弧内の要素
+enum GroupedElement {
+    /// 式として読んだ要素と、その要素の節点の記録の範囲
+    Expression(Expression, Range<usize>),
+    /// `name: type` や `...name` のように、アロー関数の仮引数としてしか読めない要素
+    Parameter(Parameter),
+}
+
+/// 右側の被演算子を待っている演算子
+enum PendingOperator {
+    Prefix(Token),
+    /// 演算子と左辺
+    Infix(Token, InfixOperator, Expression),
+}
+
+impl<S: TokenSource> Parser<S> {
+    pub fn new(source: S) -> Parser<S> {
+        Parser {
+            source,
+            cur_token: Token::EOF,
+            peek_token: Token::EOF,
+            errors: vec![],
+            loop_depth: 0,
+            block_depth: 0,
+            in_guard: false,
+            operators: OperatorTable::new(),
+            tokens: vec![],
+            cur_index: 0,
+            events: vec![],
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            chain: 0,
+            max_chain: DEFAULT_MAX_CHAIN,
+            nesting_exceeded: false,
+            doc_comments: vec![],
+            detached_doc_comments: vec![],
+        }
+    }
+
+    /// 式の入れ子の深さの上限を変える（信頼できない入力でスタックを使い切らないための制限）
+    ///
+    /// 必要なスタックの大きさは `max_depth * STACK_PER_LEVEL` を目安にする
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    /// 1つの式の中に続けて書ける演算子の数の上限を変える
+    pub fn set_max_chain(&mut self, max_chain: usize) {
+        self.max_chain = max_chain;
+    }
+
+    pub fn operators(&self) -> &OperatorTable {
+        &self.operators
+    }
+
+    /// 記号 `symbol` の演算子を定義する
+    ///
+    /// 組み込みの記号ならそのトークンの定義を変更し、そうでなければ字句解析器にも記号を登録する。
+    pub fn define_operator(&mut self, symbol: &str, fixity: Fixity, precedence: Precedence) {
+        let token = match Lexer::builtin_symbol(symbol) {
+            Some(token) => token,
+            None => {
+                self.source.add_operator(symbol);
+                Token::OPERATOR(symbol.to_string())
+            }
+        };
+        self.operators.define(token, fixity, precedence);
+    }
+
+    fn next_token(&mut self) {
+        self.cur_token = self.peek_token.clone();
+        self.peek_token = self.source.next_token();
+        self.tokens
+            .push((self.peek_token.clone(), self.source.span()));
+        if let Some(doc) = self.source.doc_comment() {
+            self.doc_comments.push((self.tokens.len() - 1, doc));
+        }
+        if self.source.detached_doc_comment() {
+            self.detached_doc_comments.push(self.tokens.len() - 1);
+        }
+        self.cur_index = self.tokens.len().saturating_sub(2);
+    }
+
+    /// これまでに読み込んだトークンとその範囲
+    pub fn tokens(&self) -> &[(Token, Span)] {
+        &self.tokens
+    }
+
+    /// 構文エラーではないが意味を持たない記述への警告
+    ///
+    /// 今のところ、`let`・`const` と関数宣言の前以外に書いた `///` コメントと、
+    /// 空行や普通のコメントでトークンから離れた `///` コメントを報告する。
+    pub fn lints(&self) -> Vec<String> {
+        let unattached = self
+            .doc_comments
+            .iter()
+            .map(|(index, _)| (*index, "is not attached to a let, const or fn declaration"));
+        let detached = self
+            .detached_doc_comments
+            .iter()
+            .map(|index| (*index, "is separated from it by a blank line or comment"));
+        let mut lints: Vec<(usize, &str)> = unattached.chain(detached).collect();
+        lints.sort_by_key(|(index, _)| *index);
+        lints
+            .into_iter()
+            .map(|(index, problem)| {
+                let (token, span) = &self.tokens[index];
+                format!("doc comment before {} at {} {}", token, span, problem)
+            })
+            .collect()
+    }
+
+    /// `index` 番目のトークンの前の `///` コメントを取り出す
+    fn take_doc_comment(&mut self, index: usize) -> Option<String> {
+        let position = self
+            .doc_comments
+            .iter()
+            .position(|(token, _)| *token == index)?;
+        Some(self.doc_comments.remove(position).1)
+    }
+
+    /// 読み終えた節点の記録（子が親より先に並ぶ）
+    pub fn syntax_events(&self) -> &[SyntaxEvent] {
+        &self.events
+    }
+
+    /// 読み終えた文・式の入力中の範囲（番号は `node_id::number_nodes` の順）
+    ///
+    /// `parse_program` が返した構文木に対してだけ使える
+    pub fn span_map(&self) -> SpanMap {
+        SpanMap::from_events(&self.tokens, &self.events)
+    }
+
+    /// `first` 番目から現在のトークンまでを1つの節点として記録する
+    fn finish_node(&mut self, kind: SyntaxKind, first: usize) {
+        self.events.push(SyntaxEvent {
+            kind,
+            first,
+            last: self.cur_index,
+        });
+    }
+
+    /// 読み飛ばした文の記録を、それ全体を覆う誤りの節点1つに置き換える
+    fn finish_error_node(&mut self, checkpoint: usize, first: usize) {
+        self.events.truncate(checkpoint);
+        let last = match self.cur_token {
+            Token::SEMICOLON => self.cur_index,
+            _ => self.cur_index.saturating_sub(1),
+        };
+        if first <= last {
+            self.events.push(SyntaxEvent {
+                kind: SyntaxKind::Error,
+                first,
+                last,
+            });
+        }
+    }
+
+    pub fn parse_program(&mut self) -> Vec<Statement> {
+        let mut statements: Vec<Statement> = Vec::new();
+        self.next_token();
+        self.next_token();
+        while self.cur_token != Token::EOF {
+            let (checkpoint, first) = (self.events.len(), self.cur_index);
+            match self.parse_statement() {
+                Ok(statement) => statements.push(statement),
+                Err(error) => {
+                    // 深すぎる入力は読み直しても同じなので、残りを読み飛ばして終える
+                    while (self.nesting_exceeded || self.cur_token != Token::SEMICOLON)
+                        && self.cur_token != Token::EOF
+                    {
+                        self.next_token();
+                        //とりあえず、、、進める
+                    }
+                    self.finish_error_node(checkpoint, first);
+                    self.errors.push(error)
+                }
+            }
+            self.next_token();
+        }
+        // 節点の番号は、文・式を読み終えた順と帰りがけ順が一致することを前提にしている
+        debug_assert_eq!(self.span_map().len(), number_nodes(&statements).len());
+        statements
+    }
+
+    /// 1つの式・文を読み込み、入力をすべて消費したか確かめる
+    fn parse_snippet<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, SnippetError> {
+        self.next_token();
+        self.next_token();
+        if self.cur_token == Token::EOF {
+            return Err(SnippetError::Empty);
+        }
+        let result = parse(self);
+        match result {
+            Err(error) => {
+                self.errors.push(error);
+                Err(SnippetError::Syntax(self.errors.clone()))
+            }
+            Ok(_) if !self.errors.is_empty() => Err(SnippetError::Syntax(self.errors.clone())),
+            Ok(_) if self.peek_token != Token::EOF => {
+                Err(SnippetError::TrailingTokens(self.peek_token.clone()))
+            }
+            Ok(value) => Ok(value),
+        }
+    }
+
+    fn parse_statement(&mut self) -> Result<Statement, ParseError> {
+        let first = self.cur_index;
+        let mut statement = match self.cur_token {
+            Token::LET => self.parse_let_statement(),
+            Token::CONST => self.parse_const_statement(),
+            Token::RETURN => self.parse_return_statement(),
+            Token::BREAK | Token::CONTINUE => self.parse_loop_control_statement(),
+            Token::INFIXL | Token::INFIXR | Token::INFIX => self.parse_operator_declaration(),
+            Token::IMPORT => self.parse_import_statement(),
+            Token::EXPORT => self.parse_export_statement(),
+            Token::FUNCTION if self.peek_token_is(&Token::IDENT(String::new())) => {
+                self.parse_fn_declaration()
+            }
+            _ => self.parse_expr_statement(),
+        }?;
+        // `export` の対象の文は `export` の前のコメントを受け取る
+        if let Some(doc @ None) = statement.doc_mut() {
+            *doc = self.take_doc_comment(first);
+        }
+        self.finish_node(SyntaxKind::of_statement(&statement), first);
+        Ok(statement)
+    }
+
+    /// `let x = e;` のほか `let [a, ...rest] = e;` や `let {"k": v} = e;` で分解して束縛できる
+    ///
+    /// `let x: int = e;` のように型の注釈を付けられる
+    fn parse_let_statement(&mut self) -> Result<Statement, ParseError> {
+        match self.peek_token {
+            Token::IDENT(_) | Token::UNDERSCORE | Token::LBRACKET | Token::LBRACE => {
+                self.next_token()
+            }
+            _ => {
+                return Err(format!(
+                    "expected next token to be IDENT, got {} instead",
+                    self.peek_token
+                ))
+            }
+        }
+        let pattern = self.parse_pattern()?;
+        check_bindings(&pattern)?;
+        let annotation = self.parse_annotation()?;
+        self.expect_peek(Token::ASSIGN)?;
+        self.next_token();
+        let ret_val = Ok(Statement::Let {
+            pattern,
+            annotation,
+            expr: self.parse_expression(Precedence::LOWEST)?,
+            doc: None,
+        });
+        self.expect_semicolon()?;
+        ret_val
+    }
+    fn expect_top_level(&self) -> Result<(), ParseError> {
+        match self.block_depth {
+            0 => Ok(()),
+            _ => Err(format!(
+                "{} must be at the top level of a module",
+                self.cur_token
+            )),
+        }
+    }
+    fn parse_import_statement(&mut self) -> Result<Statement, ParseError> {
+        self.expect_top_level()?;
+        let path = match &self.peek_token {
+            Token::STRING(path) => path.clone(),
+            _ => {
+                return Err(format!(
+                    "expected next token to be a module path string, got {} instead",
+                    self.peek_token
+                ))
+            }
+        };
+        self.next_token();
+        self.expect_peek(Token::AS)?;
+        let alias = self.expect_ident()?;
+        self.expect_semicolon()?;
+        Ok(Statement::Import { path, alias })
+    }
+    fn parse_export_statement(&mut self) -> Result<Statement, ParseError> {
+        self.expect_top_level()?;
+        match self.peek_token {
+            Token::LET | Token::CONST | Token::FUNCTION => self.next_token(),
+            _ => {
+                return Err(format!(
+                    "expected let, const or fn after export, got {} instead",
+                    self.peek_token
+                ))
+            }
+        }
+        match self.parse_statement()? {
+            stmt @ (Statement::Let { .. } | Statement::Const { .. } | Statement::FnDecl { .. }) => {
+                Ok(Statement::Export(Box::new(stmt)))
+            }
+            stmt => Err(format!("cannot export {}", stmt)),
+        }
+    }
+    fn parse_const_statement(&mut self) -> Result<Statement, ParseError> {
+        let name = self.expect_ident()?;
+        let annotation = self.parse_annotation()?;
+        self.expect_peek(Token::ASSIGN)?;
+        self.next_token();
+        let expr = self.parse_expression(Precedence::LOWEST)?;
+        self.expect_semicolon()?;
+        Ok(Statement::Const {
+            name,
+            annotation,
+            expr,
+            doc: None,
+        })
+    }
+
+    fn parse_return_statement(&mut self) -> Result<Statement, ParseError> {
+        self.next_token();
+        let ret_val = Statement::Return(self.parse_expression(Precedence::LOWEST)?);
+        if self.peek_token_is(&Token::SEMICOLON) {
+            self.next_token();
+        }
+        Ok(ret_val)
+    }
+
+    /// `fn name(params) -> type { body }` 形式の関数宣言
+    fn parse_fn_declaration(&mut self) -> Result<Statement, ParseError> {
+        let name = self.expect_ident()?;
+        let parameters = self.parse_parameters()?;
+        let return_type = self.parse_return_type()?;
+        let body = self.parse_function_body()?;
+        if self.peek_token_is(&Token::SEMICOLON) {
+            self.next_token();
+        }
+        Ok(Statement::FnDecl {
+            name,
+            parameters,
+            return_type,
+            body,
+            doc: None,
+        })
+    }
+
+    /// `infixl 65 <+>;` 宣言した演算子は以降の式で使える
+    fn parse_operator_declaration(&mut self) -> Result<Statement, ParseError> {
+        let associativity = match self.cur_token {
+            Token::INFIXL => Associativity::Left,
+            Token::INFIXR => Associativity::Right,
+            _ => Associativity::None,
+        };
+        self.next_token();
+        let precedence = match self.cur_token {
+            Token::INT(val) if (0..=i64::from(u8::MAX)).contains(&val) => val as u8,
+            _ => {
+                return Err(format!(
+                    "expected precedence between 0 and 255, got {} instead",
+                    self.cur_token
+                ))
+            }
+        };
+        self.next_token();
+        let operator = match &self.cur_token {
+            Token::OPERATOR(symbol) if Lexer::contains_comment_marker(symbol) => {
+                return Err(format!(
+                    "cannot declare fixity of {}, which contains the comment marker //",
+                    symbol
+                ))
+            }
+            Token::OPERATOR(symbol) => symbol.clone(),
+            tok => return Err(format!("cannot declare fixity of built-in token {}", tok)),
+        };
+        self.define_operator(
+            &operator,
+            Fixity::Infix(associativity),
+            Precedence(precedence),
+        );
+        if self.peek_token_is(&Token::SEMICOLON) {
+            self.next_token();
+        }
+        Ok(Statement::OperatorDecl {
+            associativity,
+            precedence,
+            operator,
+        })
+    }
+
+    /// break / continue はループ本体の中でのみ許可する
+    fn parse_loop_control_statement(&mut self) -> Result<Statement, ParseError> {
+        let statement = match self.cur_token {
+            Token::BREAK => Statement::Break,
+            _ => Statement::Continue,
+        };
+        if self.loop_depth == 0 {
+            return Err(format!("{} outside of loop", self.cur_token));
+        }
+        if self.peek_token_is(&Token::SEMICOLON) {
+            self.next_token();
+        }
+        Ok(statement)
+    }
+
+    fn parse_expr_statement(&mut self) -> Result<Statement, ParseError> {
+        let expr = self.parse_expression(Precedence::LOWEST)?;
+        if self.peek_token_is(&Token::SEMICOLON) {
+            self.next_token();
+        }
+        Ok(Statement::Expr(expr))
+    }
+
+    fn peek_token_is(&self, tok: &Token) -> bool {
+        match (&tok, &self.peek_token) {
+            (Token::IDENT(_), Token::IDENT(_)) => true,
+            (Token::INT(_), Token::INT(_)) => true,
+            (Token::OPERATOR(_), Token::OPERATOR(_)) => true,
+            _ => tok == &self.peek_token,
+        }
+    }
+
+    fn expect_peek(&mut self, tok: Token) -> Result<(), ParseError> {
+        if self.peek_token_is(&tok) {
+            self.next_token();
+            Ok(())
+        } else {
+            Err(format!(
+                "expected next token to be {}, got {} instead",
+                tok, self.peek_token
+            ))
+        }
+    }
+
+    /// 文の終わりの `;`（入力の終わりでは省略できる）
+    fn expect_semicolon(&mut self) -> Result<(), ParseError> {
+        match self.peek_token {
+            Token::EOF => Ok(()),
+            _ => self.expect_peek(Token::SEMICOLON),
+        }
+    }
+
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    pub fn print_error(&self) {
+        for i in self.errors.iter() {
+            println!("{}", i);
+        }
+    }
+
+    fn parse_expression(&mut self, precedence: Precedence) -> Result<Expression, ParseError> {
+        let chain = self.chain;
+        self.enter_nesting()?;
+        let expression = self.parse_operator_chain(precedence);
+        self.depth -= 1;
+        // 連鎖の中で数えた演算子の分をまとめて戻す
+        self.chain = chain;
+        expression
+    }
+
+    /// 入れ子を1段深くする。上限を超える入力は、スタックを使い切る前に構文エラーにする
+    fn enter_nesting(&mut self) -> Result<(), ParseError> {
+        if self.depth >= self.max_depth {
+            self.nesting_exceeded = true;
+            return Err(format!(
+                "expression nested too deeply (limit {})",
+                self.max_depth
+            ));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// 連鎖の演算子を1つ数える。上限を超える入力は、構文木を辿る側がスタックを
+    /// 使い切らないよう構文エラーにする
+    fn count_operator(&mut self) -> Result<(), ParseError> {
+        if self.chain >= self.max_chain {
+            self.nesting_exceeded = true;
+            return Err(format!(
+                "operator chain too long (limit {})",
+                self.max_chain
+            ));
+        }
+        self.chain += 1;
+        Ok(())
+    }
+
+    /// 前置演算子と中置演算子の右辺は再帰せず `pending` に積んで読むので、
+    /// `!!!!x` や `a ** b ** c ** ...` のような長い連鎖でもスタックは深くならない
+    ///
+    /// 構文木を辿る側は再帰するので、演算子の数は `count_operator` で別に数える
+    fn parse_operator_chain(&mut self, precedence: Precedence) -> Result<Expression, ParseError> {
+        // 読みかけの演算子と、それを含む式の先頭のトークンの番号・優先順位
+        let mut pending: Vec<(PendingOperator, usize, Precedence)> = vec![];
+        let mut first = self.cur_index;
+        let mut precedence = precedence;
+        'operand: loop {
+            while let Some(operand) = self.operators.prefix(&self.cur_token) {
+                self.count_operator()?;
+                let operator = PendingOperator::Prefix(self.cur_token.clone());
+                pending.push((operator, first, precedence));
+                precedence = operand;
+                self.next_token();
+                first = self.cur_index;
+            }
+            let mut left = self.parse_operand()?;
+            self.finish_node(SyntaxKind::of_expression(&left), first);
+            loop {
+                if !self.peek_token_is(&Token::SEMICOLON)
+                    && precedence < self.operators.binding_power(&self.peek_token)
+                {
+                    self.next_token();
+                    self.count_operator()?;
+                    match self.operators.infix(&self.cur_token) {
+                        Some(operator) if !self.cur_token_is_suffix() => {
+                            let token = self.cur_token.clone();
+                            let pending_operator = PendingOperator::Infix(token, operator, left);
+                            pending.push((pending_operator, first, precedence));
+                            precedence = operator.right_operand();
+                            self.next_token();
+                            first = self.cur_index;
+                            continue 'operand;
+                        }
+                        _ => left = self.parse_suffix(left)?,
+                    }
+                } else {
+                    // この段の式を読み終えたので、読みかけの演算子に渡す
+                    let (operator, outer_first, outer_precedence) = match pending.pop() {
+                        Some(entry) => entry,
+                        None => return Ok(left),
+                    };
+                    left = self.finish_operator(operator, left)?;
+                    first = outer_first;
+                    precedence = outer_precedence;
+                }
+                self.finish_node(SyntaxKind::of_expression(&left), first);
+            }
+        }
+    }
+
+    /// 左辺の後ろに続けて読む、中置演算子として扱わない構文のトークン
+    fn cur_token_is_suffix(&self) -> bool {
+        matches!(
+            self.cur_token,
+            Token::LPAREN | Token::PIPELINE | Token::DOT | Token::QUESTION
+        )
+    }
+
+    /// 呼び出し・パイプ・メンバー参照・三項演算子・後置演算子を読み込む
+    ///
+    /// 再帰の経路にある `parse_operator_chain` のスタックフレームを小さく保つため、別の関数にする
+    fn parse_suffix(&mut self, left: Expression) -> Result<Expression, ParseError> {
+        match &self.cur_token {
+            Token::LPAREN => self.parse_call_expression(left),
+            Token::PIPELINE => self.parse_pipe_expression(left),
+            Token::DOT => Ok(Expression::Member {
+                object: Box::new(left),
+                name: self.expect_ident()?,
+            }),
+            Token::QUESTION => self.parse_ternary_expression(left),
+            tok => Ok(Expression::Postfix {
+                operator: tok.clone(),
+                left: Box::new(left),
+            }),
+        }
+    }
+
+    /// 演算子を含まない式の先頭部分
+    fn parse_operand(&mut self) -> Result<Expression, ParseError> {
+        match &self.cur_token {
+            Token::IDENT(ident) => Ok(Expression::Ident(ident.clone())),
+            Token::INT(val) => Ok(Expression::Int(*val)),
+            Token::FALSE => Ok(Expression::Boolean(false)),
+            Token::TRUE => Ok(Expression::Boolean(true)),
+            Token::STRING(val) => Ok(Expression::Str(val.clone())),
+            Token::LPAREN => self.parse_grouped_expression(),
+            Token::PIPE => self.parse_pipe_function(),
+            Token::IF => self.parse_if_expression(),
+            Token::FUNCTION => self.parse_function_expression(),
+            Token::MACRO => self.parse_macro_literal(),
+            Token::WHILE => self.parse_while_expression(),
+            Token::FOR => self.parse_for_expression(),
+            Token::MATCH => self.parse_match_expression(),
+            _ => Err(format!("Parse Expression:Unknown token {}", self.cur_token)),
+        }
+    }
+
+    /// 右側の被演算子を読み終えた演算子の式を組み立てる
+    fn finish_operator(
+        &mut self,
+        operator: PendingOperator,
+        right: Expression,
+    ) -> Result<Expression, ParseError> {
+        match operator {
+            PendingOperator::Prefix(token) => Ok(Expression::Prefix {
+                operator: token,
+                right: Box::new(right),
+            }),
+            PendingOperator::Infix(token, operator, left) => {
+                if operator.associativity == Associativity::None {
+                    if let Some(next) = self.operators.infix(&self.peek_token) {
+                        if next.precedence == operator.precedence {
+                            return Err(format!(
+                                "non-associative operator {} cannot be chained with {}",
+                                token, self.peek_token
+                            ));
+                        }
+                    }
+                }
+                if token.is_assignment() {
+                    if !matches!(left, Expression::Ident(_)) {
+                        return Err(format!("cannot assign to {}", left));
+                    }
+                    return Ok(Expression::Assign {
+                        operator: token,
+                        target: Box::new(left),
+                        value: Box::new(right),
+                    });
+                }
+                Ok(Expression::Infix {
+                    left: Box::new(left),
+                    operator: token,
+                    right: Box::new(right),
+                })
+            }
+        }
+    }
+
+    /// `(expr)` または `(x, y: int = 1, ...rest) => body` を読み込む
+    fn parse_grouped_expression(&mut self) -> Result<Expression, ParseError> {
+        let in_guard = std::mem::replace(&mut self.in_guard, false);
+        let elements = self.parse_grouped_elements();
+        self.in_guard = in_guard;
+        let mut elements = elements?;
+        if !self.in_guard && self.peek_token_is(&Token::FATARROW) {
+            self.next_token();
+            // 後ろの要素から付け替えると、節点の記録を除いても前の要素の範囲はずれない
+            let mut parameters = vec![];
+            while let Some(element) = elements.pop() {
+                parameters.push(self.grouped_parameter(element)?);
+            }
+            parameters.reverse();
+            for (index, parameter) in parameters.iter().enumerate() {
+                check_parameter(&parameters[..index], parameter)?;
+            }
+            return self.parse_arrow_body(parameters);
+        }
+        match elements.pop() {
+            Some(GroupedElement::Parameter(_)) => {
+                Err("expected => after arrow function parameters".to_string())
+            }
+            Some(GroupedElement::Expression(expression, _)) if elements.is_empty() => {
+                Ok(Expression::Grouped(Box::new(expression)))
+            }
+            _ => Err("expected => after parenthesized list".to_string()),
+        }
+    }
+
+    /// 括弧内の要素をアロー関数の仮引数として読み直し、節点の記録も仮引数のものに付け替える
+    fn grouped_parameter(&mut self, element: GroupedElement) -> Result<Parameter, ParseError> {
+        let (expression, events) = match element {
+            GroupedElement::Parameter(parameter) => return Ok(parameter),
+            GroupedElement::Expression(expression, events) => (expression, events),
+        };
+        match expression {
+            Expression::Ident(name) => {
+                self.events[events.end - 1].kind = SyntaxKind::Param;
+                Ok(Parameter::new(&name))
+            }
+            Expression::Assign {
+                operator: Token::ASSIGN,
+                target,
+                value,
+            } if matches!(*target, Expression::Ident(_)) => {
+                // 名前の節点を除き、代入の節点を既定値付きの仮引数の節点にする
+                self.events.remove(events.start);
+                self.events[events.end - 2].kind = SyntaxKind::Param;
+                Ok(Parameter {
+                    default: Some(*value),
+                    ..Parameter::new(&target.to_string())
+                })
+            }
+            other => Err(format!("invalid arrow function parameter {}", other)),
+        }
+    }
+
+    /// 括弧内のカンマ区切りの要素を `)` まで読み込む
+    ///
+    /// `...name` と、名前の後ろに `: type` を付けた要素はアロー関数の仮引数として読む
+    fn parse_grouped_elements(&mut self) -> Result<Vec<GroupedElement>, ParseError> {
+        let mut elements = vec![];
+        while !self.peek_token_is(&Token::RPAREN) {
+            if !elements.is_empty() {
+                self.expect_peek(Token::COMMA)?;
+            }
+            if self.peek_token_is(&Token::ELLIPSIS) {
+                let parameter = self.parse_parameter(&Token::RPAREN)?;
+                elements.push(GroupedElement::Parameter(parameter));
+                continue;
+            }
+            self.next_token();
+            let first = self.cur_index;
+            let start = self.events.len();
+            let element = self.parse_expression(Precedence::LOWEST)?;
+            let element = match element {
+                Expression::Ident(name) if self.peek_token_is(&Token::COLON) => {
+                    // 名前の節点を除き、型と既定値を含めて仮引数の節点にする
+                    self.events.truncate(start);
+                    let annotation = self.parse_annotation()?;
+                    let default = self.parse_default(&Token::RPAREN)?;
+                    self.finish_node(SyntaxKind::Param, first);
+                    GroupedElement::Parameter(Parameter {
+                        annotation,
+                        default,
+                        ..Parameter::new(&name)
+                    })
+                }
+                element => GroupedElement::Expression(element, start..self.events.len()),
+            };
+            elements.push(element);
+        }
+        self.next_token();
+        if elements.is_empty() && !self.peek_token_is(&Token::FATARROW) {
+            return Err(format!(
+                "expected next token to be {}, got {} instead",
+                Token::FATARROW,
+                self.peek_token
+            ));
+        }
+        Ok(elements)
+    }
+
+    /// `|x, y| body`
+    fn parse_pipe_function(&mut self) -> Result<Expression, ParseError> {
+        let parameters = self.parse_parameter_list(Token::PIPE)?;
+        self.parse_arrow_body(parameters)
+    }
+
+    /// アロー関数の本体の式を、その式だけを含むブロックとして読み込む
+    fn parse_arrow_body(&mut self, parameters: Vec<Parameter>) -> Result<Expression, ParseError> {
+        self.next_token();
+        let first = self.cur_index;
+        let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        let body = self.parse_expression(Precedence::LOWEST);
+        self.loop_depth = loop_depth;
+        let body = body?;
+        self.finish_node(SyntaxKind::ExprStmt, first);
+        self.finish_node(SyntaxKind::BlockExpr, first);
+        Ok(Expression::Function {
+            parameters,
+            return_type: None,
+            body: Box::new(Expression::Block(vec![Statement::Expr(body)])),
+        })
+    }
+
+    /// `left |> right` は `right` が呼び出しなら `left` を第1引数として渡す
+    fn parse_pipe_expression(&mut self, left: Expression) -> Result<Expression, ParseError> {
+        self.next_token();
+        Ok(Expression::Pipe {
+            left: Box::new(left),
+            right: Box::new(self.parse_expression(Precedence::PIPE)?),
+        })
+    }
+
+    /// `cond ? a : b` 右結合なので代替部は同じ優先順位の `?` まで取り込む
+    fn parse_ternary_expression(
+        &mut self,
+        condition: Expression,
+    ) -> Result<Expression, ParseError> {
+        let alternative_precedence = match self.operators.infix(&Token::QUESTION) {
+            Some(operator) => operator.right_operand(),
+            None => Precedence::TERNARY.lower(),
+        };
+        self.next_token();
+        let consequence = self.parse_expression(Precedence::LOWEST)?;
+        self.expect_peek(Token::COLON)?;
+        self.next_token();
+        let alternative = self.parse_expression(alternative_precedence)?;
+        Ok(Expression::Ternary {
+            condition: Box::new(condition),
+            consequence: Box::new(consequence),
+            alternative: Box::new(alternative),
+        })
+    }
+
+    fn parse_call_expression(&mut self, function: Expression) -> Result<Expression, ParseError> {
+        let in_guard = std::mem::replace(&mut self.in_guard, false);
+        let arguments = self.parse_arguments();
+        self.in_guard = in_guard;
+        Ok(Expression::Call {
+            function: Box::new(function),
+            arguments: arguments?,
+        })
+    }
+
+    fn parse_arguments(&mut self) -> Result<Vec<Argument>, ParseError> {
+        let mut arguments: Vec<Argument> = vec![];
+        while !self.peek_token_is(&Token::RPAREN) {
+            if !arguments.is_empty() {
+                self.expect_peek(Token::COMMA)?;
+            }
+            self.next_token();
+            let first = self.cur_index;
+            let argument = match (&self.cur_token, &self.peek_token) {
+                (Token::IDENT(name), Token::COLON) => {
+                    let name = name.clone();
+                    if arguments
+                        .iter()
+                        .any(|arg| matches!(arg, Argument::Keyword(n, _) if n == &name))
+                    {
+                        return Err(format!("duplicate keyword argument {}", name));
+                    }
+                    self.next_token();
+                    self.next_token();
+                    Argument::Keyword(name, self.parse_expression(Precedence::LOWEST)?)
+                }
+                _ => {
+                    if let Some(Argument::Keyword(name, _)) = arguments.last() {
+                        return Err(format!(
+                            "positional argument follows keyword argument {}",
+                            name
+                        ));
+                    }
+                    Argument::Positional(self.parse_expression(Precedence::LOWEST)?)
+                }
+            };
+            self.finish_node(SyntaxKind::Arg, first);
+            arguments.push(argument);
+        }
+        self.next_token();
+        Ok(arguments)
+    }
+
+    fn parse_if_expression(&mut self) -> Result<Expression, ParseError> {
+        let condition = self.parse_condition()?;
+        self.expect_peek(Token::LBRACE)?;
+        let consequence = self.parse_block_expression()?;
+        let alternative = if self.peek_token_is(&Token::ELSE) {
+            self.next_token();
+            // else if はブロックで包まずにif式をそのまま代替部とする
+            if self.peek_token_is(&Token::IF) {
+                self.next_token();
+                let first = self.cur_index;
+                self.enter_nesting()?;
+                let alternative = self.parse_if_expression();
+                self.depth -= 1;
+                let alternative = alternative?;
+                self.finish_node(SyntaxKind::IfExpr, first);
+                Some(Box::new(alternative))
+            } else {
+                self.expect_peek(Token::LBRACE)?;
+                Some(Box::new(self.parse_block_expression()?))
+            }
+        } else {
+            None
+        };
+        Ok(Expression::If {
+            condition: Box::new(condition),
+            consequence: Box::new(consequence),
+            alternative,
+        })
+    }
+
+    /// `(` 条件式 `)` を読み込む
+    fn parse_condition(&mut self) -> Result<Expression, ParseError> {
+        self.expect_peek(Token::LPAREN)?;
+        self.next_token();
+        let condition = self.parse_expression(Precedence::LOWEST)?;
+        self.expect_peek(Token::RPAREN)?;
+        Ok(condition)
+    }
+
+    fn parse_while_expression(&mut self) -> Result<Expression, ParseError> {
+        let condition = self.parse_condition()?;
+        self.expect_peek(Token::LBRACE)?;
+        let body = self.parse_loop_body()?;
+        Ok(Expression::While {
+            condition: Box::new(condition),
+            body: Box::new(body),
+        })
+    }
+
+    fn parse_for_expression(&mut self) -> Result<Expression, ParseError> {
+        self.expect_peek(Token::LPAREN)?;
+        let variable = self.expect_ident()?;
+        self.expect_peek(Token::IN)?;
+        self.next_token();
+        let iterable = self.parse_expression(Precedence::LOWEST)?;
+        self.expect_peek(Token::RPAREN)?;
+        self.expect_peek(Token::LBRACE)?;
+        let body = self.parse_loop_body()?;
+        Ok(Expression::For {
+            variable,
+            iterable: Box::new(iterable),
+            body: Box::new(body),
+        })
+    }
+
+    fn parse_loop_body(&mut self) -> Result<Expression, ParseError> {
+        self.loop_depth += 1;
+        let body = self.parse_block_expression();
+        self.loop_depth -= 1;
+        body
+    }
+
+    fn parse_match_expression(&mut self) -> Result<Expression, ParseError> {
+        let subject = self.parse_condition()?;
+        self.expect_peek(Token::LBRACE)?;
+        let mut arms = vec![];
+        while !self.peek_token_is(&Token::RBRACE) {
+            self.next_token();
+            arms.push(self.parse_match_arm()?);
+            if !self.peek_token_is(&Token::RBRACE) {
+                self.expect_peek(Token::COMMA)?;
+            }
+        }
+        self.next_token();
+        Ok(Expression::Match {
+            subject: Box::new(subject),
+            arms,
+        })
+    }
+
+    fn parse_match_arm(&mut self) -> Result<MatchArm, ParseError> {
+        let first = self.cur_index;
+        let pattern = self.parse_pattern()?;
+        check_bindings(&pattern)?;
+        let guard = if self.peek_token_is(&Token::IF) {
+            self.next_token();
+            self.next_token();
+            let in_guard = std::mem::replace(&mut self.in_guard, true);
+            let guard = self.parse_expression(Precedence::LOWEST);
+            self.in_guard = in_guard;
+            Some(guard?)
+        } else {
+            None
+        };
+        self.expect_peek(Token::FATARROW)?;
+        self.next_token();
+        let body = match self.cur_token {
+            Token::LBRACE => self.parse_block_expression()?,
+            _ => self.parse_expression(Precedence::LOWEST)?,
+        };
+        self.finish_node(SyntaxKind::MatchArm, first);
+        Ok(MatchArm {
+            pattern,
+            guard,
+            body,
+        })
+    }
+
+    fn parse_pattern(&mut self) -> Result<Pattern, ParseError> {
+        let first = self.cur_index;
+        let pattern = match &self.cur_token {
+            Token::UNDERSCORE => Ok(Pattern::Wildcard),
+            Token::IDENT(name) => Ok(Pattern::Ident(name.clone())),
+            Token::LBRACKET => {
+                let mut elements = vec![];
+                let mut rest = None;
+                while !self.peek_token_is(&Token::RBRACKET) {
+                    if let Some(rest) = rest {
+                        return Err(format!(
+                            "rest element ...{} must be the last element of an array pattern",
+                            rest
+                        ));
+                    }
+                    if !elements.is_empty() {
+                        self.expect_peek(Token::COMMA)?;
+                    }
+                    if self.peek_token_is(&Token::ELLIPSIS) {
+                        self.next_token();
+                        rest = Some(self.expect_ident()?);
+                        continue;
+                    }
+                    self.next_token();
+                    elements.push(self.parse_nested_pattern()?);
+                }
+                self.next_token();
+                Ok(Pattern::Array { elements, rest })
+            }
+            Token::LBRACE => {
+                let mut pairs = vec![];
+                while !self.peek_token_is(&Token::RBRACE) {
+                    if !pairs.is_empty() {
+                        self.expect_peek(Token::COMMA)?;
+                    }
+                    self.next_token();
+                    let first = self.cur_index;
+                    let key = self.parse_literal_pattern()?;
+                    self.finish_node(SyntaxKind::LiteralPat, first);
+                    self.expect_peek(Token::COLON)?;
+                    self.next_token();
+                    pairs.push((key, self.parse_nested_pattern()?));
+                }
+                self.next_token();
+                Ok(Pattern::Hash(pairs))
+            }
+            _ => self.parse_literal_pattern(),
+        }?;
+        self.finish_node(SyntaxKind::of_pattern(&pattern), first);
+        Ok(pattern)
+    }
+
+    /// 配列・ハッシュパターンの要素（式と同じく入れ子の深さに数える）
+    fn parse_nested_pattern(&mut self) -> Result<Pattern, ParseError> {
+        self.enter_nesting()?;
+        let pattern = self.parse_pattern();
+        self.depth -= 1;
+        pattern
+    }
+
+    fn parse_literal_pattern(&mut self) -> Result<Pattern, ParseError> {
+        match &self.cur_token {
+            Token::INT(val) => Ok(Pattern::Int(*val)),
+            Token::MINUS => match self.peek_token {
+                Token::INT(val) => {
+                    self.next_token();
+                    Ok(Pattern::Int(-val))
+                }
+                _ => Err(format!(
+                    "expected INT after -, got {} instead",
+                    self.peek_token
+                )),
+            },
+            Token::TRUE => Ok(Pattern::Boolean(true)),
+            Token::FALSE => Ok(Pattern::Boolean(false)),
+            Token::STRING(val) => Ok(Pattern::Str(val.clone())),
+            _ => Err(format!("Parse Pattern:Unknown token {}", self.cur_token)),
+        }
+    }
+
+    fn parse_block_expression(&mut self) -> Result<Expression, ParseError> {
+        let block_first = self.cur_index;
+        let in_guard = std::mem::replace(&mut self.in_guard, false);
+        let statements = self.parse_block_statements();
+        self.in_guard = in_guard;
+        let statements = statements?;
+        self.finish_node(SyntaxKind::BlockExpr, block_first);
+        Ok(Expression::Block(statements))
+    }
+
+    /// `{` の直後から `}` までの文を読み込む
+    fn parse_block_statements(&mut self) -> Result<Vec<Statement>, ParseError> {
+        self.block_depth += 1;
+        let statements = self.parse_block_body();
+        self.block_depth -= 1;
+        statements
+    }
+    fn parse_block_body(&mut self) -> Result<Vec<Statement>, ParseError> {
+        let mut statements: Vec<Statement> = Vec::new();
+        self.next_token();
+        while self.cur_token != Token::RBRACE && self.cur_token != Token::EOF {
+            let (checkpoint, first) = (self.events.len(), self.cur_index);
+            match self.parse_statement() {
+                Ok(statement) => statements.push(statement),
+                Err(error) if self.nesting_exceeded => return Err(error),
+                Err(error) => {
+                    self.errors.push(error);
+                    while self.cur_token != Token::SEMICOLON
+                        && self.cur_token != Token::RBRACE
+                        && self.cur_token != Token::EOF
+                    {
+                        self.next_token();
+                        //とりあえず、、、進める
+                    }
+                    self.finish_error_node(checkpoint, first);
+                    if self.cur_token != Token::SEMICOLON {
+                        continue;
+                    }
+                }
+            }
+            self.next_token();
+        }
+        if self.cur_token != Token::RBRACE {
+            return Err(format!("expected }}, got {} instead", self.cur_token));
+        }
+        Ok(statements)
+    }
+
+    fn parse_function_expression(&mut self) -> Result<Expression, ParseError> {
+        let parameters = self.parse_parameters()?;
+        let return_type = self.parse_return_type()?;
+        let body = self.parse_function_body()?;
+        Ok(Expression::Function {
+            parameters,
+            return_type,
+            body: Box::new(body),
+        })
+    }
+
+    /// `macro(a, b) { body }` 実引数は構文木として渡すので、残りの実引数はまとめられない
+    fn parse_macro_literal(&mut self) -> Result<Expression, ParseError> {
+        let parameters = self.parse_parameters()?;
+        if let Some(rest) = parameters.iter().find(|p| p.rest) {
+            return Err(format!(
+                "macro parameter {} cannot be a rest parameter",
+                rest.name
+            ));
+        }
+        let body = self.parse_function_body()?;
+        Ok(Expression::MacroLiteral {
+            parameters,
+            body: Box::new(body),
+        })
+    }
+
+    fn parse_function_body(&mut self) -> Result<Expression, ParseError> {
+        self.expect_peek(Token::LBRACE)?;
+        // 関数本体は外側のループとは別の文脈になる
+        let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        let body = self.parse_block_expression();
+        self.loop_depth = loop_depth;
+        body
+    }
+
+    fn parse_parameters(&mut self) -> Result<Vec<Parameter>, ParseError> {
+        self.expect_peek(Token::LPAREN)?;
+        self.parse_parameter_list(Token::RPAREN)
+    }
+
+    /// 現在のトークンの直後から `close` までの仮引数を読み込む
+    fn parse_parameter_list(&mut self, close: Token) -> Result<Vec<Parameter>, ParseError> {
+        let mut parameters: Vec<Parameter> = vec![];
+        while !self.peek_token_is(&close) {
+            if !parameters.is_empty() {
+                self.expect_peek(Token::COMMA)?;
+            }
+            let parameter = self.parse_parameter(&close)?;
+            check_parameter(&parameters, &parameter)?;
+            parameters.push(parameter);
+        }
+        self.next_token();
+        Ok(parameters)
+    }
+
+    /// `name`, `name = default`, `...name` のいずれかを読み込む（名前の後ろに `: type` を付けられる）
+    fn parse_parameter(&mut self, close: &Token) -> Result<Parameter, ParseError> {
+        let first = self.cur_index + 1;
+        let rest = self.peek_token_is(&Token::ELLIPSIS);
+        if rest {
+            self.next_token();
+        }
+        let name = self.expect_ident()?;
+        let annotation = self.parse_annotation()?;
+        if rest && self.peek_token_is(&Token::ASSIGN) {
+            return Err(format!("rest parameter {} cannot have a default", name));
+        }
+        let default = self.parse_default(close)?;
+        self.finish_node(SyntaxKind::Param, first);
+        Ok(Parameter {
+            name,
+            annotation,
+            default,
+            rest,
+        })
+    }
+
+    /// 次が `=` なら、その後ろの仮引数の既定値を読み込む
+    fn parse_default(&mut self, close: &Token) -> Result<Option<Expression>, ParseError> {
+        if !self.peek_token_is(&Token::ASSIGN) {
+            return Ok(None);
+        }
+        self.next_token();
+        self.next_token();
+        // `|a = 1| ...` の既定値は閉じの `|` を論理和として取り込まない
+        let precedence = match close {
+            Token::PIPE => Precedence::BIT_OR,
+            _ => Precedence::LOWEST,
+        };
+        self.parse_expression(precedence).map(Some)
+    }
+
+    /// 次が `:` なら、その後ろの型の注釈を読み込む
+    fn parse_annotation(&mut self) -> Result<Option<TypeExpr>, ParseError> {
+        if !self.peek_token_is(&Token::COLON) {
+            return Ok(None);
+        }
+        self.next_token();
+        self.next_token();
+        self.parse_type().map(Some)
+    }
+
+    /// 次が `->` なら、その後ろの戻り値の型を読み込む
+    fn parse_return_type(&mut self) -> Result<Option<TypeExpr>, ParseError> {
+        if !self.peek_token_is(&Token::ARROW) {
+            return Ok(None);
+        }
+        self.next_token();
+        self.next_token();
+        self.parse_type().map(Some)
+    }
+
+    /// `int`, `[T]`, `{K: V}`, `fn(T, U) -> R`, `(T)` と、その後ろに続く `?` を読み込む
+    fn parse_type(&mut self) -> Result<TypeExpr, ParseError> {
+        let first = self.cur_index;
+        let parenthesized = self.cur_token == Token::LPAREN;
+        let mut ty = match &self.cur_token {
+            Token::IDENT(name) => TypeExpr::Named(name.clone()),
+            Token::LPAREN => {
+                self.next_token();
+                let inner = self.parse_nested_type()?;
+                self.expect_peek(Token::RPAREN)?;
+                inner
+            }
+            Token::LBRACKET => {
+                self.next_token();
+                let element = self.parse_nested_type()?;
+                self.expect_peek(Token::RBRACKET)?;
+                TypeExpr::Array(Box::new(element))
+            }
+            Token::LBRACE => {
+                self.next_token();
+                let key = self.parse_nested_type()?;
+                self.expect_peek(Token::COLON)?;
+                self.next_token();
+                let value = self.parse_nested_type()?;
+                self.expect_peek(Token::RBRACE)?;
+                TypeExpr::Hash {
+                    key: Box::new(key),
+                    value: Box::new(value),
+                }
+            }
+            Token::FUNCTION => {
+                self.expect_peek(Token::LPAREN)?;
+                let mut parameters = vec![];
+                while !self.peek_token_is(&Token::RPAREN) {
+                    if !parameters.is_empty() {
+                        self.expect_peek(Token::COMMA)?;
+                    }
+                    self.next_token();
+                    parameters.push(self.parse_nested_type()?);
+                }
+                self.next_token();
+                self.expect_peek(Token::ARROW)?;
+                self.next_token();
+                TypeExpr::Function {
+                    parameters,
+                    return_type: Box::new(self.parse_nested_type()?),
+                }
+            }
+            _ => return Err(format!("expected a type, got {} instead", self.cur_token)),
+        };
+        let kind = match parenthesized {
+            true => SyntaxKind::ParenType,
+            false => SyntaxKind::of_type(&ty),
+        };
+        self.finish_node(kind, first);
+        while self.peek_token_is(&Token::QUESTION) {
+            self.next_token();
+            ty = TypeExpr::Optional(Box::new(ty));
+            self.finish_node(SyntaxKind::OptionalType, first);
+        }
+        Ok(ty)
+    }
+
+    /// 型の中の型（式と同じく入れ子の深さに数える）
+    fn parse_nested_type(&mut self) -> Result<TypeExpr, ParseError> {
+        self.enter_nesting()?;
+        let ty = self.parse_type();
+        self.depth -= 1;
+        ty
+    }
+
+    fn expect_ident(&mut self) -> Result<String, ParseError> {
+        let name = match &self.peek_token {
+            Token::IDENT(n) => n.to_string(),
+            _ => {
+                return Err(format!(
+                    "expected next token to be IDENT, got {} instead",
+                    self.peek_token
+                ))
+            }
+        };
+        self.next_token();
+        Ok(name)
+    }
+}
+
+/// パターンで同じ名前を2回以上束縛していないか
+pub(crate) fn check_bindings(pattern: &Pattern) -> Result<(), ParseError> {
+    let names = pattern.bindings();
+    for (i, name) in names.iter().enumerate() {
+        if names[..i].contains(name) {
+            return Err(format!("{} is bound more than once in {}", name, pattern));
+        }
+    }
+    Ok(())
+}
+
+/// 仮引数 `parameter` を `parameters` の後ろに並べてよいか
+fn check_parameter(parameters: &[Parameter], parameter: &Parameter) -> Result<(), ParseError> {
+    if parameters.iter().any(|p| p.name == parameter.name) {
+        return Err(format!("duplicate parameter {}", parameter.name));
+    }
+    match parameters.last() {
+        Some(last) if last.rest => Err(format!(
+            "parameter {} follows rest parameter {}",
+            parameter.name, last.name
+        )),
+        Some(last) if last.default.is_some() && parameter.default.is_none() && !parameter.rest => {
+            Err(format!(
+                "non-default parameter {} follows default parameter {}",
+                parameter.name, last.name
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+impl Parser<Lexer> {
+    /// `src` 全体を1つの式として読み込む
+    pub fn parse_expression_only(src: &str) -> Result<Expression, SnippetError> {
+        let mut parser = Parser::new(Lexer::new(src));
+        parser.parse_snippet(|parser| parser.parse_expression(Precedence::LOWEST))
+    }
+
+    /// `src` 全体を1つの文として読み込む（末尾の `;` は省略できる）
+    pub fn parse_statement_only(src: &str) -> Result<Statement, SnippetError> {
+        let mut parser = Parser::new(Lexer::new(src));
+        parser.parse_snippet(|parser| parser.parse_statement())
+    }
+}
+
+#[test]
+fn test_let_statements() {
+    let input = r"
+let x = 5;
+let y = 10;
+let foobar = 838383;
+";
+    let mut lex = Lexer::new(input);
+    let mut parser = Parser::new(&mut lex);
+    let program = parser.parse_program();
+    parser.print_error();
+    println!("{:?}", program);
+    assert_eq!(program.len(), 3);
+    let tests: Vec<(&str, i64)> = vec![("x", 5), ("y", 10), ("foobar", 838383)];
+    for test_pair in tests.iter().zip(program.iter()) {
+        println!("{:?}", test_pair.1);
+        if let Statement::Let { pattern, expr, .. } = test_pair.1 {
+            assert_eq!((test_pair.0).0, pattern.to_string());
+            if let Expression::Int(int) = expr {
+                assert_eq!((test_pair.0).1, *int);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_return_statements() {
+    let input = r"
+return 5;
+return 10;
+return 838383;
+";
+    let mut lex = Lexer::new(input);
+    let mut parser = Parser::new(&mut lex);
+    let program = parser.parse_program();
+    assert_eq!(program.len(), 3);
+    assert_eq!(parser.errors.len(), 0);
+}
+
+#[test]
+fn test_parse_errors() {
+    let input = r"
+let x  5;
+let  = 10;
+let  838383;
+";
+    let mut lex = Lexer::new(input);
+    let mut parser = Parser::new(&mut lex);
+    let program = parser.parse_program();
+    parser.print_error();
+    println!("{:?}", program);
+    assert_eq!(program.len(), 0);
+    assert_eq!(parser.errors.len(), 3);
+    parser.print_error();
+}
+
+#[test]
+fn test_identifier_expression() {
+    let input = r"foobar;";
+    let mut lex = Lexer::new(input);
+    let mut parser = Parser::new(&mut lex);
+    let program = parser.parse_program();
+    parser.print_error();
+    assert_eq!(program.len(), 1);
+    assert_eq!(parser.errors.len(), 0);
+    assert_eq!(
+        program[0],
+        Statement::Expr(Expression::Ident("foobar".to_string()))
+    );
+}
+
+#[test]
+fn test_int_literal_expression() {
+    let input = r"5;";
+    let mut lex = Lexer::new(input);
+    let mut parser = Parser::new(&mut lex);
+    let program = parser.parse_program();
+    parser.print_error();
+    assert_eq!(program.len(), 1);
+    assert_eq!(parser.errors.len(), 0);
+    assert_eq!(program[0], Statement::Expr(Expression::Int(5)));
+}
+
+#[test]
+fn test_prefix_expression() {
+    let input = ["!5;", "-15;", "!true;", "!false;"];
+    let test_expr = [
+        Statement::Expr(Expression::Prefix {
+            operator: Token::BANG,
+            right: Box::new(Expression::Int(5)),
+        }),
+        Statement::Expr(Expression::Prefix {
+            operator: Token::MINUS,
+            right: Box::new(Expression::Int(15)),
+        }),
+        Statement::Expr(Expression::Prefix {
+            operator: Token::BANG,
+            right: Box::new(Expression::Boolean(true)),
+        }),
+        Statement::Expr(Expression::Prefix {
+            operator: Token::BANG,
+            right: Box::new(Expression::Boolean(false)),
+        }),
+    ];
+
+    assert_eq!(input.len(), test_expr.len());
+    for i in 0..input.len() {
+        let mut lex = Lexer::new(input[i]);
+        let mut parser = Parser::new(&mut lex);
+        let program = parser.parse_program();
+        parser.print_error();
+        assert_eq!(program.len(), 1);
+        assert_eq!(parser.errors.len(), 0);
+        assert_eq!(program[0], test_expr[i]);
+    }
+}
+
+#[test]
+fn test_infix_expression() {
+    let input = [
+        "5 + 5;", "5 - 5;", "5 * 5;", "5 / 5;", "5 < 5;", "5 > 5;", "5 == 5;", "5 != 5;",
+    ];
+    let test_expr = [
+        Statement::Expr(Expression::Infix {
+            operator: Token::PLUS,
+            left: Box::new(Expression::Int(5)),
+            right: Box::new(Expression::Int(5)),
+        }),
+        Statement::Expr(Expression::Infix {
+            operator: Token::MINUS,
+            left: Box::new(Expression::Int(5)),
+            right: Box::new(Expression::Int(5)),
+        }),
+        Statement::Expr(Expression::Infix {
+            operator: Token::ASTERISK,
+            left: Box::new(Expression::Int(5)),
+            right: Box::new(Expression::Int(5)),
+        }),
+        Statement::Expr(Expression::Infix {
+            operator: Token::SLASH,
+            left: Box::new(Expression::Int(5)),
+            right: Box::new(Expression::Int(5)),
+        }),
+        Statement::Expr(Expression::Infix {
+            operator: Token::LT,
+            left: Box::new(Expression::Int(5)),
+            right: Box::new(Expression::Int(5)),
+        }),
+        Statement::Expr(Expression::Infix {
+            operator: Token::GT,
+            left: Box::new(Expression::Int(5)),
+            right: Box::new(Expression::Int(5)),
+        }),
+        Statement::Expr(Expression::Infix {
+            operator: Token::EQ,
+            left: Box::new(Expression::Int(5)),
+            right: Box::new(Expression::Int(5)),
+        }),
+        Statement::Expr(Expression::Infix {
+            operator: Token::NOTEQ,
+            left: Box::new(Expression::Int(5)),
+            right: Box::new(Expression::Int(5)),
+        }),
+    ];
+
+    for i in 0..8 {
+        let mut lex = Lexer::new(input[i]);
+        let mut parser = Parser::new(&mut lex);
+        let program = parser.parse_program();
+        parser.print_error();
+        println!("{:?}", program);
+        assert_eq!(program.len(), 1);
+        assert_eq!(parser.errors.len(), 0);
+        assert_eq!(program[0], test_expr[i]);
+    }
+}
+
+#[test]
+fn test_operator_precedence_pasing() {
+    let input = [
+        "-a * b;",
+        "!-a;",
+        "a+b+c;",
+        "a+b-c;",
+        "a*b*c;",
+        "a*b/c",
+        "a+b/c;",
+        "a+b*c+d/e-f;",
+        "3+4;-5*5;",
+        "5>4==3<4;",
+        "5<4!=3>4;",
+        "3+4*5==3*1+4*5;",
+        "true",
+        "false",
+        "!true",
+        "3>5 == false",
+        "3<5 == true",
+        "1+ (2+3)+4",
+        "(5+5)*2",
+        "2/(5+5)",
+        "-(5+5)",
+        "!(true==true)",
+        "a + add(b * c) + d",
+        "add(a, b, 1, 2 * 3, 4 + 5, add(6, 7 * 8))",
+        "add(a + b + c * d / f + g)",
+    ];
+    let test_expr = [
+        "((-a)*b)",
+        "(!(-a))",
+        "((a+b)+c)",
+        "((a+b)-c)",
+        "((a*b)*c)",
+        "((a*b)/c)",
+        "(a+(b/c))",
+        "(((a+(b*c))+(d/e))-f)",
+        "(3+4)((-5)*5)",
+        "((5>4)==(3<4))",
+        "((5<4)!=(3>4))",
+        "((3+(4*5))==((3*1)+(4*5)))",
+        "true",
+        "false",
+        "(!true)",
+        "((3>5)==false)",
+        "((3<5)==true)",
+        "((1+(2+3))+4)",
+        "((5+5)*2)",
+        "(2/(5+5))",
+        "(-(5+5))",
+        "(!(true==true))",
+        "((a+add((b*c)))+d)",
+        "add(a,b,1,(2*3),(4+5),add(6,(7*8)))",
+        "add((((a+b)+((c*d)/f))+g))",
+    ];
+    assert_eq!(input.len(), test_expr.len());
+    for i in 0..input.len() {
+        let mut lex = Lexer::new(input[i]);
+        let mut parser = Parser::new(&mut lex);
+        let program = parser.parse_program();
+        parser.print_error();
+        assert_eq!(parser.errors.len(), 0);
+        let mut result_expr = String::new();
+        for stmt in program {
+            result_expr.push_str(&stmt.to_string());
+        }
+        println!("{2:} :  {0:?} , {1:?}", result_expr, test_expr[i], i);
+        assert_eq!(result_expr, test_expr[i]);
+    }
+}
+
+#[test]
+fn test_if_expression() {
+    let input = ["if (x < y) { x }", "if (x < y) { x } else { y }"];
+    let test_expr = ["If(x<y){x}", "If(x<y){x}else{y}"];
+    assert_eq!(input.len(), test_expr.len());
+    for i in 0..input.len() {
+        let mut lex = Lexer::new(input[i]);
+        let mut parser = Parser::new(&mut lex);
+        let program = parser.parse_program();
+        parser.print_error();
+        assert_eq!(program.len(), 1);
+        assert_eq!(parser.errors.len(), 0);
+        assert_eq!(program[0].to_string(), test_expr[i]);
+    }
+}
+
+#[test]
+fn test_function_expression() {
+    let input = ["fn(x, y){ x + y; }"];
+    let test_expr = ["fn(x,y){(x+y)}"];
+    assert_eq!(input.len(), test_expr.len());
+    for i in 0..input.len() {
+        let mut lex = Lexer::new(input[i]);
+        let mut parser = Parser::new(&mut lex);
+        let program = parser.parse_program();
+        parser.print_error();
+        assert_eq!(program.len(), 1);
+        assert_eq!(parser.errors.len(), 0);
+        assert_eq!(program[0].to_string(), test_expr[i]);
+    }
+}
+
+#[test]
+fn test_macro_literal() {
+    let input = [
+        (
+            "macro(a, b) { quote(unquote(b) - unquote(a)); }",
+            "macro(a,b){quote((unquote(b)-unquote(a)))}",
+        ),
+        (
+            "let m = macro() { while (x) { break } };",
+            "Let m=macro(){Whilex{break}}",
+        ),
+    ];
+    for (src, expected) in input.iter() {
+        let mut lex = Lexer::new(src);
+        let mut parser = Parser::new(&mut lex);
+        let program = parser.parse_program();
+        parser.print_error();
+        assert_eq!(parser.errors.len(), 0);
+        assert_eq!(program[0].to_string(), *expected);
+    }
+    let mut lex = Lexer::new("macro(a, ...b) { a }");
+    let mut parser = Parser::new(&mut lex);
+    parser.parse_program();
+    assert_eq!(
+        parser.errors,
+        vec!["macro parameter b cannot be a rest parameter".to_string()]
+    );
+}
+
+#[test]
+fn test_loop_expression() {
+    let input = [
+        "while (x < y) { x }",
+        "for (x in xs) { x }",
+        "while (x < y) { if (x == y) { break; } continue; }",
+        "for (x in xs) { while (x > 0) { break } continue }",
+    ];
+    let test_expr = [
+        "While(x<y){x}",
+        "For(x in xs){x}",
+        "While(x<y){If(x==y){break}continue}",
+        "For(x in xs){While(x>0){break}continue}",
+    ];
+    assert_eq!(input.len(), test_expr.len());
+    for i in 0..input.len() {
+        let mut lex = Lexer::new(input[i]);
+        let mut parser = Parser::new(&mut lex);
+        let program = parser.parse_program();
+        parser.print_error();
+        assert_eq!(program.len(), 1);
+        assert_eq!(parser.errors.len(), 0);
+        assert_eq!(program[0].to_string(), test_expr[i]);
+    }
+}
+
+#[test]
+fn test_loop_control_outside_loop() {
+    let input = [
+        "break;",
+        "continue;",
+        "if (x) { break; }",
+        "while (x) { fn() { continue; } }",
+    ];
+    for src in input.iter() {
+        let mut lex = Lexer::new(src);
+        let mut parser = Parser::new(&mut lex);
+        parser.parse_program();
+        parser.print_error();
+        assert_eq!(parser.errors.len(), 1, "{}", src);
+        assert!(parser.errors[0].ends_with("outside of loop"));
+    }
+}
+
+#[test]
+fn test_match_expression() {
+    let input = [
+        r#"match (value) { 0 => "zero", [a, b] => a + b, {"k": v} => v, _ => "other" }"#,
+        "match (x) { n if n < 0 => -1, -1 => { 0 }, }",
+        "match (x) {}",
+        "match (x) { n if match (n) { m if m => m } > (n) => n }",
+        "match (x) { n if f(fn() { (a) => a }) => n }",
+    ];
+    let test_expr = [
+        r#"Matchvalue{0=>"zero",[a,b]=>(a+b),{"k":v}=>v,_=>"other"}"#,
+        "Matchx{n if (n<0)=>(-1),-1=>0}",
+        "Matchx{}",
+        "Matchx{n if (Matchn{m if m=>m}>n)=>n}",
+        "Matchx{n if f(fn(){fn(a){a}})=>n}",
+    ];
+    assert_eq!(input.len(), test_expr.len());
+    for i in 0..input.len() {
+        let mut lex = Lexer::new(input[i]);
+        let mut parser = Parser::new(&mut lex);
+        let program = parser.parse_program();
+        parser.print_error();
+        assert_eq!(program.len(), 1);
+        assert_eq!(parser.errors.len(), 0);
+        assert_eq!(program[0].to_string(), test_expr[i]);
+    }
+}
+
+#[test]
+fn test_fn_declaration() {
+    let input = [
+        "fn fib(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }",
+        "fn answer() { 42 }; answer()",
+        "fn(x) { x }(1)",
+    ];
+    let test_expr = [
+        "fn fib(n){If(n<2){n}else{(fib((n-1))+fib((n-2)))}}",
+        "fn answer(){42}answer()",
+        "fn(x){x}(1)",
+    ];
+    assert_eq!(input.len(), test_expr.len());
+    for i in 0..input.len() {
+        let mut lex = Lexer::new(input[i]);
+        let mut parser = Parser::new(&mut lex);
+        let program = parser.parse_program();
+        parser.print_error();
+        assert_eq!(parser.errors.len(), 0);
+        let mut result = String::new();
+        for stmt in program {
+            result.push_str(&stmt.to_string());
+        }
+        assert_eq!(result, test_expr[i]);
+    }
+}
+
+#[test]
+fn test_function_parameters() {
+    let input = [
+        "fn(a, b = 10, ...rest) { a }",
+        "fn(a = 1, ...rest) { rest }",
+        "f(1, b: 3)",
+        "f(a: 1, b: 2 + 3)",
+    ];
+    let test_expr = [
+        "fn(a,b=10,...rest){a}",
+        "fn(a=1,...rest){rest}",
+        "f(1,b:3)",
+        "f(a:1,b:(2+3))",
+    ];
+    assert_eq!(input.len(), test_expr.len());
+    for i in 0..input.len() {
+        let mut lex = Lexer::new(input[i]);
+        let mut parser = Parser::new(&mut lex);
+        let program = parser.parse_program();
+        parser.print_error();
+        assert_eq!(program.len(), 1);
+        assert_eq!(parser.errors.len(), 0);
+        assert_eq!(program[0].to_string(), test_expr[i]);
+    }
+}
+
+#[test]
+fn test_function_parameter_errors() {
+    let input = [
+        ("fn(a, a) { a }", "duplicate parameter a"),
+        (
+            "fn(a, ...b, ...c) { a }",
+            "parameter c follows rest parameter b",
+        ),
+        ("fn(...b, c) { b }", "parameter c follows rest parameter b"),
+        (
+            "fn(a = 1, b) { a }",
+            "non-default parameter b follows default parameter a",
+        ),
+        (
+            "fn(...r = 1) { r }",
+            "rest parameter r cannot have a default",
+        ),
+        (
+            "f(a: 1, 2)",
+            "positional argument follows keyword argument a",
+        ),
+        ("f(a: 1, a: 2)", "duplicate keyword argument a"),
+    ];
+    for (src, error) in input.iter() {
+        let mut lex = Lexer::new(src);
+        let mut parser = Parser::new(&mut lex);
+        parser.parse_program();
+        assert_eq!(parser.errors, vec![error.to_string()], "{}", src);
+    }
+}
+
+#[test]
+fn test_arrow_function() {
+    let input = [
+        "|x| x * 2",
+        "(x, y) => x + y",
+        "() => 1",
+        "(x) => x",
+        "|| 1",
+        "|a, b = 2, ...c| a",
+        "(a, b = 2, ...c) => a",
+        "map(xs, |x| x + 1)",
+        "(x)",
+        "match (x) { n if (n) => n, _ => 0 }",
+        "match (x) { n if f((y) => y) => n, _ => 0 }",
+    ];
+    let test_expr = [
+        "fn(x){(x*2)}",
+        "fn(x,y){(x+y)}",
+        "fn(){1}",
+        "fn(x){x}",
+        "fn(){1}",
+        "fn(a,b=2,...c){a}",
+        "fn(a,b=2,...c){a}",
+        "map(xs,fn(x){(x+1)})",
+        "x",
+        "Matchx{n if n=>n,_=>0}",
+        "Matchx{n if f(fn(y){y})=>n,_=>0}",
+    ];
+    assert_eq!(input.len(), test_expr.len());
+    for i in 0..input.len() {
+        let mut lex = Lexer::new(input[i]);
+        let mut parser = Parser::new(&mut lex);
+        let program = parser.parse_program();
+        parser.print_error();
+        assert_eq!(program.len(), 1);
+        assert_eq!(parser.errors.len(), 0);
+        assert_eq!(program[0].to_string(), test_expr[i]);
+    }
+}
+
+#[test]
+fn test_arrow_function_desugar() {
+    let mut lex = Lexer::new("|x| x");
+    let mut parser = Parser::new(&mut lex);
+    let program = parser.parse_program();
+    assert_eq!(
+        program[0],
+        Statement::Expr(Expression::Function {
+            parameters: vec![Parameter::new("x")],
+            return_type: None,
+            body: Box::new(Expression::Block(vec![Statement::Expr(Expression::Ident(
+                "x".to_string()
+            ))])),
+        })
+    );
+}
+
+#[test]
+fn test_arrow_function_errors() {
+    let input = [
+        ("(x + 1) => x", "invalid arrow function parameter (x+1)"),
+        ("(a, b)", "expected => after parenthesized list"),
+        ("|x, x| x", "duplicate parameter x"),
+        ("(a, a) => a", "duplicate parameter a"),
+        (
+            "(a = 1, b) => a",
+            "non-default parameter b follows default parameter a",
+        ),
+        ("(...r, a) => r", "parameter a follows rest parameter r"),
+        ("(...r = 1) => r", "rest parameter r cannot have a default"),
+        ("(a += 1) => a", "invalid arrow function parameter (a+=1)"),
+        ("(...r)", "expected => after arrow function parameters"),
+    ];
+    for (src, error) in input.iter() {
+        let mut lex = Lexer::new(src);
+        let mut parser = Parser::new(&mut lex);
+        parser.parse_program();
+        assert_eq!(parser.errors, vec![error.to_string()], "{}", src);
+    }
+}
+
+#[test]
+fn test_pipe_expression() {
+    let input = [
+        "xs |> filter(even) |> map(double)",
+        "a + b |> f",
+        "x |> |y| y * 2",
+        "f(x |> g)",
+    ];
+    let test_expr = [
+        "((xs|>filter(even))|>map(double))",
+        "((a+b)|>f)",
+        "(x|>fn(y){(y*2)})",
+        "f((x|>g))",
+    ];
+    assert_eq!(input.len(), test_expr.len());
+    for i in 0..input.len() {
+        let mut lex = Lexer::new(input[i]);
+        let mut parser = Parser::new(&mut lex);
+        let program = parser.parse_program();
+        parser.print_error();
+        assert_eq!(program.len(), 1);
+        assert_eq!(parser.errors.len(), 0);
+        let printed = program[0].to_string();
+        assert_eq!(printed, test_expr[i]);
+
+        let mut lex = Lexer::new(&printed);
+        let mut parser = Parser::new(&mut lex);
+        let reparsed = parser.parse_program();
+        assert_eq!(parser.errors.len(), 0);
+        assert_eq!(reparsed[0].to_string(), printed);
+    }
+}
+
+#[test]
+fn test_pipe_desugar() {
+    let input = [("xs |> map(double)", "map(xs,double)"), ("x |> f", "f(x)")];
+    for (src, expected) in input.iter() {
+        let mut lex = Lexer::new(src);
+        let mut parser = Parser::new(&mut lex);
+        let program = parser.parse_program();
+        match &program[0] {
+            Statement::Expr(expr) => {
+                assert_eq!(expr.desugar_pipe().unwrap().to_string(), *expected)
+            }
+            stmt => panic!("unexpected statement {}", stmt),
+        }
+    }
+}
+
+#[test]
+fn test_else_if_expression() {
+    let input = "if (a < b) { x } else if (b < c) { y } else { z }";
+    let mut lex = Lexer::new(input);
+    let mut parser = Parser::new(&mut lex);
+    let program = parser.parse_program();
+    parser.print_error();
+    assert_eq!(parser.errors.len(), 0);
+    assert_eq!(program[0].to_string(), "If(a<b){x}elseIf(b<c){y}else{z}");
+    match &program[0] {
+        Statement::Expr(Expression::If {
+            alternative: Some(alt),
+            ..
+        }) => assert!(matches!(**alt, Expression::If { .. })),
+        stmt => panic!("unexpected statement {}", stmt),
+    }
+}
+
+#[test]
+fn test_ternary_expression() {
+    let input = [
+        "a ? b : c",
+        "a ? b : c ? d : e",
+        "a ? b ? c : d : e",
+        "a == b ? 1 + 2 : 3 * 4",
+        "x |> f ? g : h",
+        "f(a ? b : c, d)",
+    ];
+    let test_expr = [
+        "(a?b:c)",
+        "(a?b:(c?d:e))",
+        "(a?(b?c:d):e)",
+        "((a==b)?(1+2):(3*4))",
+        "(x|>(f?g:h))",
+        "f((a?b:c),d)",
+    ];
+    assert_eq!(input.len(), test_expr.len());
+    for i in 0..input.len() {
+        let mut lex = Lexer::new(input[i]);
+        let mut parser = Parser::new(&mut lex);
+        let program = parser.parse_program();
+        parser.print_error();
+        assert_eq!(program.len(), 1);
+        assert_eq!(parser.errors.len(), 0);
+        assert_eq!(program[0].to_string(), test_expr[i]);
+    }
+}
+
+#[test]
+fn test_bitwise_and_power_precedence() {
+    let input = [
+        "2 ** 3 ** 2",
+        "-2 ** 2",
+        "2 ** -1",
+        "a * b ** c",
+        "a | b ^ c & d",
+        "a << 1 + 2",
+        "a & b == c",
+        "~a & b",
+        "a >> b >> c",
+        "|a = 1| a | b",
+    ];
+    let test_expr = [
+        "(2**(3**2))",
+        "(-(2**2))",
+        "(2**(-1))",
+        "(a*(b**c))",
+        "(a|(b^(c&d)))",
+        "(a<<(1+2))",
+        "((a&b)==c)",
+        "((~a)&b)",
+        "((a>>b)>>c)",
+        "fn(a=1){(a|b)}",
+    ];
+    assert_eq!(input.len(), test_expr.len());
+    for i in 0..input.len() {
+        let mut lex = Lexer::new(input[i]);
+        let mut parser = Parser::new(&mut lex);
+        let program = parser.parse_program();
+        parser.print_error();
+        assert_eq!(program.len(), 1);
+        assert_eq!(parser.errors.len(), 0);
+        assert_eq!(program[0].to_string(), test_expr[i]);
+    }
+}
+
+#[test]
+fn test_user_defined_operators() {
+    let input = [
+        "infixl 105 <+>; a <+> b * c <+> d",
+        "infixr 85 ++>; a ++> b ++> c + d",
+        "infix 35 <=>; a <=> b == c",
+    ];
+    let test_expr = [
+        "infixl 105 <+>((a<+>b)*(c<+>d))",
+        "infixr 85 ++>(a++>(b++>(c+d)))",
+        "infix 35 <=>((a<=>b)==c)",
+    ];
+    assert_eq!(input.len(), test_expr.len());
+    for i in 0..input.len() {
+        let mut lex = Lexer::new(input[i]);
+        let mut parser = Parser::new(&mut lex);
+        let program = parser.parse_program();
+        parser.print_error();
+        assert_eq!(parser.errors.len(), 0);
+        let mut result = String::new();
+        for stmt in program {
+            result.push_str(&stmt.to_string());
+        }
+        assert_eq!(result, test_expr[i]);
+    }
+}
+
+#[test]
+fn test_embedder_defined_operators() {
+    let mut lex = Lexer::new("~~a ** b; a! + b != !c; a @@ b @@ c");
+    let mut parser = Parser::new(&mut lex);
+    parser.define_operator("~~", Fixity::Prefix, Precedence(125));
+    parser.define_operator("!", Fixity::Postfix, Precedence::CALL);
+    parser.define_operator("@@", Fixity::Infix(Associativity::None), Precedence::SUM);
+    let program = parser.parse_program();
+    let result: Vec<String> = program.iter().map(|stmt| stmt.to_string()).collect();
+    assert_eq!(result, vec!["((~~a)**b)", "(((a!)+b)!=(!c))"]);
+    assert_eq!(
+        parser.errors,
+        vec!["non-associative operator @@ cannot be chained with @@".to_string()]
+    );
+}
+
+#[test]
+fn test_operator_declaration_errors() {
+    let input = [
+        ("infixl 65 +;", "cannot declare fixity of built-in token +"),
+        (
+            "infixl 65 +//; a +// b\nc;",
+            "cannot declare fixity of +//, which contains the comment marker //",
+        ),
+        (
+            "infixl 300 <+>;",
+            "expected precedence between 0 and 255, got INT(300) instead",
+        ),
+    ];
+    for (src, error) in input.iter() {
+        let mut lex = Lexer::new(src);
+        let mut parser = Parser::new(&mut lex);
+        parser.parse_program();
+        assert_eq!(parser.errors, vec![error.to_string()], "{}", src);
+    }
+}
+
+#[test]
+fn test_parse_token_vector() {
+    use crate::token_source::TokenIter;
+    let tokens = vec![
+        Token::LET,
+        Token::IDENT("x".to_string()),
+        Token::ASSIGN,
+        Token::INT(1),
+        Token::PLUS,
+        Token::INT(2),
+        Token::SEMICOLON,
+        Token::IDENT("f".to_string()),
+        Token::LPAREN,
+        Token::IDENT("x".to_string()),
+        Token::RPAREN,
+    ];
+    let mut parser = Parser::new(TokenIter::from(&tokens[..]));
+    let program = parser.parse_program();
+    assert_eq!(parser.errors.len(), 0);
+    let result: Vec<String> = program.iter().map(|stmt| stmt.to_string()).collect();
+    assert_eq!(result, vec!["Let x=(1+2)", "f(x)"]);
+
+    let mut parser = Parser::new(TokenIter::from(tokens));
+    assert_eq!(parser.parse_program(), program);
+}
+
+#[test]
+fn test_parse_expression_only() {
+    assert_eq!(
+        Parser::parse_expression_only("1 + 2 * x").map(|expr| expr.to_string()),
+        Ok("(1+(2*x))".to_string())
+    );
+    assert_eq!(
+        Parser::parse_expression_only("1 + 2; 3"),
+        Err(SnippetError::TrailingTokens(Token::SEMICOLON))
+    );
+    assert_eq!(
+        Parser::parse_expression_only("f(x) y"),
+        Err(SnippetError::TrailingTokens(Token::IDENT("y".to_string())))
+    );
+    assert_eq!(
+        Parser::parse_expression_only("  "),
+        Err(SnippetError::Empty)
+    );
+    assert_eq!(
+        Parser::parse_expression_only("1 +"),
+        Err(SnippetError::Syntax(vec![
+            "Parse Expression:Unknown token EOF".to_string()
+        ]))
+    );
+}
+
+#[test]
+fn test_parse_statement_only() {
+    assert_eq!(
+        Parser::parse_statement_only("let x = 5;").map(|stmt| stmt.to_string()),
+        Ok("Let x=5".to_string())
+    );
+    assert_eq!(
+        Parser::parse_statement_only("return x").map(|stmt| stmt.to_string()),
+        Ok("return x".to_string())
+    );
+    for (src, expected) in [("let x = 5", "Let x=5"), ("const y = 1", "Const y=1")].iter() {
+        assert_eq!(
+            Parser::parse_statement_only(src).map(|stmt| stmt.to_string()),
+            Ok(expected.to_string())
+        );
+    }
+    assert!(matches!(
+        Parser::parse_statement_only("let x = 5 x"),
+        Err(SnippetError::Syntax(_))
+    ));
+    assert_eq!(
+        Parser::parse_statement_only("x; y;"),
+        Err(SnippetError::TrailingTokens(Token::IDENT("y".to_string())))
+    );
+    assert!(matches!(
+        Parser::parse_statement_only("fn f() { let = 1; }"),
+        Err(SnippetError::Syntax(_))
+    ));
+}
+
+/// 深さの上限まで読めるだけのスタックを用意したスレッドで読む
+#[cfg(test)]
+fn parse_errors(input: &str, max_depth: usize) -> Vec<ParseError> {
+    let input = input.to_string();
+    std::thread::Builder::new()
+        .stack_size((max_depth + 1) * STACK_PER_LEVEL)
+        .spawn(move || {
+            let mut parser = Parser::new(Lexer::new(&input));
+            parser.set_max_depth(max_depth);
+            parser.parse_program();
+            parser.errors().to_vec()
+        })
+        .unwrap()
+        .join()
+        .unwrap()
+}
+
+#[test]
+fn test_nesting_limit() {
+    let too_deep = vec!["expression nested too deeply (limit 256)".to_string()];
+    let n = 10000;
+    let inputs = [
+        format!("{}1{}", "(".repeat(n), ")".repeat(n)),
+        format!("let x = {}1{};", "(".repeat(n), ")".repeat(n)),
+        format!("{}1{}", "if (x) { ".repeat(n), " }".repeat(n)),
+        format!(
+            "if (x) {{ 1 }}{} else {{ 2 }}",
+            " else if (x) { 1 }".repeat(n)
+        ),
+        format!("{}1{}", "fn() { ".repeat(n), " }".repeat(n)),
+        format!("match (x) {{ {}1{} => 1 }}", "[".repeat(n), "]".repeat(n)),
+    ];
+    for input in inputs.iter() {
+        assert_eq!(parse_errors(input, DEFAULT_MAX_DEPTH), too_deep);
+    }
+
+    assert!(parse_errors("((1))", 3).is_empty());
+    assert_eq!(
+        parse_errors("(((1)))", 3),
+        vec!["expression nested too deeply (limit 3)".to_string()]
+    );
+}
+
+#[test]
+fn test_long_operator_chains() {
+    let chains = |n: usize| {
+        vec![
+            format!("{}x", "!".repeat(n)),
+            format!("{}x", "- ".repeat(n)),
+            format!("a{}", " ** a".repeat(n)),
+            format!("a{}", " + a".repeat(n)),
+            format!("f{}", "(a)".repeat(n)),
+        ]
+    };
+    // 連鎖は入れ子の深さには数えない
+    for input in chains(300).iter() {
+        assert!(parse_errors(input, DEFAULT_MAX_DEPTH).is_empty());
+    }
+    let too_long = vec!["operator chain too long (limit 1024)".to_string()];
+    for input in chains(20000).iter() {
+        assert_eq!(parse_errors(input, DEFAULT_MAX_DEPTH), too_long);
+    }
+    // 括弧の中の連鎖は、外側の連鎖の演算子と合わせて数える
+    let nested = |operands: usize| {
+        let outer = "a + ".repeat(operands);
+        format!("{}a{}", format!("{}(", outer).repeat(200), ")".repeat(200))
+    };
+    assert!(parse_errors(&nested(5), DEFAULT_MAX_DEPTH).is_empty());
+    assert_eq!(parse_errors(&nested(6), DEFAULT_MAX_DEPTH), too_long);
+}
+
+#[test]
+fn test_doc_comments() {
+    let input = "/// Adds one.
+/// Returns an int.
+fn inc(x) { x + 1 }
+// plain comment
+let a = 1;
+/// The answer.
+let b = fn() {
+    /// Inner.
+    let c = 2;
+    /// stray
+    c
+};
+/// trailing";
+    let mut parser = Parser::new(Lexer::new(input));
+    let program = parser.parse_program();
+    assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+    let docs: Vec<Option<&str>> = program
+        .iter()
+        .map(|stmt| match stmt {
+            Statement::Let { doc, .. } | Statement::FnDecl { doc, .. } => doc.as_deref(),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        docs,
+        vec![
+            Some("Adds one.\nReturns an int."),
+            None,
+            Some("The answer.")
+        ]
+    );
+    match &program[2] {
+        Statement::Let {
+            expr: Expression::Function { body, .. },
+            ..
+        } => assert!(matches!(
+            &**body,
+            Expression::Block(statements)
+                if matches!(&statements[0], Statement::Let { doc: Some(doc), .. } if doc == "Inner.")
+        )),
+        stmt => panic!("{:?}", stmt),
+    }
+    assert_eq!(
+        parser.lints(),
+        vec![
+            "doc comment before c at 161..162 is not attached to a let, const or fn declaration",
+            "doc comment before EOF at 178..178 is not attached to a let, const or fn declaration",
+        ]
+    );
+
+    // 空行や普通のコメントより前の `///` は付けず、警告にする
+    let mut parser = Parser::new(Lexer::new("/// a\n\n// note\n/// b\nlet x = 1;"));
+    let program = parser.parse_program();
+    assert!(matches!(&program[0], Statement::Let { doc: Some(doc), .. } if doc == "b"));
+    assert_eq!(
+        parser.lints(),
+        vec!["doc comment before LET at 21..24 is separated from it by a blank line or comment"]
+    );
+}
+
+#[test]
+fn test_destructuring_let() {
+    let tests = [
+        ("let [a, b, ...rest] = xs;", "Let [a,b,...rest]=xs"),
+        ("let [...all] = xs;", "Let [...all]=xs"),
+        ("let [] = xs;", "Let []=xs"),
+        (
+            "let {\"name\": n, \"age\": a} = person;",
+            "Let {\"name\":n,\"age\":a}=person",
+        ),
+        ("let [_, [x, 1], {1: y}] = v;", "Let [_,[x,1],{1:y}]=v"),
+        ("let _ = f();", "Let _=f()"),
+    ];
+    for (input, expected) in tests.iter() {
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse_program();
+        assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+        assert_eq!(program[0].to_string(), *expected);
+    }
+    assert_eq!(
+        parse_errors("match (x) { [a, ...r] => a, _ => 0 };", DEFAULT_MAX_DEPTH),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn test_destructuring_let_errors() {
+    let tests = [
+        (
+            "let 5 = x;",
+            "expected next token to be IDENT, got INT(5) instead",
+        ),
+        (
+            "let [a, ...r, b] = x;",
+            "rest element ...r must be the last element of an array pattern",
+        ),
+        (
+            "let [a, ...1] = x;",
+            "expected next token to be IDENT, got INT(1) instead",
+        ),
+        ("let [a, b, a] = x;", "a is bound more than once in [a,b,a]"),
+        (
+            "let {\"a\": x, \"b\": [y, ...x]} = v;",
+            "x is bound more than once in {\"a\":x,\"b\":[y,...x]}",
+        ),
+        (
+            "match (v) { [n, n] => n };",
+            "n is bound more than once in [n,n]",
+        ),
+    ];
+    for (input, expected) in tests.iter() {
+        assert_eq!(
+            parse_errors(input, DEFAULT_MAX_DEPTH).first(),
+            Some(&expected.to_string()),
+            "{}",
+            input
+        );
+    }
+}
+
+#[test]
+fn test_type_annotations() {
+    let tests = [
+        ("let x: int = 5;", "Let x:int=5"),
+        ("let [a, b]: [string?] = xs;", "Let [a,b]:[string?]=xs"),
+        (
+            "let f = fn(a: int, b: [string]) -> bool { a };",
+            "Let f=fn(a:int,b:[string])->bool{a}",
+        ),
+        (
+            "fn g(h: fn(int, string) -> {string: int}, k: int? = 1, ...r: [int]) -> int?? { 0 }",
+            "fn g(h:fn(int,string)->{string:int},k:int?=1,...r:[int])->int??{0}",
+        ),
+        ("let h: fn() -> int? = f;", "Let h:fn()->int?=f"),
+        ("let h: (fn() -> int)? = f;", "Let h:(fn()->int)?=f"),
+        ("let h: ((int)) = f;", "Let h:int=f"),
+        ("const LIMIT: int = 10;", "Const LIMIT:int=10"),
+        ("|a: int?, b: int| a;", "fn(a:int?,b:int){a}"),
+        ("(a: int, b) => a;", "fn(a:int,b){a}"),
+        ("(f: fn(int) -> int?) => f;", "fn(f:fn(int)->int?){f}"),
+    ];
+    for (input, expected) in tests.iter() {
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse_program();
+        assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+        assert_eq!(program[0].to_string(), *expected);
+    }
+    let errors = [
+        ("let x: = 5;", "expected a type, got = instead"),
+        (
+            "let x: [int = 5;",
+            "expected next token to be ], got = instead",
+        ),
+        (
+            "let x: {string} = h;",
+            "expected next token to be :, got } instead",
+        ),
+        (
+            "let f: fn(int) = g;",
+            "expected next token to be ->, got = instead",
+        ),
+        ("fn f() -> ; { 1 }", "expected a type, got ; instead"),
+        (
+            "fn f() -> int 1",
+            "expected next token to be {, got INT(1) instead",
+        ),
+        ("(a: int);", "expected => after arrow function parameters"),
+        (
+            "(a + 1: int) => a;",
+            "expected next token to be ,, got : instead",
+        ),
+    ];
+    for (input, expected) in errors.iter() {
+        assert_eq!(
+            parse_errors(input, DEFAULT_MAX_DEPTH).first(),
+            Some(&expected.to_string()),
+            "{}",
+            input
+        );
+    }
+    assert_eq!(
+        parse_errors(
+            &format!("let x: {}int{} = 1;", "[".repeat(200), "]".repeat(200)),
+            64
+        ),
+        vec!["expression nested too deeply (limit 64)".to_string()]
+    );
+}
+
+#[test]
+fn test_modules() {
+    let tests = [
+        (
+            r#"import "./util.monkey" as util;"#,
+            r#"import "./util.monkey" as util"#,
+        ),
+        ("export let x = 1;", "export Let x=1"),
+        ("export const n = 2;", "export Const n=2"),
+        ("export fn f(a) { a }", "export fn f(a){a}"),
+        ("util.f(1).g;", "util.f(1).g"),
+        ("-m.x ** 2;", "(-(m.x**2))"),
+    ];
+    for (input, expected) in tests.iter() {
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse_program();
+        assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+        assert_eq!(program[0].to_string(), *expected);
+    }
+    let mut parser = Parser::new(Lexer::new("/// Shared.\nexport const n = 1;"));
+    assert!(matches!(
+        &parser.parse_program()[0],
+        Statement::Export(stmt) if matches!(&**stmt, Statement::Const { doc: Some(doc), .. } if doc == "Shared.")
+    ));
+    let errors = [
+        (
+            "import util;",
+            "expected next token to be a module path string, got util instead",
+        ),
+        (
+            r#"import "./u.monkey";"#,
+            "expected next token to be as, got ; instead",
+        ),
+        (
+            "export x = 1;",
+            "expected let, const or fn after export, got x instead",
+        ),
+        ("export fn() { 1 };", "cannot export fn(){1}"),
+        (
+            r#"fn f() { import "./u.monkey" as u; }"#,
+            "import must be at the top level of a module",
+        ),
+        (
+            "if (x) { export let y = 1; }",
+            "export must be at the top level of a module",
+        ),
+        (
+            "m.1;",
+            "expected next token to be IDENT, got INT(1) instead",
+        ),
+    ];
+    for (input, expected) in errors.iter() {
+        assert_eq!(
+            parse_errors(input, DEFAULT_MAX_DEPTH).first(),
+            Some(&expected.to_string()),
+            "{}",
+            input
+        );
+    }
+}
+
+#[test]
+fn test_const_and_assignment() {
+    let tests = [
+        ("const limit = 10;", "Const limit=10"),
+        ("x = 1;", "(x=1)"),
+        ("x = y = z;", "(x=(y=z))"),
+        ("x += y * 2;", "(x+=(y*2))"),
+        ("x -= y |> f;", "(x-=(y|>f))"),
+        ("x *= a ? b : c;", "(x*=(a?b:c))"),
+        ("x /= 2 + 1 == 3;", "(x/=((2+1)==3))"),
+        ("f(x = 1);", "f((x=1))"),
+    ];
+    for (input, expected) in tests.iter() {
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse_program();
+        assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+        assert_eq!(program[0].to_string(), *expected);
+    }
+    let errors = [
+        ("a + b = 1;", "cannot assign to (a+b)"),
+        ("f(x) += 1;", "cannot assign to f(x)"),
+        (
+            "const [a] = x;",
+            "expected next token to be IDENT, got [ instead",
+        ),
+        ("const a;", "expected next token to be =, got ; instead"),
+    ];
+    for (input, expected) in errors.iter() {
+        assert_eq!(
+            parse_errors(input, DEFAULT_MAX_DEPTH).first(),
+            Some(&expected.to_string()),
+            "{}",
+            input
+        );
+    }
+    let mut parser = Parser::new(Lexer::new("/// Upper bound.\nconst max = 9;"));
+    assert!(matches!(
+        &parser.parse_program()[0],
+        Statement::Const { doc: Some(doc), .. } if doc == "Upper bound."
+    ));
+}
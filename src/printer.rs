@@ -0,0 +1,1175 @@
+//! 構文木を再び読み込めるMonkeyのソースとして書き出す
+//!
+//! `Display` の出力はテスト向けの表記なので、こちらは入力として正しい形を出す。
+//! 字下げ幅と行幅は [`PrintOptions`] で指定し、行に収まらない部分はWadler式の文書を使って折り返す。
+use crate::ast::{Argument, Expression, MatchArm, Parameter, Pattern, Statement, TypeExpr};
+use crate::operator::{Associativity, Fixity, InfixOperator, OperatorTable, Precedence};
+use crate::token::Token;
+
+/// 折り返し可能な文書
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Doc {
+    Text(String),
+    /// 収まれば空白1つ、収まらなければ改行
+    Line,
+    /// 収まれば何も出さず、収まらなければ改行
+    SoftLine,
+    /// 常に改行（囲んでいるグループも折り返す）
+    HardLine,
+    Concat(Vec<Doc>),
+    /// 内側の改行の字下げを増やす
+    Nest(usize, Box<Doc>),
+    /// 全体が1行に収まるなら改行しない
+    Group(Box<Doc>),
+}
+
+impl Doc {
+    pub fn text(text: impl Into<String>) -> Doc {
+        Doc::Text(text.into())
+    }
+
+    pub fn concat(docs: Vec<Doc>) -> Doc {
+        Doc::Concat(docs)
+    }
+
+    pub fn nest(self, indent: usize) -> Doc {
+        Doc::Nest(indent, Box::new(self))
+    }
+
+    pub fn group(self) -> Doc {
+        Doc::Group(Box::new(self))
+    }
+
+    /// `docs` を `separator` で区切って並べる
+    pub fn join(docs: Vec<Doc>, separator: Doc) -> Doc {
+        let mut joined = vec![];
+        for (i, doc) in docs.into_iter().enumerate() {
+            if i > 0 {
+                joined.push(separator.clone());
+            }
+            joined.push(doc);
+        }
+        Doc::Concat(joined)
+    }
+
+    /// 行幅 `width` に収まるように文字列にする
+    pub fn render(&self, width: usize) -> String {
+        let mut out = String::new();
+        let mut column = 0;
+        let mut stack: Vec<(usize, bool, &Doc)> = vec![(0, false, self)];
+        while let Some((indent, flat, doc)) = stack.pop() {
+            match doc {
+                Doc::Text(text) => {
+                    out.push_str(text);
+                    column += text.chars().count();
+                }
+                Doc::Line if flat => {
+                    out.push(' ');
+                    column += 1;
+                }
+                Doc::SoftLine if flat => {}
+                Doc::Line | Doc::SoftLine | Doc::HardLine => {
+                    out.push('\n');
+                    out.push_str(&" ".repeat(indent));
+                    column = indent;
+                }
+                Doc::Concat(docs) => {
+                    for doc in docs.iter().rev() {
+                        stack.push((indent, flat, doc));
+                    }
+                }
+                Doc::Nest(extra, doc) => stack.push((indent + extra, flat, doc)),
+                Doc::Group(doc) => {
+                    let flat = flat || fits(width.saturating_sub(column), doc, &stack);
+                    stack.push((indent, flat, doc));
+                }
+            }
+        }
+        out
+    }
+}
+
+/// `doc` を1行で出したとき、次の改行までが `remaining` 文字に収まるか
+fn fits(remaining: usize, doc: &Doc, rest: &[(usize, bool, &Doc)]) -> bool {
+    let mut remaining = remaining as isize;
+    let mut stack: Vec<(bool, &Doc)> = vec![(true, doc)];
+    let mut rest = rest.iter().rev();
+    loop {
+        let (flat, doc) = match stack.pop() {
+            Some(item) => item,
+            None => match rest.next() {
+                Some((_, flat, doc)) => (*flat, *doc),
+                None => return true,
+            },
+        };
+        match doc {
+            Doc::Text(text) => remaining -= text.chars().count() as isize,
+            Doc::Line if flat => remaining -= 1,
+            Doc::SoftLine if flat => {}
+            Doc::HardLine if flat => return false,
+            Doc::Line | Doc::SoftLine | Doc::HardLine => return true,
+            Doc::Concat(docs) => {
+                for doc in docs.iter().rev() {
+                    stack.push((flat, doc));
+                }
+            }
+            Doc::Nest(_, doc) | Doc::Group(doc) => stack.push((flat, doc)),
+        }
+        if remaining < 0 {
+            return false;
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct PrintOptions {
+    /// 1段の字下げの幅
+    pub indent: usize,
+    /// 1行の幅
+    pub width: usize,
+}
+
+impl Default for PrintOptions {
+    fn default() -> PrintOptions {
+        PrintOptions {
+            indent: 4,
+            width: 80,
+        }
+    }
+}
+
+/// 演算子を含まない、または括弧などで閉じた式
+const ATOM: Precedence = Precedence(u8::MAX);
+
+/// 書き出した式と、周りの演算子との結合を判断するための優先順位
+struct Printed {
+    doc: Doc,
+    /// 式の最上位の演算子が左辺を取り込む強さ
+    left: Precedence,
+    /// 式の末尾が読み込まれた優先順位（これより強い演算子が後に続くと取り込まれる）
+    right: Precedence,
+}
+
+impl Printed {
+    fn atom(doc: Doc) -> Printed {
+        Printed {
+            doc,
+            left: ATOM,
+            right: ATOM,
+        }
+    }
+
+    fn parenthesized(self) -> Doc {
+        Doc::concat(vec![Doc::text("("), self.doc, Doc::text(")")])
+    }
+}
+
+/// 構文木をソースに書き出す
+///
+/// 中置演算子の宣言を書き出すと、以降の式ではその演算子の優先順位に従って括弧を付ける。
+pub struct Printer {
+    options: PrintOptions,
+    operators: OperatorTable,
+}
+
+impl Printer {
+    pub fn new(options: PrintOptions) -> Printer {
+        Printer {
+            options,
+            operators: OperatorTable::new(),
+        }
+    }
+
+    /// 組み込み以外の演算子を含む表を使う
+    pub fn with_operators(options: PrintOptions, operators: OperatorTable) -> Printer {
+        Printer { options, operators }
+    }
+
+    pub fn print_program(&mut self, program: &[Statement]) -> String {
+        let mut out = String::new();
+        for stmt in program {
+            let doc = self.statement(stmt);
+            out.push_str(&doc.render(self.options.width));
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn print_statement(&mut self, stmt: &Statement) -> String {
+        self.statement(stmt).render(self.options.width)
+    }
+
+    pub fn print_expression(&mut self, expr: &Expression) -> String {
+        self.expression(expr).doc.render(self.options.width)
+    }
+
+    fn statement(&mut self, stmt: &Statement) -> Doc {
+        match stmt {
+            Statement::Let {
+                pattern,
+                annotation,
+                expr,
+                doc,
+            } => Doc::concat(vec![
+                doc_comment(doc),
+                Doc::text(format!(
+                    "let {}{} = ",
+                    self::pattern(pattern),
+                    self::annotation(annotation)
+                )),
+                self.expression(expr).doc,
+                Doc::text(";"),
+            ]),
+            Statement::Const {
+                name,
+                annotation,
+                expr,
+                doc,
+            } => Doc::concat(vec![
+                doc_comment(doc),
+                Doc::text(format!("const {}{} = ", name, self::annotation(annotation))),
+                self.expression(expr).doc,
+                Doc::text(";"),
+            ]),
+            Statement::Return(expr) => Doc::concat(vec![
+                Doc::text("return "),
+                self.expression(expr).doc,
+                Doc::text(";"),
+            ]),
+            Statement::Expr(expr) => Doc::concat(vec![self.expression(expr).doc, Doc::text(";")]),
+            Statement::FnDecl {
+                name,
+                parameters,
+                return_type,
+                body,
+                doc,
+            } => Doc::concat(vec![
+                doc_comment(doc),
+                Doc::text(format!("fn {}", name)),
+                self.parameters(parameters),
+                Doc::text(format!("{} ", returns(return_type))),
+                self.body(body),
+            ]),
+            Statement::Break => Doc::text("break;"),
+            Statement::Continue => Doc::text("continue;"),
+            Statement::OperatorDecl {
+                associativity,
+                precedence,
+                operator,
+            } => {
+                self.operators.define(
+                    Token::OPERATOR(operator.clone()),
+                    Fixity::Infix(*associativity),
+                    Precedence(*precedence),
+                );
+                let keyword = match associativity {
+                    Associativity::Left => Token::INFIXL,
+                    Associativity::Right => Token::INFIXR,
+                    Associativity::None => Token::INFIX,
+                };
+                Doc::text(format!("{} {} {};", keyword, precedence, operator))
+            }
+            Statement::Import { path, alias } => {
+                Doc::text(format!("import {} as {};", quote(path), alias))
+            }
+            Statement::Export(stmt) => {
+                // `///` コメントは `export` の前に書く
+                let mut stmt = (**stmt).clone();
+                let doc = stmt.doc_mut().and_then(Option::take);
+                Doc::concat(vec![
+                    doc_comment(&doc),
+                    Doc::text("export "),
+                    self.statement(&stmt),
+                ])
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: &Expression) -> Printed {
+        match expr {
+            Expression::Int(value) => Printed::atom(Doc::text(value.to_string())),
+            Expression::Ident(name) => Printed::atom(Doc::text(name.clone())),
+            Expression::Boolean(value) => Printed::atom(Doc::text(value.to_string())),
+            Expression::Str(value) => Printed::atom(Doc::text(quote(value))),
+            Expression::Grouped(inner) => Printed::atom(self.expression(inner).parenthesized()),
+            Expression::Prefix { operator, right } => {
+                let precedence = self
+                    .operators
+                    .prefix(operator)
+                    .unwrap_or(Precedence::PREFIX);
+                let (right, right_end) = self.right_operand(right, precedence);
+                Printed {
+                    doc: Doc::concat(vec![Doc::text(operator.to_string()), right]),
+                    left: ATOM,
+                    right: right_end,
+                }
+            }
+            Expression::Infix {
+                operator,
+                left,
+                right,
+            }
+            | Expression::Assign {
+                operator,
+                target: left,
+                value: right,
+            } => {
+                let infix = self.infix(operator);
+                let left = self.left_operand(left, infix);
+                let (right, right_end) = self.right_operand(right, infix.right_operand());
+                Printed {
+                    doc: Doc::concat(vec![
+                        left,
+                        Doc::text(format!(" {}", operator)),
+                        Doc::concat(vec![Doc::Line, right]).nest(self.options.indent),
+                    ])
+                    .group(),
+                    left: infix.precedence,
+                    right: right_end,
+                }
+            }
+            Expression::Postfix { operator, left } => {
+                let precedence = self.operators.postfix(operator).unwrap_or(Precedence::CALL);
+                let left = self.left_operand(left, self.left_assoc(precedence));
+                Printed {
+                    doc: Doc::concat(vec![left, Doc::text(operator.to_string())]),
+                    left: precedence,
+                    right: ATOM,
+                }
+            }
+            Expression::Member { object, name } => {
+                let infix = self.infix(&Token::DOT);
+                let object = self.left_operand(object, infix);
+                Printed {
+                    doc: Doc::concat(vec![object, Doc::text(format!(".{}", name))]),
+                    left: infix.precedence,
+                    right: ATOM,
+                }
+            }
+            Expression::Pipe { left, right } => {
+                let infix = self.infix(&Token::PIPELINE);
+                let left = self.left_operand(left, infix);
+                let (right, right_end) = self.right_operand(right, Precedence::PIPE);
+                Printed {
+                    doc: Doc::concat(vec![
+                        left,
+                        Doc::concat(vec![Doc::Line, Doc::text("|> "), right])
+                            .nest(self.options.indent),
+                    ])
+                    .group(),
+                    left: infix.precedence,
+                    right: right_end,
+                }
+            }
+            Expression::Ternary {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                let infix = self.infix(&Token::QUESTION);
+                let condition = self.left_operand(condition, infix);
+                let consequence = self.expression(consequence).doc;
+                let (alternative, right_end) =
+                    self.right_operand(alternative, infix.right_operand());
+                Printed {
+                    doc: Doc::concat(vec![
+                        condition,
+                        Doc::concat(vec![
+                            Doc::Line,
+                            Doc::text("? "),
+                            consequence,
+                            Doc::Line,
+                            Doc::text(": "),
+                            alternative,
+                        ])
+                        .nest(self.options.indent),
+                    ])
+                    .group(),
+                    left: infix.precedence,
+                    right: right_end,
+                }
+            }
+            Expression::Call {
+                function,
+                arguments,
+            } => {
+                let infix = self.infix(&Token::LPAREN);
+                let function = self.left_operand(function, infix);
+                let arguments = arguments.iter().map(|arg| self.argument(arg)).collect();
+                Printed {
+                    doc: Doc::concat(vec![function, self.delimited("(", arguments, ")")]),
+                    left: infix.precedence,
+                    right: ATOM,
+                }
+            }
+            Expression::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                let mut docs = vec![
+                    Doc::text("if ("),
+                    self.expression(condition).doc,
+                    Doc::text(") "),
+                    self.body(consequence),
+                ];
+                match alternative.as_deref() {
+                    Some(alt @ Expression::If { .. }) => {
+                        docs.push(Doc::text(" else "));
+                        docs.push(self.expression(alt).doc);
+                    }
+                    Some(alt) => {
+                        docs.push(Doc::text(" else "));
+                        docs.push(self.body(alt));
+                    }
+                    None => {}
+                }
+                Printed::atom(Doc::concat(docs))
+            }
+            Expression::Block(_) => Printed::atom(self.body(expr)),
+            Expression::Function {
+                parameters,
+                return_type,
+                body,
+            } => Printed::atom(Doc::concat(vec![
+                Doc::text("fn"),
+                self.parameters(parameters),
+                Doc::text(format!("{} ", returns(return_type))),
+                self.body(body),
+            ])),
+            Expression::MacroLiteral { parameters, body } => Printed::atom(Doc::concat(vec![
+                Doc::text("macro"),
+                self.parameters(parameters),
+                Doc::text(" "),
+                self.body(body),
+            ])),
+            Expression::While { condition, body } => Printed::atom(Doc::concat(vec![
+                Doc::text("while ("),
+                self.expression(condition).doc,
+                Doc::text(") "),
+                self.body(body),
+            ])),
+            Expression::For {
+                variable,
+                iterable,
+                body,
+            } => Printed::atom(Doc::concat(vec![
+                Doc::text(format!("for ({} in ", variable)),
+                self.expression(iterable).doc,
+                Doc::text(") "),
+                self.body(body),
+            ])),
+            Expression::Match { subject, arms } => {
+                let arms = arms.iter().map(|arm| self.match_arm(arm)).collect();
+                Printed::atom(Doc::concat(vec![
+                    Doc::text("match ("),
+                    self.expression(subject).doc,
+                    Doc::text(") "),
+                    self.braced(Doc::join(
+                        arms,
+                        Doc::concat(vec![Doc::text(","), Doc::Line]),
+                    )),
+                ]))
+            }
+        }
+    }
+
+    fn infix(&self, operator: &Token) -> InfixOperator {
+        self.operators
+            .infix(operator)
+            .unwrap_or_else(|| self.left_assoc(Precedence::LOWEST))
+    }
+
+    fn left_assoc(&self, precedence: Precedence) -> InfixOperator {
+        InfixOperator {
+            precedence,
+            associativity: Associativity::Left,
+        }
+    }
+
+    /// 演算子の左側の式（末尾の被演算子が演算子を取り込んでしまうなら括弧で囲む）
+    fn left_operand(&mut self, expr: &Expression, operator: InfixOperator) -> Doc {
+        let non_associative = match expr {
+            Expression::Infix {
+                operator: inner, ..
+            } => self.operators.infix(inner).is_some_and(|inner| {
+                inner.associativity == Associativity::None
+                    && inner.precedence == operator.precedence
+            }),
+            _ => false,
+        };
+        let printed = self.expression(expr);
+        if printed.right < operator.precedence || non_associative {
+            printed.parenthesized()
+        } else {
+            printed.doc
+        }
+    }
+
+    /// 優先順位 `precedence` で読み込まれる右側の式と、書き出した全体の末尾の優先順位
+    fn right_operand(&mut self, expr: &Expression, precedence: Precedence) -> (Doc, Precedence) {
+        let printed = self.expression(expr);
+        if printed.left <= precedence {
+            (printed.parenthesized(), precedence)
+        } else {
+            (printed.doc, printed.right.min(precedence))
+        }
+    }
+
+    fn argument(&mut self, arg: &Argument) -> Doc {
+        match arg {
+            Argument::Positional(value) => self.expression(value).doc,
+            Argument::Keyword(name, value) => Doc::concat(vec![
+                Doc::text(format!("{}: ", name)),
+                self.expression(value).doc,
+            ]),
+        }
+    }
+
+    fn parameters(&mut self, parameters: &[Parameter]) -> Doc {
+        let parameters = parameters
+            .iter()
+            .map(|param| {
+                let name = match param.rest {
+                    true => format!("...{}", param.name),
+                    false => param.name.clone(),
+                } + &annotation(&param.annotation);
+                match &param.default {
+                    Some(default) => Doc::concat(vec![
+                        Doc::text(format!("{} = ", name)),
+                        self.expression(default).doc,
+                    ]),
+                    None => Doc::text(name),
+                }
+            })
+            .collect();
+        self.delimited("(", parameters, ")")
+    }
+
+    /// 括弧で囲んだカンマ区切りの並び（収まらなければ1行に1つずつ）
+    fn delimited(&self, open: &str, items: Vec<Doc>, close: &str) -> Doc {
+        if items.is_empty() {
+            return Doc::text(format!("{}{}", open, close));
+        }
+        Doc::concat(vec![
+            Doc::text(open),
+            Doc::concat(vec![
+                Doc::SoftLine,
+                Doc::join(items, Doc::concat(vec![Doc::text(","), Doc::Line])),
+            ])
+            .nest(self.options.indent),
+            Doc::SoftLine,
+            Doc::text(close),
+        ])
+        .group()
+    }
+
+    /// `{ ... }` で囲む（中身が空なら `{}`）
+    fn braced(&self, inner: Doc) -> Doc {
+        if inner == Doc::Concat(vec![]) {
+            return Doc::text("{}");
+        }
+        Doc::concat(vec![
+            Doc::text("{"),
+            Doc::concat(vec![Doc::Line, inner]).nest(self.options.indent),
+            Doc::Line,
+            Doc::text("}"),
+        ])
+        .group()
+    }
+
+    /// 関数・制御構文の本体（ブロックでなければ1文のブロックとして書く）
+    fn body(&mut self, body: &Expression) -> Doc {
+        let statements = match body {
+            Expression::Block(statements) => {
+                statements.iter().map(|stmt| self.statement(stmt)).collect()
+            }
+            expr => vec![Doc::concat(vec![self.expression(expr).doc, Doc::text(";")])],
+        };
+        // 2文以上のブロックは常に1行に1文ずつ書く
+        self.braced(Doc::join(statements, Doc::HardLine))
+    }
+
+    fn match_arm(&mut self, arm: &MatchArm) -> Doc {
+        let mut docs = vec![Doc::text(pattern(&arm.pattern))];
+        if let Some(guard) = &arm.guard {
+            docs.push(Doc::text(" if "));
+            docs.push(self.expression(guard).doc);
+        }
+        docs.push(Doc::text(" => "));
+        docs.push(match &arm.body {
+            Expression::Block(_) => self.body(&arm.body),
+            body => self.expression(body).doc,
+        });
+        Doc::concat(docs)
+    }
+}
+
+/// `///` コメントを1行ずつ書き出す（文の前に置く）
+fn doc_comment(doc: &Option<String>) -> Doc {
+    let lines = doc.iter().flat_map(|doc| doc.split('\n'));
+    Doc::concat(
+        lines
+            .flat_map(|line| match line.is_empty() {
+                true => vec![Doc::text("///"), Doc::HardLine],
+                false => vec![Doc::text(format!("/// {}", line)), Doc::HardLine],
+            })
+            .collect(),
+    )
+}
+
+/// 文字列リテラルとして書き出す
+fn quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for ch in value.chars() {
+        match ch {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            ch => quoted.push(ch),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn pattern(pattern: &Pattern) -> String {
+    match pattern {
+        Pattern::Wildcard => "_".to_string(),
+        Pattern::Ident(name) => name.clone(),
+        Pattern::Int(value) => value.to_string(),
+        Pattern::Boolean(value) => value.to_string(),
+        Pattern::Str(value) => quote(value),
+        Pattern::Array { elements, rest } => {
+            let mut elements: Vec<String> = elements.iter().map(self::pattern).collect();
+            elements.extend(rest.iter().map(|rest| format!("...{}", rest)));
+            format!("[{}]", elements.join(", "))
+        }
+        Pattern::Hash(pairs) => {
+            let pairs: Vec<String> = pairs
+                .iter()
+                .map(|(key, value)| format!("{}: {}", self::pattern(key), self::pattern(value)))
+                .collect();
+            format!("{{{}}}", pairs.join(", "))
+        }
+    }
+}
+
+fn type_expr(ty: &TypeExpr) -> String {
+    match ty {
+        TypeExpr::Named(name) => name.clone(),
+        TypeExpr::Array(element) => format!("[{}]", type_expr(element)),
+        TypeExpr::Hash { key, value } => format!("{{{}: {}}}", type_expr(key), type_expr(value)),
+        TypeExpr::Function {
+            parameters,
+            return_type,
+        } => {
+            let parameters: Vec<String> = parameters.iter().map(type_expr).collect();
+            format!(
+                "fn({}) -> {}",
+                parameters.join(", "),
+                type_expr(return_type)
+            )
+        }
+        // 括弧がないと `?` は関数の戻り値の型に付く
+        TypeExpr::Optional(inner) => match **inner {
+            TypeExpr::Function { .. } => format!("({})?", type_expr(inner)),
+            _ => format!("{}?", type_expr(inner)),
+        },
+    }
+}
+
+/// `: type`（注釈がなければ空）
+fn annotation(annotation: &Option<TypeExpr>) -> String {
+    match annotation {
+        Some(ty) => format!(": {}", type_expr(ty)),
+        None => String::new(),
+    }
+}
+
+/// ` -> type`（戻り値の型がなければ空）
+fn returns(return_type: &Option<TypeExpr>) -> String {
+    match return_type {
+        Some(ty) => format!(" -> {}", type_expr(ty)),
+        None => String::new(),
+    }
+}
+
+/// 既定の設定でプログラムを書き出す
+pub fn print_program(program: &[Statement]) -> String {
+    Printer::new(PrintOptions::default()).print_program(program)
+}
+
+#[cfg(test)]
+fn parse_source(input: &str) -> Vec<Statement> {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    let mut parser = Parser::new(Lexer::new(input));
+    let program = parser.parse_program();
+    assert_eq!(parser.errors(), &[] as &[String], "{}", input);
+    program
+}
+
+#[test]
+fn test_print_program() {
+    let input = r#"let add = fn(a, b = 1, ...rest) { a + b };
+fn f(x) { if (x < 0) { return -x; } else if (x == 0) { "zero\n" } else { x } }
+while (true) { break; }
+match (x) { [a, _] if a > 0 => a, {"k": -1} => 0, _ => { 1 } };
+(x, y) => x * (y + 1);
+c ? a : b ? d : e;
+xs |> f(b: 2);
+(a ** b) ** c;
+-(a ** b);"#;
+    let expected = r#"let add = fn(a, b = 1, ...rest) { a + b; };
+fn f(x) {
+    if (x < 0) { return -x; } else if (x == 0) { "zero\n"; } else { x; };
+}
+while (true) { break; };
+match (x) { [a, _] if a > 0 => a, {"k": -1} => 0, _ => { 1; } };
+fn(x, y) { x * (y + 1); };
+c ? a : b ? d : e;
+xs |> f(b: 2);
+(a ** b) ** c;
+-(a ** b);
+"#;
+    assert_eq!(print_program(&parse_source(input)), expected);
+}
+
+#[test]
+fn test_print_type_annotations() {
+    let input = "let  x:int?=5; let [a,b] : [{string:int}] = xs;
+fn f(a:int,b:[string]=xs,...c:[(fn(int)->bool)?])->fn()->int? { |d:int?| d }";
+    let expected = "let x: int? = 5;
+let [a, b]: [{string: int}] = xs;
+fn f(a: int, b: [string] = xs, ...c: [(fn(int) -> bool)?]) -> fn() -> int? {
+    fn(d: int?) { d; };
+}
+";
+    assert_eq!(print_program(&parse_source(input)), expected);
+}
+
+#[test]
+fn test_print_line_width() {
+    let program =
+        parse_source("let result = compute(first_argument, second_argument, third_argument + 1);");
+    let options = PrintOptions {
+        indent: 2,
+        width: 40,
+    };
+    assert_eq!(
+        Printer::new(options).print_program(&program),
+        "let result = compute(\n  first_argument,\n  second_argument,\n  third_argument + 1\n);\n"
+    );
+    let program = parse_source("fn f() { let a = 1; a }");
+    assert_eq!(
+        Printer::new(options).print_program(&program),
+        "fn f() {\n  let a = 1;\n  a;\n}\n"
+    );
+}
+
+#[test]
+fn test_print_adds_parentheses() {
+    let ident = |name: &str| Box::new(Expression::Ident(name.to_string()));
+    let infix = |operator: Token, left: Box<Expression>, right: Box<Expression>| {
+        Box::new(Expression::Infix {
+            operator,
+            left,
+            right,
+        })
+    };
+    let expr = infix(
+        Token::ASTERISK,
+        infix(Token::PLUS, ident("a"), ident("b")),
+        infix(Token::MINUS, ident("c"), ident("d")),
+    );
+    let mut printer = Printer::new(PrintOptions::default());
+    assert_eq!(printer.print_expression(&expr), "(a + b) * (c - d)");
+    let expr = infix(
+        Token::POWER,
+        Box::new(Expression::Prefix {
+            operator: Token::MINUS,
+            right: ident("a"),
+        }),
+        infix(Token::POWER, ident("b"), ident("c")),
+    );
+    assert_eq!(printer.print_expression(&expr), "(-a) ** b ** c");
+}
+
+#[test]
+fn test_print_user_operators() {
+    let input = "infixl 105 <+>; a <+> (b * c); infix 30 <=>; (a <=> b) == c;";
+    let printed = print_program(&parse_source(input));
+    assert_eq!(
+        printed,
+        "infixl 105 <+>;\na <+> (b * c);\ninfix 30 <=>;\n(a <=> b) == c;\n"
+    );
+    assert_eq!(parse_source(&printed), parse_source(input));
+}
+
+/// 試験用の構文木を乱数（xorshift）で作る（構文解析器が受け付ける形に限る）
+#[cfg(test)]
+pub(crate) struct Generator {
+    pub(crate) state: u64,
+}
+
+#[cfg(test)]
+impl Generator {
+    const NAMES: [&'static str; 6] = ["a", "b", "c", "xs", "count", "value"];
+    const TYPES: [&'static str; 4] = ["int", "string", "bool", "Point"];
+    const STRINGS: [&'static str; 4] = ["", "hello", "say \"hi\"", "tab\tand\\slash\n"];
+    const DOCS: [&'static str; 3] = ["Adds one.", "", "Line one.\n\n  Indented /// line."];
+    const OPERATORS: [Token; 14] = [
+        Token::PLUS,
+        Token::MINUS,
+        Token::ASTERISK,
+        Token::SLASH,
+        Token::EQ,
+        Token::NOTEQ,
+        Token::LT,
+        Token::GT,
+        Token::PIPE,
+        Token::CARET,
+        Token::AMPERSAND,
+        Token::LSHIFT,
+        Token::RSHIFT,
+        Token::POWER,
+    ];
+
+    fn below(&mut self, n: usize) -> usize {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state % n as u64) as usize
+    }
+
+    fn doc(&mut self) -> Option<String> {
+        match self.below(4) {
+            0 => Some(Generator::DOCS[self.below(Generator::DOCS.len())].to_string()),
+            _ => None,
+        }
+    }
+
+    fn name(&mut self) -> String {
+        Generator::NAMES[self.below(Generator::NAMES.len())].to_string()
+    }
+
+    fn boxed(&mut self, depth: usize) -> Box<Expression> {
+        Box::new(self.expression(depth))
+    }
+
+    fn expression(&mut self, depth: usize) -> Expression {
+        if depth == 0 {
+            return match self.below(4) {
+                0 => Expression::Int(self.below(1000) as i64),
+                1 => Expression::Boolean(self.below(2) == 0),
+                2 => Expression::Str(Generator::STRINGS[self.below(4)].to_string()),
+                _ => Expression::Ident(self.name()),
+            };
+        }
+        let depth = depth - 1;
+        match self.below(16) {
+            0 => Expression::Grouped(self.boxed(depth)),
+            1 => Expression::Prefix {
+                operator: [Token::MINUS, Token::BANG, Token::TILDE][self.below(3)].clone(),
+                right: self.boxed(depth),
+            },
+            2..=4 => Expression::Infix {
+                operator: Generator::OPERATORS[self.below(Generator::OPERATORS.len())].clone(),
+                left: self.boxed(depth),
+                right: self.boxed(depth),
+            },
+            5 => Expression::Pipe {
+                left: self.boxed(depth),
+                right: self.boxed(depth),
+            },
+            6 => Expression::Ternary {
+                condition: self.boxed(depth),
+                consequence: self.boxed(depth),
+                alternative: self.boxed(depth),
+            },
+            7 => {
+                let mut arguments = vec![];
+                for _ in 0..self.below(3) {
+                    arguments.push(Argument::Positional(self.expression(depth)));
+                }
+                if self.below(2) == 0 {
+                    arguments.push(Argument::Keyword(self.name(), self.expression(depth)));
+                }
+                Expression::Call {
+                    function: self.boxed(depth),
+                    arguments,
+                }
+            }
+            8 => Expression::If {
+                condition: self.boxed(depth),
+                consequence: Box::new(self.block(depth, false)),
+                alternative: match self.below(3) {
+                    0 => None,
+                    1 => Some(Box::new(self.block(depth, false))),
+                    _ => Some(Box::new(Expression::If {
+                        condition: self.boxed(depth),
+                        consequence: Box::new(self.block(depth, false)),
+                        alternative: None,
+                    })),
+                },
+            },
+            9 => {
+                let parameters = self.parameters(depth);
+                let body = Box::new(self.block(depth, false));
+                match self.below(4) {
+                    0 => Expression::MacroLiteral {
+                        parameters: parameters.into_iter().filter(|p| !p.rest).collect(),
+                        body,
+                    },
+                    _ => Expression::Function {
+                        parameters,
+                        return_type: self.annotation(),
+                        body,
+                    },
+                }
+            }
+            10 => Expression::While {
+                condition: self.boxed(depth),
+                body: Box::new(self.block(depth, true)),
+            },
+            11 => Expression::For {
+                variable: self.name(),
+                iterable: self.boxed(depth),
+                body: Box::new(self.block(depth, true)),
+            },
+            12 => {
+                let mut arms = vec![];
+                for _ in 0..=self.below(3) {
+                    arms.push(MatchArm {
+                        pattern: self.binding_pattern(2, true),
+                        guard: match self.below(2) {
+                            0 => Some(self.expression(depth)),
+                            _ => None,
+                        },
+                        body: match self.below(3) {
+                            0 => self.block(depth, false),
+                            _ => self.expression(depth),
+                        },
+                    });
+                }
+                Expression::Match {
+                    subject: self.boxed(depth),
+                    arms,
+                }
+            }
+            13 => Expression::Assign {
+                operator: [
+                    Token::ASSIGN,
+                    Token::PLUSASSIGN,
+                    Token::MINUSASSIGN,
+                    Token::ASTERISKASSIGN,
+                    Token::SLASHASSIGN,
+                ][self.below(5)]
+                .clone(),
+                target: Box::new(Expression::Ident(self.name())),
+                value: self.boxed(depth),
+            },
+            14 => Expression::Member {
+                object: self.boxed(depth),
+                name: self.name(),
+            },
+            _ => self.expression(0),
+        }
+    }
+
+    fn parameters(&mut self, depth: usize) -> Vec<Parameter> {
+        let mut parameters: Vec<Parameter> = vec![];
+        for name in Generator::NAMES.iter().take(self.below(4)) {
+            let default = match parameters.last() {
+                Some(last) if last.default.is_some() => Some(self.expression(depth)),
+                _ if self.below(3) == 0 => Some(self.expression(depth)),
+                _ => None,
+            };
+            parameters.push(Parameter {
+                name: name.to_string(),
+                annotation: self.annotation(),
+                default,
+                rest: false,
+            });
+        }
+        if self.below(3) == 0 {
+            parameters.push(Parameter {
+                name: "rest".to_string(),
+                annotation: self.annotation(),
+                default: None,
+                rest: true,
+            });
+        }
+        parameters
+    }
+
+    fn type_expr(&mut self, depth: usize) -> TypeExpr {
+        if depth == 0 {
+            return TypeExpr::Named(
+                Generator::TYPES[self.below(Generator::TYPES.len())].to_string(),
+            );
+        }
+        let depth = depth - 1;
+        match self.below(6) {
+            0 => TypeExpr::Array(Box::new(self.type_expr(depth))),
+            1 => TypeExpr::Hash {
+                key: Box::new(self.type_expr(depth)),
+                value: Box::new(self.type_expr(depth)),
+            },
+            2 => TypeExpr::Function {
+                parameters: (0..self.below(3)).map(|_| self.type_expr(depth)).collect(),
+                return_type: Box::new(self.type_expr(depth)),
+            },
+            3 => TypeExpr::Optional(Box::new(self.type_expr(depth))),
+            _ => self.type_expr(0),
+        }
+    }
+
+    /// 半分ほどの確率で型の注釈を付ける
+    fn annotation(&mut self) -> Option<TypeExpr> {
+        match self.below(2) {
+            0 => Some(self.type_expr(3)),
+            _ => None,
+        }
+    }
+
+    fn pattern(&mut self, depth: usize) -> Pattern {
+        match self.below(if depth == 0 { 5 } else { 7 }) {
+            0 => Pattern::Wildcard,
+            1 => Pattern::Ident(self.name()),
+            2 => Pattern::Int(self.below(20) as i64 - 10),
+            3 => Pattern::Boolean(self.below(2) == 0),
+            4 => Pattern::Str(Generator::STRINGS[self.below(4)].to_string()),
+            5 => Pattern::Array {
+                elements: (0..self.below(3))
+                    .map(|_| self.pattern(depth - 1))
+                    .collect(),
+                rest: match self.below(3) {
+                    0 => Some(self.name()),
+                    _ => None,
+                },
+            },
+            _ => Pattern::Hash(
+                (0..self.below(3))
+                    .map(|_| (Pattern::Int(self.below(5) as i64), self.pattern(depth - 1)))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// 同じ名前を2回束縛しないパターン（`top_level_literal` でなければ最上位はリテラル以外）
+    fn binding_pattern(&mut self, depth: usize, top_level_literal: bool) -> Pattern {
+        loop {
+            let pattern = self.pattern(depth);
+            let literal = matches!(
+                pattern,
+                Pattern::Int(_) | Pattern::Boolean(_) | Pattern::Str(_)
+            );
+            if crate::parser::check_bindings(&pattern).is_ok() && (top_level_literal || !literal) {
+                return pattern;
+            }
+        }
+    }
+
+    fn block(&mut self, depth: usize, in_loop: bool) -> Expression {
+        Expression::Block(
+            (0..self.below(3))
+                .map(|_| self.statement(depth, in_loop))
+                .collect(),
+        )
+    }
+
+    /// 最上位の文（ブロックの中には書けない `import` と `export` も作る）
+    pub(crate) fn top_level_statement(&mut self, depth: usize) -> Statement {
+        match self.below(6) {
+            0 => Statement::Import {
+                path: format!("./{}.monkey", self.name()),
+                alias: self.name(),
+            },
+            1 => loop {
+                if let stmt @ (Statement::Let { .. }
+                | Statement::Const { .. }
+                | Statement::FnDecl { .. }) = self.statement(depth, false)
+                {
+                    return Statement::Export(Box::new(stmt));
+                }
+            },
+            _ => self.statement(depth, false),
+        }
+    }
+
+    pub(crate) fn statement(&mut self, depth: usize, in_loop: bool) -> Statement {
+        match self.below(if in_loop { 7 } else { 5 }) {
+            0 => Statement::Let {
+                pattern: match self.below(3) {
+                    0 => self.binding_pattern(2, false),
+                    _ => Pattern::Ident(self.name()),
+                },
+                annotation: self.annotation(),
+                expr: self.expression(depth),
+                doc: self.doc(),
+            },
+            1 => Statement::Return(self.expression(depth)),
+            2 => Statement::Expr(self.expression(depth)),
+            3 => Statement::FnDecl {
+                name: self.name(),
+                parameters: self.parameters(depth.saturating_sub(1)),
+                return_type: self.annotation(),
+                body: self.block(depth.saturating_sub(1), false),
+                doc: self.doc(),
+            },
+            4 => Statement::Const {
+                name: self.name(),
+                annotation: self.annotation(),
+                expr: self.expression(depth),
+                doc: self.doc(),
+            },
+            5 => Statement::Break,
+            _ => Statement::Continue,
+        }
+    }
+}
+
+#[test]
+fn test_print_round_trip() {
+    use crate::ast::{fold_expression, Fold};
+    /// 括弧の有無だけを無視して比べるため `Grouped` を取り除く
+    struct StripGroups;
+    impl Fold for StripGroups {
+        fn fold_expression(&mut self, expr: Expression) -> Expression {
+            match fold_expression(self, expr) {
+                Expression::Grouped(inner) => *inner,
+                expr => expr,
+            }
+        }
+    }
+    let strip = |program: Vec<Statement>| -> Vec<Statement> {
+        program
+            .into_iter()
+            .map(|stmt| StripGroups.fold_statement(stmt))
+            .collect()
+    };
+    let mut generator = Generator {
+        state: 0x2545_f491_4f6c_dd1d,
+    };
+    for width in [80, 20].iter() {
+        let options = PrintOptions {
+            indent: 2,
+            width: *width,
+        };
+        for _ in 0..300 {
+            let program: Vec<Statement> =
+                (0..3).map(|_| generator.top_level_statement(4)).collect();
+            // 括弧を補って書き出したものは、括弧を除けば元の構文木に戻る
+            let printed = Printer::new(options).print_program(&program);
+            let parsed = parse_source(&printed);
+            assert_eq!(strip(parsed.clone()), strip(program), "{}", printed);
+            // 構文解析の結果は括弧も含めてそのまま戻る
+            let reprinted = Printer::new(options).print_program(&parsed);
+            assert_eq!(parse_source(&reprinted), parsed, "{}", reprinted);
+            assert_eq!(reprinted, printed);
+        }
+    }
+}
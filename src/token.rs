@@ -1,65 +1,200 @@
-use std::fmt;
-
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
-pub enum Token {
-    ///トークン文字列が未知
-    ILLEGAL(char),
-    ///ファイル終端
-    EOF,
-    ///識別子
-    IDENT(String),
-    ///整数
-    INT(i64),
-    ///代入
-    ASSIGN,
-    ///四則演算
-    PLUS,
-    MINUS,
-    ASTERISK,
-    SLASH,
-    BANG,
-    LT,
-    GT,
-    EQ,
-    NOTEQ,
-    COMMA,
-    SEMICOLON,
-    LPAREN,
-    RPAREN,
-    LBRACE,
-    RBRACE,
-    FUNCTION,
-    LET,
-    IF,
-    ELSE,
-    RETURN,
-    TRUE,
-    FALSE,
-}
-
-impl fmt::Display for Token {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Token::IDENT(name) => write!(f, "{}", name),
-            Token::INT(val) => write!(f, "INT({})", val),
-            Token::MINUS => write!(f, "-"),
-            Token::PLUS => write!(f, "+"),
-            Token::BANG => write!(f, "!"),
-            Token::ASTERISK => write!(f, "*"),
-            Token::SLASH => write!(f, "/"),
-            Token::GT => write!(f, ">"),
-            Token::LT => write!(f, "<"),
-            Token::EQ => write!(f, "=="),
-            Token::NOTEQ => write!(f, "!="),
-            Token::SEMICOLON => write!(f, ";"),
-            Token::ASSIGN => write!(f, "="),
-            Token::FUNCTION => write!(f, "fn"),
-            Token::LPAREN => write!(f, "("),
-            Token::RPAREN => write!(f, ")"),
-            Token::LBRACE => write!(f, "{{"),
-            Token::RBRACE => write!(f, "}}"),
-            Token::COMMA => write!(f, ","),
-            tok => write!(f, "{:?}", tok),
-        }
-    }
-}
\ No newline at end of file
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum Token {
+    ///トークン文字列が未知
+    ILLEGAL(char),
+    ///ファイル終端
+    EOF,
+    ///識別子
+    IDENT(String),
+    ///整数
+    INT(i64),
+    ///利用者定義の演算子記号
+    OPERATOR(String),
+    ///文字列
+    STRING(String),
+    ///代入
+    ASSIGN,
+    ///複合代入 `+=` `-=` `*=` `/=`
+    PLUSASSIGN,
+    MINUSASSIGN,
+    ASTERISKASSIGN,
+    SLASHASSIGN,
+    ///四則演算
+    PLUS,
+    MINUS,
+    ASTERISK,
+    SLASH,
+    ///べき乗 `**`（右結合）
+    POWER,
+    BANG,
+    ///ビット演算
+    AMPERSAND,
+    CARET,
+    TILDE,
+    LSHIFT,
+    RSHIFT,
+    LT,
+    GT,
+    EQ,
+    NOTEQ,
+    COMMA,
+    SEMICOLON,
+    LPAREN,
+    RPAREN,
+    LBRACE,
+    RBRACE,
+    LBRACKET,
+    RBRACKET,
+    COLON,
+    QUESTION,
+    ///可変長引数 `...`
+    ELLIPSIS,
+    ///名前空間の要素 `util.f`
+    DOT,
+    ///アロー関数の仮引数の区切り `|`
+    PIPE,
+    ///パイプライン演算子 `|>`
+    PIPELINE,
+    ///パターンと本体の区切り `=>`
+    FATARROW,
+    ///戻り値の型の前置き `->`
+    ARROW,
+    ///ワイルドカード `_`
+    UNDERSCORE,
+    FUNCTION,
+    LET,
+    ///再代入できない束縛 `const NAME = expr;`
+    CONST,
+    IF,
+    ELSE,
+    RETURN,
+    TRUE,
+    FALSE,
+    WHILE,
+    FOR,
+    IN,
+    BREAK,
+    CONTINUE,
+    MATCH,
+    ///マクロリテラル `macro(a) { ... }`
+    MACRO,
+    ///モジュール `import "./util.monkey" as util;` `export let f = ...;`
+    IMPORT,
+    EXPORT,
+    AS,
+    ///結合性宣言
+    INFIXL,
+    INFIXR,
+    INFIX,
+}
+
+impl Token {
+    /// `=` と複合代入の演算子
+    pub fn is_assignment(&self) -> bool {
+        matches!(
+            self,
+            Token::ASSIGN
+                | Token::PLUSASSIGN
+                | Token::MINUSASSIGN
+                | Token::ASTERISKASSIGN
+                | Token::SLASHASSIGN
+        )
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::IDENT(name) => write!(f, "{}", name),
+            Token::INT(val) => write!(f, "INT({})", val),
+            Token::STRING(val) => write!(f, "{:?}", val),
+            Token::OPERATOR(symbol) => write!(f, "{}", symbol),
+            Token::MINUS => write!(f, "-"),
+            Token::PLUS => write!(f, "+"),
+            Token::BANG => write!(f, "!"),
+            Token::ASTERISK => write!(f, "*"),
+            Token::SLASH => write!(f, "/"),
+            Token::POWER => write!(f, "**"),
+            Token::AMPERSAND => write!(f, "&"),
+            Token::CARET => write!(f, "^"),
+            Token::TILDE => write!(f, "~"),
+            Token::LSHIFT => write!(f, "<<"),
+            Token::RSHIFT => write!(f, ">>"),
+            Token::GT => write!(f, ">"),
+            Token::LT => write!(f, "<"),
+            Token::EQ => write!(f, "=="),
+            Token::NOTEQ => write!(f, "!="),
+            Token::SEMICOLON => write!(f, ";"),
+            Token::ASSIGN => write!(f, "="),
+            Token::PLUSASSIGN => write!(f, "+="),
+            Token::MINUSASSIGN => write!(f, "-="),
+            Token::ASTERISKASSIGN => write!(f, "*="),
+            Token::SLASHASSIGN => write!(f, "/="),
+            Token::FUNCTION => write!(f, "fn"),
+            Token::LPAREN => write!(f, "("),
+            Token::RPAREN => write!(f, ")"),
+            Token::LBRACE => write!(f, "{{"),
+            Token::RBRACE => write!(f, "}}"),
+            Token::LBRACKET => write!(f, "["),
+            Token::RBRACKET => write!(f, "]"),
+            Token::COLON => write!(f, ":"),
+            Token::QUESTION => write!(f, "?"),
+            Token::ELLIPSIS => write!(f, "..."),
+            Token::DOT => write!(f, "."),
+            Token::PIPE => write!(f, "|"),
+            Token::PIPELINE => write!(f, "|>"),
+            Token::FATARROW => write!(f, "=>"),
+            Token::ARROW => write!(f, "->"),
+            Token::UNDERSCORE => write!(f, "_"),
+            Token::COMMA => write!(f, ","),
+            Token::WHILE => write!(f, "while"),
+            Token::FOR => write!(f, "for"),
+            Token::IN => write!(f, "in"),
+            Token::BREAK => write!(f, "break"),
+            Token::CONTINUE => write!(f, "continue"),
+            Token::MATCH => write!(f, "match"),
+            Token::MACRO => write!(f, "macro"),
+            Token::INFIXL => write!(f, "infixl"),
+            Token::INFIXR => write!(f, "infixr"),
+            Token::INFIX => write!(f, "infix"),
+            Token::IMPORT => write!(f, "import"),
+            Token::EXPORT => write!(f, "export"),
+            Token::AS => write!(f, "as"),
+            tok => write!(f, "{:?}", tok),
+        }
+    }
+}
+
+/// 入力中の範囲（文字単位の位置、終端は含まない）
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    /// 2つの範囲を覆う範囲
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
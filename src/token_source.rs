@@ -0,0 +1,115 @@
+use crate::lexer::Lexer;
+use crate::token::{Span, Token};
+use std::iter::Cloned;
+use std::slice;
+use std::vec;
+
+/// 構文解析器にトークンを供給するもの
+pub trait TokenSource {
+    /// 次のトークン（終端に達した後は `Token::EOF` を返し続ける）
+    fn next_token(&mut self) -> Token;
+
+    /// 利用者定義の演算子記号を以降のトークンに反映する
+    ///
+    /// 字句解析済みのトークン列では何もしない。
+    fn add_operator(&mut self, _symbol: &str) {}
+
+    /// 最後に返したトークンの入力中の範囲
+    ///
+    /// 元の文字列を持たない供給元では空の範囲を返す。
+    fn span(&self) -> Span {
+        Span::default()
+    }
+
+    /// 最後に返したトークンの直前の `///` コメントの本文
+    fn doc_comment(&self) -> Option<String> {
+        None
+    }
+
+    /// 最後に返したトークンの前に、空行や普通のコメントで離れた `///` コメントがあったか
+    fn detached_doc_comment(&self) -> bool {
+        false
+    }
+}
+
+impl TokenSource for Lexer {
+    fn next_token(&mut self) -> Token {
+        Lexer::next_token(self)
+    }
+
+    fn add_operator(&mut self, symbol: &str) {
+        Lexer::add_operator(self, symbol)
+    }
+
+    fn span(&self) -> Span {
+        Lexer::span(self)
+    }
+
+    fn doc_comment(&self) -> Option<String> {
+        Lexer::doc_comment(self).map(str::to_string)
+    }
+
+    fn detached_doc_comment(&self) -> bool {
+        Lexer::detached_doc_comment(self)
+    }
+}
+
+impl<S: TokenSource + ?Sized> TokenSource for &mut S {
+    fn next_token(&mut self) -> Token {
+        (**self).next_token()
+    }
+
+    fn add_operator(&mut self, symbol: &str) {
+        (**self).add_operator(symbol)
+    }
+
+    fn span(&self) -> Span {
+        (**self).span()
+    }
+
+    fn doc_comment(&self) -> Option<String> {
+        (**self).doc_comment()
+    }
+
+    fn detached_doc_comment(&self) -> bool {
+        (**self).detached_doc_comment()
+    }
+}
+
+/// トークンのイテレータをトークン供給元にする
+pub struct TokenIter<I> {
+    tokens: I,
+}
+
+impl<I: Iterator<Item = Token>> TokenIter<I> {
+    pub fn new(tokens: I) -> TokenIter<I> {
+        TokenIter { tokens }
+    }
+}
+
+impl<I: Iterator<Item = Token>> TokenSource for TokenIter<I> {
+    fn next_token(&mut self) -> Token {
+        self.tokens.next().unwrap_or(Token::EOF)
+    }
+}
+
+impl<'a> From<&'a [Token]> for TokenIter<Cloned<slice::Iter<'a, Token>>> {
+    fn from(tokens: &'a [Token]) -> Self {
+        TokenIter::new(tokens.iter().cloned())
+    }
+}
+
+impl From<Vec<Token>> for TokenIter<vec::IntoIter<Token>> {
+    fn from(tokens: Vec<Token>) -> Self {
+        TokenIter::new(tokens.into_iter())
+    }
+}
+
+#[test]
+fn test_token_iter() {
+    let mut source = TokenIter::from(vec![Token::INT(1), Token::SEMICOLON]);
+    assert_eq!(source.next_token(), Token::INT(1));
+    assert_eq!(source.next_token(), Token::SEMICOLON);
+    assert_eq!(source.next_token(), Token::EOF);
+    assert_eq!(source.next_token(), Token::EOF);
+}
@@ -0,0 +1,578 @@
+//! 構文解析を経ずに組み立てた構文木の検査
+//!
+//! 構文解析器が受け付けない形（前置演算子でないトークンの `Prefix`、ループ外の `break` など）を
+//! 見つける。ビルダーやJSONから作った構文木を評価や整形に渡す前に使う。
+use crate::ast::{walk_expression, walk_parameter, walk_statement, walk_type, Visitor};
+use crate::ast::{Argument, Expression, MatchArm, Parameter, Pattern, Statement, TypeExpr};
+use crate::lexer::Lexer;
+use crate::operator::{Fixity, OperatorTable, Precedence};
+use crate::parser::{check_bindings, ParseError};
+use crate::token::Token;
+
+/// プログラムを検査し、見つかった誤りをすべて返す
+pub fn validate_program(program: &[Statement]) -> Vec<ParseError> {
+    let mut validator = Validator {
+        operators: OperatorTable::new(),
+        loop_depth: 0,
+        block_depth: 0,
+        body: false,
+        errors: vec![],
+    };
+    for stmt in program {
+        validator.visit_statement(stmt);
+    }
+    validator.errors
+}
+
+/// 演算子の記号に対応するトークン（組み込みの記号でなければ利用者定義の演算子）
+pub fn operator_token(symbol: &str) -> Token {
+    Lexer::builtin_symbol(symbol).unwrap_or_else(|| Token::OPERATOR(symbol.to_string()))
+}
+
+struct Validator {
+    operators: OperatorTable,
+    loop_depth: usize,
+    /// `import` と `export` はブロックの外にしか書けない
+    block_depth: usize,
+    /// 次に辿る式が関数・ループ・`if`・`match` の本体で、ブロックを書ける位置か
+    body: bool,
+    errors: Vec<ParseError>,
+}
+
+impl Validator {
+    fn check_name(&mut self, name: &str) {
+        if !Lexer::is_identifier(name) {
+            self.errors.push(format!("{:?} is not an identifier", name));
+        }
+    }
+
+    fn check_block(&mut self, kind: &str, body: &Expression) {
+        if !matches!(body, Expression::Block(_)) {
+            self.errors
+                .push(format!("{} body must be a block, got {}", kind, body));
+        }
+    }
+
+    /// 仮引数の並びの規則は `Parser::parse_parameter_list` と同じ
+    fn check_parameters(&mut self, parameters: &[Parameter]) {
+        for (i, parameter) in parameters.iter().enumerate() {
+            if parameters[..i].iter().any(|p| p.name == parameter.name) {
+                self.errors
+                    .push(format!("duplicate parameter {}", parameter.name));
+            }
+            if parameter.rest && parameter.default.is_some() {
+                self.errors.push(format!(
+                    "rest parameter {} cannot have a default",
+                    parameter.name
+                ));
+            }
+            match i.checked_sub(1).map(|j| &parameters[j]) {
+                Some(last) if last.rest => self.errors.push(format!(
+                    "parameter {} follows rest parameter {}",
+                    parameter.name, last.name
+                )),
+                Some(last)
+                    if last.default.is_some() && parameter.default.is_none() && !parameter.rest =>
+                {
+                    self.errors.push(format!(
+                        "non-default parameter {} follows default parameter {}",
+                        parameter.name, last.name
+                    ))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn check_arguments(&mut self, arguments: &[Argument]) {
+        let mut keywords: Vec<&String> = vec![];
+        for argument in arguments {
+            match argument {
+                Argument::Keyword(name, _) => {
+                    if keywords.contains(&name) {
+                        self.errors
+                            .push(format!("duplicate keyword argument {}", name));
+                    }
+                    keywords.push(name);
+                }
+                Argument::Positional(_) => {
+                    if let Some(name) = keywords.last() {
+                        self.errors.push(format!(
+                            "positional argument follows keyword argument {}",
+                            name
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_bindings(&mut self, pattern: &Pattern) {
+        if let Err(error) = check_bindings(pattern) {
+            self.errors.push(error);
+        }
+    }
+
+    fn check_operator(&mut self, operator: &Token, defined: bool, fixity: &str) {
+        if !defined {
+            self.errors
+                .push(format!("{} is not {} operator", operator, fixity));
+        }
+    }
+
+    /// 関数本体は外側のループとは別の文脈になる
+    fn visit_function(
+        &mut self,
+        parameters: &[Parameter],
+        return_type: &Option<TypeExpr>,
+        body: &Expression,
+    ) {
+        self.check_parameters(parameters);
+        self.check_block("function", body);
+        for param in parameters {
+            self.visit_parameter(param);
+        }
+        if let Some(ty) = return_type {
+            self.visit_type(ty);
+        }
+        let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        self.visit_body(body);
+        self.loop_depth = loop_depth;
+    }
+
+    fn visit_loop_body(&mut self, kind: &str, body: &Expression) {
+        self.check_block(kind, body);
+        self.loop_depth += 1;
+        self.visit_body(body);
+        self.loop_depth -= 1;
+    }
+
+    /// ブロックを書ける位置の式を辿る（それ以外の位置のブロックは構文解析器が受け付けない）
+    fn visit_body(&mut self, body: &Expression) {
+        self.body = true;
+        self.visit_expression(body);
+    }
+}
+
+impl Visitor<'_> for Validator {
+    fn visit_statement(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Let { pattern, .. } => {
+                if !matches!(
+                    pattern,
+                    Pattern::Ident(_)
+                        | Pattern::Wildcard
+                        | Pattern::Array { .. }
+                        | Pattern::Hash(_)
+                ) {
+                    self.errors.push(format!(
+                        "let pattern must be an identifier, array or hash pattern, got {}",
+                        pattern
+                    ));
+                }
+                self.check_bindings(pattern);
+            }
+            Statement::Const { name, .. } => self.check_name(name),
+            Statement::Import { .. } | Statement::Export(_) if self.block_depth > 0 => {
+                let keyword = match stmt {
+                    Statement::Import { .. } => Token::IMPORT,
+                    _ => Token::EXPORT,
+                };
+                self.errors
+                    .push(format!("{} must be at the top level of a module", keyword));
+            }
+            Statement::Import { alias, .. } => self.check_name(alias),
+            Statement::Export(exported)
+                if !matches!(
+                    **exported,
+                    Statement::Let { .. } | Statement::Const { .. } | Statement::FnDecl { .. }
+                ) =>
+            {
+                self.errors.push(format!("cannot export {}", exported));
+            }
+            Statement::FnDecl {
+                name,
+                parameters,
+                return_type,
+                body,
+                ..
+            } => {
+                self.check_name(name);
+                return self.visit_function(parameters, return_type, body);
+            }
+            Statement::Break | Statement::Continue if self.loop_depth == 0 => {
+                let keyword = match stmt {
+                    Statement::Break => Token::BREAK,
+                    _ => Token::CONTINUE,
+                };
+                self.errors.push(format!("{} outside of loop", keyword));
+            }
+            Statement::OperatorDecl {
+                associativity,
+                precedence,
+                operator,
+            } => {
+                if Lexer::is_operator_symbol(operator) {
+                    self.operators.define(
+                        Token::OPERATOR(operator.clone()),
+                        Fixity::Infix(*associativity),
+                        Precedence(*precedence),
+                    );
+                } else {
+                    self.errors
+                        .push(format!("cannot declare fixity of {:?}", operator));
+                }
+            }
+            _ => {}
+        }
+        walk_statement(self, stmt);
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        let body = std::mem::replace(&mut self.body, false);
+        match expr {
+            Expression::Ident(name) => self.check_name(name),
+            // 負の数は前置の `-` として読むので、負の整数の字句はない
+            Expression::Int(value) if *value < 0 => self
+                .errors
+                .push(format!("integer literal {} must not be negative", value)),
+            Expression::Prefix { operator, .. } => {
+                let defined = self.operators.prefix(operator).is_some();
+                self.check_operator(operator, defined, "a prefix")
+            }
+            Expression::Infix { operator, .. } => {
+                // 呼び出し・パイプ・三項演算子・代入はそれぞれ専用の式で表す
+                let defined = self.operators.infix(operator).is_some()
+                    && !matches!(
+                        operator,
+                        Token::LPAREN | Token::PIPELINE | Token::QUESTION | Token::DOT
+                    )
+                    && !operator.is_assignment();
+                self.check_operator(operator, defined, "an infix")
+            }
+            Expression::Assign {
+                operator, target, ..
+            } => {
+                self.check_operator(operator, operator.is_assignment(), "an assignment");
+                if !matches!(**target, Expression::Ident(_)) {
+                    self.errors.push(format!("cannot assign to {}", target));
+                }
+            }
+            Expression::Postfix { operator, .. } => {
+                let defined = self.operators.postfix(operator).is_some();
+                self.check_operator(operator, defined, "a postfix")
+            }
+            Expression::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.check_block("if", consequence);
+                self.visit_expression(condition);
+                self.visit_body(consequence);
+                if let Some(alternative) = alternative {
+                    if !matches!(**alternative, Expression::If { .. }) {
+                        self.check_block("else", alternative);
+                    }
+                    self.visit_body(alternative);
+                }
+                return;
+            }
+            Expression::Function {
+                parameters,
+                return_type,
+                body,
+            } => return self.visit_function(parameters, return_type, body),
+            Expression::MacroLiteral { parameters, body } => {
+                if let Some(rest) = parameters.iter().find(|p| p.rest) {
+                    self.errors.push(format!(
+                        "macro parameter {} cannot be a rest parameter",
+                        rest.name
+                    ));
+                }
+                return self.visit_function(parameters, &None, body);
+            }
+            Expression::While { condition, body } => {
+                self.visit_expression(condition);
+                return self.visit_loop_body("while", body);
+            }
+            Expression::For {
+                variable,
+                iterable,
+                body,
+            } => {
+                self.check_name(variable);
+                self.visit_expression(iterable);
+                return self.visit_loop_body("for", body);
+            }
+            Expression::Call { arguments, .. } => self.check_arguments(arguments),
+            Expression::Member { name, .. } => self.check_name(name),
+            Expression::Block(_) => {
+                if !body {
+                    self.errors.push(
+                        "a block can only be the body of a function, loop, if or match".to_string(),
+                    );
+                }
+                self.block_depth += 1;
+                walk_expression(self, expr);
+                self.block_depth -= 1;
+                return;
+            }
+            _ => {}
+        }
+        walk_expression(self, expr);
+    }
+
+    fn visit_argument(&mut self, arg: &Argument) {
+        if let Argument::Keyword(name, _) = arg {
+            self.check_name(name);
+        }
+        self.visit_expression(arg.value());
+    }
+
+    fn visit_parameter(&mut self, param: &Parameter) {
+        self.check_name(&param.name);
+        walk_parameter(self, param);
+    }
+
+    fn visit_match_arm(&mut self, arm: &MatchArm) {
+        self.check_bindings(&arm.pattern);
+        self.visit_pattern(&arm.pattern);
+        if let Some(guard) = &arm.guard {
+            self.visit_expression(guard);
+        }
+        self.visit_body(&arm.body);
+    }
+
+    fn visit_pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Ident(name) => self.check_name(name),
+            Pattern::Array { elements, rest } => {
+                for element in elements {
+                    self.visit_pattern(element);
+                }
+                if let Some(rest) = rest {
+                    self.check_name(rest);
+                }
+            }
+            Pattern::Hash(pairs) => {
+                for (key, value) in pairs {
+                    if !matches!(key, Pattern::Int(_) | Pattern::Boolean(_) | Pattern::Str(_)) {
+                        self.errors
+                            .push(format!("hash pattern key must be a literal, got {:?}", key));
+                    }
+                    self.visit_pattern(value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn visit_type(&mut self, ty: &TypeExpr) {
+        if let TypeExpr::Named(name) = ty {
+            self.check_name(name);
+        }
+        walk_type(self, ty);
+    }
+}
+
+#[cfg(test)]
+fn validate_source(input: &str) -> Vec<ParseError> {
+    let mut parser = crate::parser::Parser::new(Lexer::new(input));
+    let program = parser.parse_program();
+    assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+    validate_program(&program)
+}
+
+#[test]
+fn test_validate_parsed_program() {
+    let input = "infixl 65 <+>; fn f(a, b = 1, ...c) { while (a) { if (b) { break } } }
+        import \"./lib.monkey\" as lib; export const k = lib.k;
+        let g = |x| x <+> -x; g += k; let [h, {\"k\": _}, ...t] = g; match (f(1, b: 2)) { {\"k\": [n, _]} if n => n, _ => { 0 } };
+        for (i in xs) { fn() { 1 }; continue; }
+        let p: {string: [int]}? = fn(a: fn(int) -> bool, ...r: [int]) -> (fn() -> int)? { a };";
+    assert_eq!(validate_source(input), Vec::<ParseError>::new());
+}
+
+#[test]
+fn test_validate_errors() {
+    let ident = |name: &str| Box::new(Expression::Ident(name.to_string()));
+    let tests = vec![
+        (
+            Statement::Expr(Expression::Prefix {
+                operator: Token::PLUS,
+                right: ident("x"),
+            }),
+            "+ is not a prefix operator",
+        ),
+        (
+            Statement::Expr(Expression::Infix {
+                operator: Token::LPAREN,
+                left: ident("f"),
+                right: ident("x"),
+            }),
+            "( is not an infix operator",
+        ),
+        (
+            Statement::Expr(Expression::Infix {
+                operator: operator_token("<+>"),
+                left: ident("a"),
+                right: ident("b"),
+            }),
+            "<+> is not an infix operator",
+        ),
+        (
+            Statement::Let {
+                annotation: None,
+                pattern: Pattern::Ident("if".to_string()),
+                expr: Expression::Int(1),
+                doc: None,
+            },
+            "\"if\" is not an identifier",
+        ),
+        (
+            Statement::Expr(Expression::While {
+                condition: ident("x"),
+                body: ident("x"),
+            }),
+            "while body must be a block, got x",
+        ),
+        (
+            Statement::Expr(Expression::Call {
+                function: ident("f"),
+                arguments: vec![
+                    Argument::Keyword("k".to_string(), Expression::Int(1)),
+                    Argument::Positional(Expression::Int(2)),
+                ],
+            }),
+            "positional argument follows keyword argument k",
+        ),
+        (
+            Statement::Expr(Expression::Match {
+                subject: ident("x"),
+                arms: vec![MatchArm {
+                    pattern: Pattern::Hash(vec![(Pattern::Wildcard, Pattern::Wildcard)]),
+                    guard: None,
+                    body: Expression::Int(1),
+                }],
+            }),
+            "hash pattern key must be a literal, got Wildcard",
+        ),
+        (
+            Statement::Let {
+                annotation: None,
+                pattern: Pattern::Int(1),
+                expr: Expression::Int(1),
+                doc: None,
+            },
+            "let pattern must be an identifier, array or hash pattern, got 1",
+        ),
+        (
+            Statement::Let {
+                annotation: None,
+                pattern: Pattern::Array {
+                    elements: vec![Pattern::Ident("a".to_string())],
+                    rest: Some("a".to_string()),
+                },
+                expr: Expression::Int(1),
+                doc: None,
+            },
+            "a is bound more than once in [a,...a]",
+        ),
+        (
+            Statement::Expr(Expression::Infix {
+                operator: Token::PLUSASSIGN,
+                left: ident("x"),
+                right: ident("y"),
+            }),
+            "+= is not an infix operator",
+        ),
+        (
+            Statement::Expr(Expression::Assign {
+                operator: Token::ASSIGN,
+                target: Box::new(Expression::Int(1)),
+                value: ident("y"),
+            }),
+            "cannot assign to 1",
+        ),
+        (
+            Statement::Const {
+                name: "1x".to_string(),
+                annotation: None,
+                expr: Expression::Int(1),
+                doc: None,
+            },
+            "\"1x\" is not an identifier",
+        ),
+        (
+            Statement::Export(Box::new(Statement::Return(Expression::Int(1)))),
+            "cannot export return 1",
+        ),
+        (
+            Statement::Expr(Expression::Function {
+                parameters: vec![],
+                return_type: None,
+                body: Box::new(Expression::Block(vec![Statement::Import {
+                    path: "./a.monkey".to_string(),
+                    alias: "a".to_string(),
+                }])),
+            }),
+            "import must be at the top level of a module",
+        ),
+        (
+            Statement::Let {
+                pattern: Pattern::Ident("x".to_string()),
+                annotation: Some(TypeExpr::Array(Box::new(TypeExpr::Named("1x".to_string())))),
+                expr: Expression::Int(1),
+                doc: None,
+            },
+            "\"1x\" is not an identifier",
+        ),
+        (
+            Statement::Expr(Expression::Function {
+                parameters: vec![Parameter {
+                    annotation: Some(TypeExpr::Optional(Box::new(TypeExpr::Named(
+                        "fn".to_string(),
+                    )))),
+                    ..Parameter::new("a")
+                }],
+                return_type: Some(TypeExpr::Function {
+                    parameters: vec![],
+                    return_type: Box::new(TypeExpr::Named("int".to_string())),
+                }),
+                body: Box::new(Expression::Block(vec![])),
+            }),
+            "\"fn\" is not an identifier",
+        ),
+        (
+            Statement::OperatorDecl {
+                associativity: crate::operator::Associativity::Left,
+                precedence: 10,
+                operator: "+".to_string(),
+            },
+            "cannot declare fixity of \"+\"",
+        ),
+        (
+            Statement::Expr(Expression::Infix {
+                operator: Token::MINUS,
+                left: ident("a"),
+                right: Box::new(Expression::Int(-5)),
+            }),
+            "integer literal -5 must not be negative",
+        ),
+        (
+            Statement::Expr(Expression::Block(vec![Statement::Expr(*ident("x"))])),
+            "a block can only be the body of a function, loop, if or match",
+        ),
+        (
+            Statement::Return(Expression::Call {
+                function: ident("f"),
+                arguments: vec![Argument::Positional(Expression::Block(vec![]))],
+            }),
+            "a block can only be the body of a function, loop, if or match",
+        ),
+    ];
+    for (stmt, expected) in tests {
+        assert_eq!(validate_program(&[stmt]), vec![expected.to_string()]);
+    }
+}